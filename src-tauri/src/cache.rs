@@ -0,0 +1,287 @@
+//! Persistent cache of finished analyses.
+//!
+//! Results are stored in the app cache directory under a key derived from a
+//! content fingerprint of the video (size, modification time and a hash of
+//! its first, middle and last megabyte) plus the analysis parameters. A file
+//! renamed or moved within a disk keeps its modification time and still
+//! hits; a copy usually gets a new one and misses, as does an edited file.
+//! Each entry is a result file with a small metadata file next to it, so
+//! listing the cache does not have to parse full results. Least recently used
+//! entries are evicted once the cache grows past its size cap.
+
+use crate::checkpoint::fnv1a;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever scores or the result layout change, so old entries miss
+const CACHE_VERSION: u32 = 5;
+
+/// Bytes hashed at each of the three sample points of a file
+const FINGERPRINT_CHUNK: u64 = 1024 * 1024;
+
+/// Metadata of one cached analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    /// Path the video had when it was analyzed
+    pub video_path: String,
+    /// Content fingerprint of the video
+    pub fingerprint: String,
+    /// Analysis parameters, human readable
+    pub params: String,
+    pub frame_count: usize,
+    /// Size of the stored result in bytes
+    pub size_bytes: u64,
+    /// Unix seconds
+    pub created_at: u64,
+    pub last_used: u64,
+}
+
+/// Root directory for the app's caches, with `name` as a subdirectory
+pub fn app_cache_dir(name: &str) -> Result<PathBuf> {
+    let dir = dirs::cache_dir()
+        .context("Failed to get cache directory")?
+        .join("sharp-frame-extractor")
+        .join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Content fingerprint of a file: size, modification time and an FNV-1a hash
+/// of its first, middle and last megabyte. Cheap even for huge recordings.
+pub fn fingerprint(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut sampled = Vec::new();
+    for offset in [0, size / 2, size.saturating_sub(FINGERPRINT_CHUNK)] {
+        file.seek(SeekFrom::Start(offset))?;
+        (&mut file)
+            .take(FINGERPRINT_CHUNK)
+            .read_to_end(&mut sampled)?;
+    }
+
+    Ok(format!("{}-{}-{:016x}", size, modified, fnv1a(&sampled)))
+}
+
+/// Cache key for a video fingerprint and analysis parameters
+pub fn cache_key(fingerprint: &str, params: &str) -> String {
+    format!(
+        "{:016x}",
+        fnv1a(format!("v{}|{}|{}", CACHE_VERSION, fingerprint, params).as_bytes())
+    )
+}
+
+/// Analysis cache rooted at a directory
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    /// The cache in the app cache directory
+    pub fn open() -> Result<Self> {
+        Ok(Self {
+            dir: app_cache_dir("analyses")?,
+        })
+    }
+
+    fn result_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    fn meta_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.meta.json", key))
+    }
+
+    /// Loads a cached result and marks it as recently used
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut entry = self.entry(key)?;
+        let contents = fs::read(self.result_path(key)).ok()?;
+        let result = serde_json::from_slice(&contents).ok()?;
+
+        entry.last_used = now();
+        if let Ok(json) = serde_json::to_vec_pretty(&entry) {
+            let _ = fs::write(self.meta_path(key), json);
+        }
+        Some(result)
+    }
+
+    /// Metadata of one entry
+    pub fn entry(&self, key: &str) -> Option<CacheEntry> {
+        let contents = fs::read(self.meta_path(key)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    /// Stores a result, replacing any previous entry with the same key
+    pub fn put<T: Serialize>(
+        &self,
+        key: &str,
+        video_path: &Path,
+        fingerprint: &str,
+        params: &str,
+        frame_count: usize,
+        result: &T,
+    ) -> Result<CacheEntry> {
+        let contents = serde_json::to_vec(result)?;
+        fs::write(self.result_path(key), &contents)?;
+
+        let timestamp = now();
+        let entry = CacheEntry {
+            key: key.to_string(),
+            video_path: video_path.to_string_lossy().to_string(),
+            fingerprint: fingerprint.to_string(),
+            params: params.to_string(),
+            frame_count,
+            size_bytes: contents.len() as u64,
+            created_at: timestamp,
+            last_used: timestamp,
+        };
+        fs::write(self.meta_path(key), serde_json::to_vec_pretty(&entry)?)?;
+        Ok(entry)
+    }
+
+    /// All entries, most recently used first
+    pub fn list(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries: Vec<CacheEntry> = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                let key = name.strip_suffix(".meta.json")?;
+                self.entry(key)
+            })
+            .collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.last_used));
+        Ok(entries)
+    }
+
+    /// Removes one entry. Returns false if it did not exist.
+    pub fn remove(&self, key: &str) -> bool {
+        let existed = self.meta_path(key).exists();
+        let _ = fs::remove_file(self.result_path(key));
+        let _ = fs::remove_file(self.meta_path(key));
+        existed
+    }
+
+    /// Removes every entry, returning how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.list()?;
+        for entry in &entries {
+            self.remove(&entry.key);
+        }
+        Ok(entries.len())
+    }
+
+    /// Evicts least recently used entries until the cache fits in `max_bytes`.
+    /// Returns the number of entries removed.
+    pub fn enforce_limit(&self, max_bytes: u64) -> Result<usize> {
+        let entries = self.list()?;
+        let mut total: u64 = entries.iter().map(|e| e.size_bytes).sum();
+        let mut removed = 0;
+
+        for entry in entries.iter().rev() {
+            if total <= max_bytes {
+                break;
+            }
+            self.remove(&entry.key);
+            total = total.saturating_sub(entry.size_bytes);
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_tracks_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let a = dir.join("a.bin");
+        let b = dir.join("b.bin");
+        fs::write(&a, vec![7u8; 3 * 1024 * 1024]).unwrap();
+        fs::copy(&a, &b).unwrap();
+
+        let hash = |path: &Path| {
+            fingerprint(path)
+                .unwrap()
+                .rsplit('-')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(hash(&a), hash(&b));
+
+        let mut changed = vec![7u8; 3 * 1024 * 1024];
+        changed[3 * 1024 * 1024 - 1] = 8;
+        fs::write(&b, changed).unwrap();
+        assert_ne!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_eviction() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let cache = AnalysisCache {
+            dir: dir.to_path_buf(),
+        };
+
+        let video = Path::new("/videos/clip.mp4");
+        let first = cache_key("100-1-abc", "rate=30");
+        let second = cache_key("100-1-abc", "rate=10");
+        assert_ne!(first, second);
+
+        cache
+            .put(
+                &first,
+                video,
+                "100-1-abc",
+                "rate=30",
+                3,
+                &vec![1.0, 2.0, 3.0],
+            )
+            .unwrap();
+        cache
+            .put(&second, video, "100-1-abc", "rate=10", 1, &vec![4.0])
+            .unwrap();
+        assert_eq!(cache.get::<Vec<f64>>(&first), Some(vec![1.0, 2.0, 3.0]));
+        assert_eq!(cache.list().unwrap().len(), 2);
+
+        // Only the most recently used entry fits
+        let keep = cache.entry(&first).unwrap();
+        let mut recent = keep.clone();
+        recent.last_used = u64::MAX;
+        fs::write(
+            cache.meta_path(&first),
+            serde_json::to_vec(&recent).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(cache.enforce_limit(keep.size_bytes).unwrap(), 1);
+        assert!(cache.entry(&first).is_some());
+        assert!(cache.get::<Vec<f64>>(&second).is_none());
+
+        assert_eq!(cache.clear().unwrap(), 1);
+    }
+}
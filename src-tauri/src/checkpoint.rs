@@ -0,0 +1,226 @@
+//! On-disk checkpoints of analyses in progress.
+//!
+//! Scored frames are appended to a JSON Lines file in the app cache directory
+//! as they complete. The file name is a hash of the video (path, size,
+//! modification time) and every parameter that affects which frames are
+//! scored or how, so re-running the same analysis after a crash, restart or
+//! cancel only has to score the frames that are missing. The checkpoint is
+//! removed once the analysis finishes.
+
+use crate::cache::app_cache_dir;
+use crate::video::FrameData;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// Bumped whenever scores or the file layout change, so stale checkpoints are ignored
+const CHECKPOINT_VERSION: u32 = 2;
+
+/// Scored frames buffered before they are written out
+const FLUSH_EVERY: usize = 100;
+
+/// First line of a checkpoint file
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct CheckpointHeader {
+    version: u32,
+    key: String,
+}
+
+/// 64-bit FNV-1a: stable across runs and platforms, unlike `DefaultHasher`
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Identity of a video file on disk: canonical path, size and modification time
+pub fn file_identity(video_path: &Path) -> Result<String> {
+    let path = fs::canonicalize(video_path).unwrap_or_else(|_| video_path.to_path_buf());
+    let metadata =
+        fs::metadata(&path).with_context(|| format!("Failed to read {}", video_path.display()))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    Ok(format!(
+        "{}|{}|{}",
+        path.display(),
+        metadata.len(),
+        modified
+    ))
+}
+
+/// Checkpoint of one analysis: frames finished by earlier runs plus a writer
+/// that appends newly scored frames
+pub struct Checkpoint {
+    path: PathBuf,
+    writer: Mutex<Option<CheckpointWriter>>,
+}
+
+struct CheckpointWriter {
+    file: BufWriter<File>,
+    pending: usize,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint for `key` (video identity plus analysis
+    /// parameters) in the app cache directory, returning it together with
+    /// the frames already scored
+    pub fn open(key: &str) -> Result<(Self, Vec<FrameData>)> {
+        let path =
+            app_cache_dir("checkpoints")?.join(format!("{:016x}.jsonl", fnv1a(key.as_bytes())));
+        Self::open_at(path, key)
+    }
+
+    fn open_at(path: PathBuf, key: &str) -> Result<(Self, Vec<FrameData>)> {
+        let header = CheckpointHeader {
+            version: CHECKPOINT_VERSION,
+            key: key.to_string(),
+        };
+
+        // Missing, stale and colliding files yield no frames and are started over
+        let frames = read_frames(&path, &header).unwrap_or_default();
+
+        // Rewrite rather than append, which also drops a torn last line
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create checkpoint {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        for frame in &frames {
+            writeln!(file, "{}", serde_json::to_string(frame)?)?;
+        }
+        file.flush()?;
+
+        let checkpoint = Self {
+            path,
+            writer: Mutex::new(Some(CheckpointWriter { file, pending: 0 })),
+        };
+        Ok((checkpoint, frames))
+    }
+
+    /// Records a scored frame. Write errors disable the checkpoint rather than
+    /// failing the analysis.
+    pub fn record(&self, frame: &FrameData) {
+        let mut guard = self.writer.lock().unwrap();
+        let Some(writer) = guard.as_mut() else {
+            return;
+        };
+
+        let result = serde_json::to_string(frame)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(writer.file, "{}", line)?))
+            .and_then(|_| {
+                writer.pending += 1;
+                if writer.pending >= FLUSH_EVERY {
+                    writer.pending = 0;
+                    writer.file.flush()?;
+                }
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            eprintln!(
+                "Disabling analysis checkpoint {}: {}",
+                self.path.display(),
+                e
+            );
+            *guard = None;
+        }
+    }
+
+    /// Writes out buffered frames
+    pub fn flush(&self) {
+        if let Some(writer) = self.writer.lock().unwrap().as_mut() {
+            let _ = writer.file.flush();
+        }
+    }
+
+    /// Deletes the checkpoint once the analysis has completed
+    pub fn finish(self) {
+        self.writer.lock().unwrap().take();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Drop for Checkpoint {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Reads the frames of a checkpoint file if its header matches. A torn last
+/// line from a crash mid-write is ignored.
+fn read_frames(path: &Path, header: &CheckpointHeader) -> Option<Vec<FrameData>> {
+    let mut lines = BufReader::new(File::open(path).ok()?).lines();
+    let stored: CheckpointHeader = serde_json::from_str(&lines.next()?.ok()?).ok()?;
+    if &stored != header {
+        return None;
+    }
+
+    Some(
+        lines
+            .map_while(|line| line.ok())
+            .map_while(|line| serde_json::from_str::<FrameData>(&line).ok())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn test_fnv1a_is_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint.jsonl");
+
+        let frame = |n: usize| FrameData {
+            frame_number: n,
+            timestamp: n as f64 / 30.0,
+            sharpness: 100.0 + n as f64,
+            ..Default::default()
+        };
+
+        let (checkpoint, resumed) = Checkpoint::open_at(path.clone(), "video|rate=30").unwrap();
+        assert!(resumed.is_empty());
+        checkpoint.record(&frame(0));
+        checkpoint.record(&frame(30));
+        drop(checkpoint);
+
+        // Simulate a crash in the middle of writing a line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"frame_number\":6").unwrap();
+        drop(file);
+
+        let (checkpoint, resumed) = Checkpoint::open_at(path.clone(), "video|rate=30").unwrap();
+        assert_eq!(
+            resumed.iter().map(|f| f.frame_number).collect::<Vec<_>>(),
+            vec![0, 30]
+        );
+        checkpoint.record(&frame(60));
+        drop(checkpoint);
+
+        let (checkpoint, resumed) = Checkpoint::open_at(path.clone(), "video|rate=30").unwrap();
+        assert_eq!(resumed.len(), 3);
+        drop(checkpoint);
+
+        // Different parameters must not pick up these frames
+        let (checkpoint, resumed) = Checkpoint::open_at(path.clone(), "video|rate=10").unwrap();
+        assert!(resumed.is_empty());
+        checkpoint.finish();
+        assert!(!path.exists());
+    }
+}
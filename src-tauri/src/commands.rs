@@ -1,16 +1,41 @@
-use crate::sharpness::{calculate_auto_threshold, calculate_sharpness, select_frames_smart};
+use crate::cache::{cache_key, fingerprint, AnalysisCache, CacheEntry};
+use crate::checkpoint::{file_identity, Checkpoint};
+use crate::exif::{video_frame_exif, write_jpeg_exif};
+use crate::export::{ensure_unique, rename_outputs, render_filename, write_manifest};
+use crate::hwaccel::{self, HwAccelProbe};
+use crate::jobs::{self, start_job, CancelToken, JobError};
+use crate::segments::{kept_runs, TimeSegments};
+use crate::sequence::{get_sequence_info, is_image_sequence, list_sequence_images, SequenceOrder};
+use crate::sharpness::{
+    calculate_auto_threshold, calculate_normalized_sharpness, select_frames_by_distance,
+    select_frames_smart,
+};
+use crate::source::{open_source, DecodedFrames, FfmpegSource, ImageSequenceSource, VideoSource};
+use crate::telemetry::{load_telemetry, telemetry_key};
+use crate::timecode::TimeInput;
+use crate::toolchain::{validate_toolchain, ToolchainReport};
 use crate::video::{
-    extract_frame_to_memory, extract_frames_batch, get_video_info, sample_frames, FrameData,
-    VideoInfo,
+    apply_hw_decode, detect_field_order, get_video_info, list_video_streams, refine_frame_numbers,
+    DecodeOptions, Deinterlace, FrameData, FrameStatus, RawPixelFormat, ToneMapping, VideoInfo,
+    VideoStreamInfo,
 };
 use anyhow::Result;
-use image::GenericImageView;
+use image::{DynamicImage, GenericImageView};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tauri::Emitter;
 
+/// Single-frame decode attempts for a frame the decode stream did not deliver
+const DECODE_RETRIES: usize = 2;
+
+/// Missing frames of one decode pass that get single-frame retries. Past
+/// this the stream is taken to be broken and the rest are marked failed
+/// rather than spawning an ffmpeg process per attempt for each of them.
+const MAX_RETRIED_FRAMES: usize = 32;
+
 // Configure Rayon for maximum performance
 fn configure_rayon_for_max_performance() {
     // Use all available CPU cores
@@ -26,6 +51,40 @@ pub struct AnalysisProgress {
     pub current_frame: usize,
     pub total_frames: usize,
     pub percentage: f32,
+    /// Which pass the scored frames belong to
+    pub stage: AnalysisStage,
+    /// Frames added to `total_frames` by the refinement pass
+    pub added_frames: usize,
+    /// Frames of `current_frame` taken from a checkpoint of an earlier run
+    pub resumed_frames: usize,
+}
+
+/// Pass of an analysis reported in progress events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+    /// Regular grid at the requested sample rate
+    Sampling,
+    /// Dense re-sampling around peaks and blurry gaps (adaptive mode)
+    Refining,
+}
+
+/// How frames are chosen for scoring
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisMode {
+    /// Every `sample_rate`-th frame
+    #[default]
+    Uniform,
+    /// A frame every `sample_rate` average frame durations of wall-clock
+    /// time, for even spacing on variable frame rate footage
+    UniformTime,
+    /// Uniform coarse pass, then dense re-sampling around promising peaks
+    /// and stretches where every coarse sample was blurry
+    Adaptive,
+    /// Quick pre-scan of keyframes only (sample rate is ignored), for picking
+    /// time ranges of long recordings to analyze in full
+    Keyframes,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,14 +93,153 @@ pub struct AnalysisResult {
     pub frames: Vec<FrameData>,
     pub suggested_threshold: f64,
     pub suggested_frame_count: usize,
+    /// Sampling mode the frames were chosen with
+    #[serde(default)]
+    pub mode: AnalysisMode,
+    /// Number of frames added by adaptive refinement
+    #[serde(default)]
+    pub refined_frame_count: usize,
+    /// Analysis frame size relative to the display size (1.0 = native)
+    #[serde(default = "native_scale")]
+    pub analysis_scale: f64,
+    /// Size of the frames that were scored
+    #[serde(default)]
+    pub analysis_width: u32,
+    #[serde(default)]
+    pub analysis_height: u32,
+    /// Frames taken from the checkpoint of an interrupted earlier run
+    #[serde(default)]
+    pub resumed_frame_count: usize,
+    /// Served from the analysis cache without decoding anything
+    #[serde(default)]
+    pub cached: bool,
+    /// Frames that needed a retry or could not be decoded at all
+    #[serde(default)]
+    pub decode_failures: DecodeFailureSummary,
+    /// Segments the frames were sampled from, with the time range applied
+    #[serde(default)]
+    pub segments: TimeSegments,
+    /// SRT file or subtitle stream the frames' telemetry was read from
+    #[serde(default)]
+    pub telemetry_source: Option<String>,
+    /// Order the images of a sequence were numbered in
+    #[serde(default)]
+    pub sequence_order: SequenceOrder,
+}
+
+/// Decode problems of an analysis, to tell a damaged file from a blurry one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecodeFailureSummary {
+    /// Frames that could not be decoded; they are left out of thresholds and selection
+    pub failed_frames: usize,
+    /// Frames that decoded on a retry
+    pub recovered_frames: usize,
+    /// Distinct error messages of failed frames, most frequent first
+    pub errors: Vec<String>,
+}
+
+impl DecodeFailureSummary {
+    /// Error messages kept in the summary; every frame still has its own
+    const MAX_ERRORS: usize = 5;
+
+    fn from_frames(frames: &[FrameData]) -> Self {
+        let mut summary = Self::default();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for frame in frames {
+            match frame.status {
+                FrameStatus::Decoded => {}
+                FrameStatus::Recovered => summary.recovered_frames += 1,
+                FrameStatus::Failed => {
+                    summary.failed_frames += 1;
+                    *counts
+                        .entry(frame.error.as_deref().unwrap_or("Unknown error"))
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        let mut errors: Vec<(&str, usize)> = counts.into_iter().collect();
+        errors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        summary.errors = errors
+            .into_iter()
+            .take(Self::MAX_ERRORS)
+            .map(|(message, _)| message.to_string())
+            .collect();
+        summary
+    }
+}
+
+fn native_scale() -> f64 {
+    1.0
+}
+
+/// Optional analysis settings beyond the basic sampling parameters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisOptions {
+    /// Absolute index of the video stream to analyze (default: first video stream)
+    pub stream_index: Option<usize>,
+    /// Tone mapping applied to HDR sources before scoring
+    pub tone_mapping: ToneMapping,
+    /// Deinterlacing before scoring (default: when the video is detected as interlaced)
+    pub deinterlace: Deinterlace,
+    /// Image ordering when the input is a folder of images
+    pub sequence_order: SequenceOrder,
+    /// Uniform or coarse-to-fine sampling
+    pub mode: AnalysisMode,
+    /// Long edge in pixels that frames are downscaled to before scoring
+    /// (default: native resolution). Scores are resolution-normalized either way.
+    pub analysis_resolution: Option<u32>,
+    /// Frontend-chosen ID that `cancel_job` can stop this analysis by
+    pub job_id: Option<String>,
+    /// Re-analyze even if the analysis cache has a result
+    pub refresh: bool,
+    /// Stretches of the video to analyze and to skip, in seconds or timecode
+    pub segments: TimeSegments<TimeInput>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportOptions {
-    pub format: String, // "jpg" or "png"
+    pub format: String, // "jpg", "png" or "tiff"
     pub threshold: Option<f64>,
     pub max_frames: Option<usize>,
     pub min_frame_distance: usize,
+    /// Tone mapping applied to HDR sources for 8-bit output
+    #[serde(default)]
+    pub tone_mapping: ToneMapping,
+    /// Deinterlacing of exported frames; keep it as analyzed so exports match the scores
+    #[serde(default)]
+    pub deinterlace: Deinterlace,
+    /// Bits per component for PNG/TIFF output: 8 (default) or 16
+    #[serde(default)]
+    pub bit_depth: Option<u8>,
+    /// Frontend-chosen ID that `cancel_job` can stop this export by
+    #[serde(default)]
+    pub job_id: Option<String>,
+    /// Restricts selection to these segments, in seconds or timecode
+    /// (default: the analysis's segments)
+    #[serde(default)]
+    pub segments: Option<TimeSegments<TimeInput>>,
+    /// File name pattern such as `{video}_{timecode}`; see `export.rs` for placeholders
+    #[serde(default)]
+    pub filename_template: Option<String>,
+    /// Also write a `manifest.csv` listing each file with its frame, timestamp and timecode
+    #[serde(default)]
+    pub write_manifest: bool,
+    /// Write the video's location (or each frame's telemetry position) and
+    /// creation time (offset by each frame's timestamp) into JPEG frames as
+    /// EXIF GPS and DateTimeOriginal tags
+    #[serde(default = "default_write_exif")]
+    pub write_exif: bool,
+    /// Space selected frames by at least this many meters travelled along the
+    /// telemetry GPS track instead of `min_frame_distance` frames
+    #[serde(default)]
+    pub min_travel_distance: Option<f64>,
+}
+
+fn default_write_exif() -> bool {
+    true
 }
 
 /// Analyzes a video and returns sharpness scores for all sampled frames
@@ -50,22 +248,63 @@ pub async fn analyze_video(
     video_path: String,
     sample_rate: usize,
     use_gpu: bool,
-    start_time: Option<f64>,
-    end_time: Option<f64>,
+    start_time: Option<TimeInput>,
+    end_time: Option<TimeInput>,
+    options: Option<AnalysisOptions>,
     window: tauri::Window,
-) -> Result<AnalysisResult, String> {
+) -> Result<AnalysisResult, JobError> {
     let path = Path::new(&video_path);
 
     // Configure Rayon for maximum CPU utilization
     configure_rayon_for_max_performance();
 
-    // Get video information
-    let video_info = get_video_info(path).map_err(|e| e.to_string())?;
+    let options = options.unwrap_or_default();
+    let job = start_job(options.job_id.as_deref());
+    let cancel = job.token();
 
-    // Sample frames to analyze with optional time range filtering
-    let frame_numbers = sample_frames(path, sample_rate, start_time, end_time).map_err(|e| e.to_string())?;
+    let emit = |progress: &AnalysisProgress| {
+        let _ = window.emit("analysis-progress", progress);
+    };
 
-    let total_frames = frame_numbers.len();
+    // Folders of photos are scored image by image; video files are decoded by ffmpeg
+    if is_image_sequence(path) {
+        let source = ImageSequenceSource::open(path, options.sequence_order)?;
+        let segments = resolve_segments(&options, source.info(), start_time, end_time)?;
+        return Ok(run_analysis(
+            &source,
+            sample_rate,
+            &segments,
+            &options,
+            cancel,
+            None,
+            &emit,
+        )?);
+    }
+
+    // Get video information
+    let mut video_info = get_video_info(path, options.stream_index)?;
+    let segments = resolve_segments(&options, &video_info, start_time, end_time)?;
+
+    // Same file content and parameters as an earlier run: reuse its result
+    let params = analysis_params(
+        &video_info,
+        sample_rate,
+        &segments,
+        &options,
+        &telemetry_key(path, &video_info),
+    );
+    let video_fingerprint = fingerprint(path)
+        .map_err(|e| eprintln!("Analysis cache unavailable: {}", e))
+        .ok();
+    if let Some(fingerprint) = video_fingerprint.as_deref().filter(|_| !options.refresh) {
+        let cached = AnalysisCache::open()
+            .ok()
+            .and_then(|cache| cache.get::<AnalysisResult>(&cache_key(fingerprint, &params)));
+        if let Some(mut result) = cached {
+            result.cached = true;
+            return Ok(result);
+        }
+    }
 
     // GPU-accelerated analysis path
     if use_gpu {
@@ -77,56 +316,420 @@ pub async fn analyze_video(
         // Fall through to CPU path for maximum performance
     }
 
-    // CPU-parallelized analysis path - optimized for maximum throughput
-    let progress_counter = Arc::new(Mutex::new(0usize));
-    let frames: Vec<FrameData> = frame_numbers
-        .par_iter()
-        .enumerate()
-        .map(|(_idx, &frame_num)| {
-            // Extract frame and calculate sharpness in parallel
-            let result = extract_frame_to_memory(path, frame_num)
-                .and_then(|img| {
-                    let sharpness = calculate_sharpness(&img);
-                    Ok(FrameData {
-                        frame_number: frame_num,
-                        timestamp: frame_num as f64 / video_info.fps,
-                        sharpness,
-                        path: None,
-                    })
-                })
-                .unwrap_or_else(|_| FrameData {
-                    frame_number: frame_num,
-                    timestamp: frame_num as f64 / video_info.fps,
-                    sharpness: 0.0,
-                    path: None,
-                });
-
-            // Update progress with proper synchronization
-            {
-                let mut p = progress_counter.lock().unwrap();
-                *p += 1;
-                let current = *p;
-
-                // Update every 10 frames to reduce overhead
-                if current % 10 == 0 || current == total_frames {
-                    let percentage = (current as f32 / total_frames as f32) * 100.0;
-                    let _ = window.emit(
-                        "analysis-progress",
-                        AnalysisProgress {
-                            current_frame: current,
-                            total_frames,
-                            percentage,
-                        },
-                    );
-                }
+    // DJI flight telemetry from a sibling SRT file or a subtitle stream;
+    // analyses work without it
+    let telemetry = load_telemetry(path, &video_info, cancel)
+        .map_err(|e| eprintln!("Telemetry unavailable: {}", e))
+        .ok()
+        .flatten();
+
+    // Automatic deinterlacing needs to know whether the frames are interlaced
+    if options.deinterlace == Deinterlace::Auto {
+        detect_field_order(path, &mut video_info, cancel)?;
+    }
+
+    // Building the frame index maps frame numbers to real presentation timestamps
+    let source = FfmpegSource::new(path, video_info, cancel)?.with_telemetry(telemetry);
+
+    // Long analyses write scored frames to a checkpoint and pick up where an
+    // interrupted run stopped. Keyframe scans are quick and decode in one
+    // contiguous run, so they always start fresh.
+    let (checkpoint, resumed) = if options.mode == AnalysisMode::Keyframes {
+        (None, Vec::new())
+    } else {
+        match file_identity(path)
+            .and_then(|identity| Checkpoint::open(&format!("{}|{}", identity, params)))
+        {
+            Ok((checkpoint, frames)) => (Some(checkpoint), frames),
+            Err(e) => {
+                eprintln!("Analysis checkpoint unavailable: {}", e);
+                (None, Vec::new())
+            }
+        }
+    };
+
+    let resume = checkpoint.as_ref().map(|checkpoint| (checkpoint, resumed));
+    let mut result = run_analysis(
+        &source,
+        sample_rate,
+        &segments,
+        &options,
+        cancel,
+        resume,
+        &emit,
+    )?;
+    // A hardware decoder may have failed, and been replaced, while streaming
+    apply_hw_decode(path, &mut result.video_info, cancel)?;
+
+    // Finished analyses are not resumed; they are served from the cache instead
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.finish();
+    }
+    // Frames that failed to decode get another chance on the next run
+    if let Some(fingerprint) =
+        video_fingerprint.filter(|_| result.decode_failures.failed_frames == 0)
+    {
+        store_in_cache(path, &fingerprint, &params, &result);
+    }
+
+    Ok(result)
+}
+
+/// Samples, decodes and scores frames of any source. `resume` is the
+/// checkpoint to record scored frames in, with the frames it already holds.
+fn run_analysis(
+    source: &dyn VideoSource,
+    sample_rate: usize,
+    segments: &TimeSegments,
+    options: &AnalysisOptions,
+    cancel: &CancelToken,
+    resume: Option<(&Checkpoint, Vec<FrameData>)>,
+    emit: &(dyn Fn(&AnalysisProgress) + Sync),
+) -> Result<AnalysisResult> {
+    let (checkpoint, resumed) = match resume {
+        Some((checkpoint, frames)) => (Some(checkpoint), frames),
+        None => (None, Vec::new()),
+    };
+    let resumed_frame_count = resumed.len();
+    let mut resumed: HashMap<usize, FrameData> =
+        resumed.into_iter().map(|f| (f.frame_number, f)).collect();
+
+    // Sample frames between the first and last included time, then drop the
+    // ones outside the segments
+    let (start_time, end_time) = segments.bounds();
+    let candidates = match options.mode {
+        AnalysisMode::Keyframes => source.keyframes(start_time, end_time),
+        AnalysisMode::UniformTime => {
+            let info = source.info();
+            let fps = if info.avg_fps > 0.0 {
+                info.avg_fps
+            } else {
+                info.fps
+            };
+            source.sample_by_time(sample_rate.max(1) as f64 / fps, start_time, end_time)
+        }
+        AnalysisMode::Uniform | AnalysisMode::Adaptive => {
+            source.sample(sample_rate, start_time, end_time)
+        }
+    };
+    let runs = kept_runs(&candidates, |n| segments.contains(source.position(n)));
+    let frame_numbers = runs.concat();
+    if options.mode == AnalysisMode::Keyframes && frame_numbers.is_empty() {
+        anyhow::bail!("No keyframes found in the selected range");
+    }
+
+    let total_frames = frame_numbers.len();
+
+    // HDR frames are tone-mapped so their scores are comparable with SDR clips
+    let decode_options = DecodeOptions {
+        tone_mapping: options.tone_mapping,
+        deinterlace: options.deinterlace,
+        high_bit_depth: false,
+        analysis_resolution: options.analysis_resolution,
+        cancel: cancel.clone(),
+    };
+
+    let progress = ProgressReporter::new(emit, total_frames);
+    let (mut frames, remaining) = take_resumed(&mut resumed, &frame_numbers);
+    progress.resume(frames.len());
+
+    if !remaining.is_empty() {
+        if options.mode == AnalysisMode::Keyframes {
+            // Keyframes are decoded in runs of consecutive ones, so each
+            // stretch between excluded segments is scanned on its own.
+            // Keyframe scans are never resumed, so `runs` is still complete.
+            for run in &runs {
+                let decoded =
+                    source.decode_keyframes(run, &decode_options, RawPixelFormat::Gray)?;
+                frames.extend(score_frames(
+                    decoded,
+                    source,
+                    run,
+                    &decode_options,
+                    false,
+                    cancel,
+                    &progress,
+                    checkpoint,
+                )?);
+            }
+        } else {
+            let decoded = source.decode(&remaining, &decode_options, RawPixelFormat::Gray)?;
+            frames.extend(score_frames(
+                decoded,
+                source,
+                &remaining,
+                &decode_options,
+                false,
+                cancel,
+                &progress,
+                checkpoint,
+            )?);
+        }
+        frames.sort_by_key(|f| f.frame_number);
+    }
+
+    // Second pass: look closer where the coarse grid found a peak or only blur.
+    // The extra work is capped at the size of the coarse pass.
+    let mut refined_frame_count = 0;
+    if options.mode == AnalysisMode::Adaptive {
+        let coarse: Vec<(usize, f64)> = frames
+            .iter()
+            .filter(|f| f.is_decoded())
+            .map(|f| (f.frame_number, f.sharpness))
+            .collect();
+        let refine_numbers =
+            refine_frame_numbers(&coarse, sample_rate, source.frame_count(), total_frames);
+        // Peak neighborhoods may reach past a segment boundary
+        let refine_numbers: Vec<usize> = refine_numbers
+            .into_iter()
+            .filter(|&n| segments.contains(source.position(n)))
+            .collect();
+
+        if !refine_numbers.is_empty() {
+            progress.start_refining(refine_numbers.len());
+            let (mut refined, remaining) = take_resumed(&mut resumed, &refine_numbers);
+            progress.resume(refined.len());
+
+            if !remaining.is_empty() {
+                let decoded = source.decode(&remaining, &decode_options, RawPixelFormat::Gray)?;
+                refined.extend(score_frames(
+                    decoded,
+                    source,
+                    &remaining,
+                    &decode_options,
+                    true,
+                    cancel,
+                    &progress,
+                    checkpoint,
+                )?);
             }
+            refined_frame_count = refined.len();
+            frames.extend(refined);
+            frames.sort_by_key(|f| f.frame_number);
+        }
+    }
 
-            result
+    // Set here rather than while scoring so frames resumed from a checkpoint get them too
+    for frame in &mut frames {
+        frame.timecode = source.timecode(frame.frame_number);
+        frame.telemetry = source.telemetry(frame.frame_number);
+    }
+
+    let analysis_size = decode_options.output_dimensions(source.info());
+    let mut result = finish_analysis(source.info().clone(), frames, analysis_size);
+    result.mode = options.mode;
+    result.refined_frame_count = refined_frame_count;
+    result.resumed_frame_count = resumed_frame_count;
+    result.segments = segments.clone();
+    result.telemetry_source = source.telemetry_source().map(String::from);
+    result.sequence_order = options.sequence_order;
+    Ok(result)
+}
+
+/// Converts a time range given in seconds or timecode to seconds and
+/// combines it with the segments of the options
+fn resolve_segments(
+    options: &AnalysisOptions,
+    info: &VideoInfo,
+    start_time: Option<TimeInput>,
+    end_time: Option<TimeInput>,
+) -> Result<TimeSegments> {
+    let seconds = |time: Option<TimeInput>| time.map(|t| t.seconds(info)).transpose();
+    options
+        .segments
+        .seconds(info)?
+        .resolve(seconds(start_time)?, seconds(end_time)?)
+}
+
+/// Every parameter that changes which frames an analysis scores, what they
+/// score or what is recorded with them, as a human-readable string for
+/// checkpoint and cache keys
+fn analysis_params(
+    video_info: &VideoInfo,
+    sample_rate: usize,
+    segments: &TimeSegments,
+    options: &AnalysisOptions,
+    telemetry: &str,
+) -> String {
+    format!(
+        "stream={}|rate={}|{}|mode={:?}|tone={:?}|deinterlace={:?}|resolution={:?}|telemetry={}",
+        video_info.stream_index,
+        sample_rate,
+        segments.describe(),
+        options.mode,
+        options.tone_mapping,
+        options.deinterlace,
+        options.analysis_resolution,
+        telemetry,
+    )
+}
+
+/// Adds a finished analysis to the cache and trims the cache to its size cap.
+/// Failures only cost a future cache hit, so they are logged and ignored.
+fn store_in_cache(path: &Path, fingerprint: &str, params: &str, result: &AnalysisResult) {
+    let stored = AnalysisCache::open().and_then(|cache| {
+        let key = cache_key(fingerprint, params);
+        cache.put(&key, path, fingerprint, params, result.frames.len(), result)?;
+        let limit_mb = AppSettings::load()
+            .map(|s| s.analysis_cache_limit_mb)
+            .unwrap_or_default();
+        cache.enforce_limit(limit_mb * 1024 * 1024)
+    });
+    if let Err(e) = stored {
+        eprintln!("Failed to cache analysis: {}", e);
+    }
+}
+
+/// Splits `frame_numbers` into frames already scored by an earlier run
+/// (removed from `resumed`) and frames that still need scoring
+fn take_resumed(
+    resumed: &mut HashMap<usize, FrameData>,
+    frame_numbers: &[usize],
+) -> (Vec<FrameData>, Vec<usize>) {
+    let mut done = Vec::new();
+    let mut remaining = Vec::new();
+    for &frame_number in frame_numbers {
+        match resumed.remove(&frame_number) {
+            Some(frame) => done.push(frame),
+            None => remaining.push(frame_number),
+        }
+    }
+    (done, remaining)
+}
+
+/// Scores frames in parallel as they come out of the decoder (for ffmpeg, a
+/// single process for all of them). Up to `MAX_RETRIED_FRAMES` frames the
+/// stream did not deliver are decoded again one at a time; frames that still
+/// do not decode, and any past that cap, are recorded as failed. Returns them
+/// sorted by frame number.
+#[allow(clippy::too_many_arguments)]
+fn score_frames(
+    decoded: DecodedFrames,
+    source: &dyn VideoSource,
+    frame_numbers: &[usize],
+    decode_options: &DecodeOptions,
+    refined: bool,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+    checkpoint: Option<&Checkpoint>,
+) -> Result<Vec<FrameData>> {
+    let stream_error: Mutex<Option<String>> = Mutex::new(None);
+    let scored = |frame_num: usize, img: &DynamicImage| FrameData {
+        frame_number: frame_num,
+        timestamp: source.timestamp(frame_num),
+        sharpness: calculate_normalized_sharpness(img),
+        path: source
+            .frame_path(frame_num)
+            .map(|p| p.to_string_lossy().to_string()),
+        refined,
+        ..Default::default()
+    };
+    let record = |frame: FrameData| {
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.record(&frame);
+        }
+        progress.frame_done();
+        frame
+    };
+
+    let mut frames: Vec<FrameData> = decoded
+        .par_bridge()
+        .filter_map(|decoded| {
+            // Cancelling kills ffmpeg, which ends the stream; skip frames still in flight
+            if cancel.is_cancelled() {
+                return None;
+            }
+            match decoded {
+                Ok((frame_num, img)) => Some(record(scored(frame_num, &img))),
+                Err(e) => {
+                    stream_error
+                        .lock()
+                        .unwrap()
+                        .get_or_insert_with(|| format!("{:#}", e));
+                    None
+                }
+            }
         })
         .collect();
 
-    // Calculate suggested threshold and frame count
-    let sharpness_scores: Vec<f64> = frames.iter().map(|f| f.sharpness).collect();
+    // Missing frames after a cancel are not decode failures
+    cancel.check()?;
+
+    let decoded: HashSet<usize> = frames.iter().map(|f| f.frame_number).collect();
+    let missing: Vec<usize> = frame_numbers
+        .iter()
+        .copied()
+        .filter(|n| !decoded.contains(n))
+        .collect();
+    if !missing.is_empty() {
+        let first_error = stream_error
+            .into_inner()
+            .unwrap()
+            .unwrap_or_else(|| "Frame was not returned by the decoder".to_string());
+
+        let retried: Vec<FrameData> = missing
+            .par_iter()
+            .enumerate()
+            .map(|(i, &frame_num)| {
+                let mut error = first_error.clone();
+                let attempts = if i < MAX_RETRIED_FRAMES {
+                    DECODE_RETRIES
+                } else {
+                    0
+                };
+                for _ in 0..attempts {
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    match source.decode_frame(frame_num, decode_options, RawPixelFormat::Gray) {
+                        Ok(img) => {
+                            return record(FrameData {
+                                status: FrameStatus::Recovered,
+                                error: Some(error),
+                                ..scored(frame_num, &img)
+                            });
+                        }
+                        Err(e) => error = format!("{:#}", e),
+                    }
+                }
+                // Failed frames stay out of the checkpoint so a resumed run tries them again
+                progress.frame_done();
+                FrameData {
+                    frame_number: frame_num,
+                    timestamp: source.timestamp(frame_num),
+                    sharpness: 0.0,
+                    path: source
+                        .frame_path(frame_num)
+                        .map(|p| p.to_string_lossy().to_string()),
+                    refined,
+                    status: FrameStatus::Failed,
+                    error: Some(error),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        cancel.check()?;
+        frames.extend(retried);
+    }
+
+    // par_bridge does not preserve order
+    frames.sort_by_key(|f| f.frame_number);
+
+    Ok(frames)
+}
+
+/// Computes the suggested threshold and frame count for scored frames
+fn finish_analysis(
+    video_info: VideoInfo,
+    frames: Vec<FrameData>,
+    (analysis_width, analysis_height): (u32, u32),
+) -> AnalysisResult {
+    // Calculate suggested threshold and frame count from frames that were decoded
+    let sharpness_scores: Vec<f64> = frames
+        .iter()
+        .filter(|f| f.is_decoded())
+        .map(|f| f.sharpness)
+        .collect();
     let suggested_threshold = calculate_auto_threshold(&sharpness_scores, None);
 
     // Count frames above threshold
@@ -135,12 +738,103 @@ pub async fn analyze_video(
         .filter(|&&s| s >= suggested_threshold)
         .count();
 
-    Ok(AnalysisResult {
+    let analysis_scale = if video_info.display_width > 0 {
+        analysis_width as f64 / video_info.display_width as f64
+    } else {
+        1.0
+    };
+
+    let decode_failures = DecodeFailureSummary::from_frames(&frames);
+
+    AnalysisResult {
         video_info,
         frames,
         suggested_threshold,
         suggested_frame_count,
-    })
+        mode: AnalysisMode::Uniform,
+        refined_frame_count: 0,
+        analysis_scale,
+        analysis_width,
+        analysis_height,
+        resumed_frame_count: 0,
+        cached: false,
+        decode_failures,
+        segments: TimeSegments::default(),
+        telemetry_source: None,
+        sequence_order: SequenceOrder::default(),
+    }
+}
+
+/// Counts scored frames from parallel workers and reports progress through
+/// `emit` (an `analysis-progress` event for the frontend)
+struct ProgressReporter<'a> {
+    emit: &'a (dyn Fn(&AnalysisProgress) + Sync),
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    completed: usize,
+    total_frames: usize,
+    added_frames: usize,
+    resumed_frames: usize,
+    stage: AnalysisStage,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(emit: &'a (dyn Fn(&AnalysisProgress) + Sync), total_frames: usize) -> Self {
+        Self {
+            emit,
+            state: Mutex::new(ProgressState {
+                completed: 0,
+                total_frames,
+                added_frames: 0,
+                resumed_frames: 0,
+                stage: AnalysisStage::Sampling,
+            }),
+        }
+    }
+
+    /// Switches to the refinement pass, growing the total by its frame count
+    fn start_refining(&self, added_frames: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.stage = AnalysisStage::Refining;
+        state.total_frames += added_frames;
+        state.added_frames += added_frames;
+    }
+
+    /// Counts frames restored from a checkpoint as done and reports them at once
+    fn resume(&self, frames: usize) {
+        if frames == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.completed += frames;
+        state.resumed_frames += frames;
+        self.emit(&state);
+    }
+
+    fn frame_done(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.completed += 1;
+        let current = state.completed;
+
+        // Update every 10 frames to reduce overhead
+        if current % 10 == 0 || current == state.total_frames {
+            self.emit(&state);
+        }
+    }
+
+    fn emit(&self, state: &ProgressState) {
+        let percentage = (state.completed as f32 / state.total_frames as f32) * 100.0;
+        (self.emit)(&AnalysisProgress {
+            current_frame: state.completed,
+            total_frames: state.total_frames,
+            percentage,
+            stage: state.stage,
+            added_frames: state.added_frames,
+            resumed_frames: state.resumed_frames,
+        });
+    }
 }
 
 /// Exports selected frames based on the provided options
@@ -151,24 +845,110 @@ pub async fn export_frames(
     analysis_result: AnalysisResult,
     options: ExportOptions,
     _window: tauri::Window,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, JobError> {
     let video_path = Path::new(&video_path);
     let output_dir = Path::new(&output_dir);
 
-    // Determine threshold
-    let threshold = options
-        .threshold
-        .unwrap_or(analysis_result.suggested_threshold);
+    let job = start_job(options.job_id.as_deref());
+    let cancel = job.token();
 
-    // Get sharpness scores
-    let sharpness_scores: Vec<f64> = analysis_result.frames.iter().map(|f| f.sharpness).collect();
+    let high_bit_depth = match options.bit_depth.unwrap_or(8) {
+        8 => false,
+        16 if options.format != "jpg" => true,
+        16 => {
+            return Err("16-bit export requires PNG or TIFF format"
+                .to_string()
+                .into())
+        }
+        other => return Err(format!("Unsupported export bit depth: {}", other).into()),
+    };
+    let decode_options = DecodeOptions {
+        tone_mapping: options.tone_mapping,
+        deinterlace: options.deinterlace,
+        high_bit_depth,
+        analysis_resolution: None,
+        cancel: cancel.clone(),
+    };
 
-    // Select frames using smart selection
-    let selected_indices = select_frames_smart(
-        &sharpness_scores,
-        threshold,
-        options.min_frame_distance,
-    );
+    // Image sequences export the chosen originals, copied or converted, in
+    // the order they were numbered in; videos decode the frames at the stream
+    // and geometry recorded in the analysis
+    let is_sequence = is_image_sequence(video_path);
+    let source: Box<dyn VideoSource> = if is_sequence {
+        Box::new(ImageSequenceSource::open(
+            video_path,
+            analysis_result.sequence_order,
+        )?)
+    } else {
+        Box::new(FfmpegSource::new(
+            video_path,
+            analysis_result.video_info.clone(),
+            cancel,
+        )?)
+    };
+
+    // Segment times are seconds from the start of the video. Sequence frames
+    // were numbered in an order export does not know, but their recorded
+    // timestamps already are such positions.
+    let segments = match &options.segments {
+        Some(segments) => segments.seconds(&analysis_result.video_info)?,
+        None => analysis_result.segments.clone(),
+    };
+    let position = |f: &FrameData| {
+        if is_sequence {
+            f.timestamp
+        } else {
+            source.position(f.frame_number)
+        }
+    };
+
+    // Get sharpness scores; frames that failed to decode or lie outside the
+    // segments can never be selected
+    let sharpness_scores: Vec<f64> = analysis_result
+        .frames
+        .iter()
+        .map(|f| {
+            if f.is_decoded() && segments.contains(position(f)) {
+                f.sharpness
+            } else {
+                f64::NEG_INFINITY
+            }
+        })
+        .collect();
+
+    // Determine threshold; narrower segments than analyzed get their own suggestion
+    let threshold = options.threshold.unwrap_or_else(|| {
+        if options.segments.is_some() {
+            let eligible: Vec<f64> = sharpness_scores
+                .iter()
+                .copied()
+                .filter(|s| s.is_finite())
+                .collect();
+            calculate_auto_threshold(&eligible, None)
+        } else {
+            analysis_result.suggested_threshold
+        }
+    });
+
+    // Select frames using smart selection, spaced by frames or by meters flown
+    let selected_indices = match options.min_travel_distance {
+        Some(min_distance) => {
+            let distances: Vec<Option<f64>> = analysis_result
+                .frames
+                .iter()
+                .map(|f| f.telemetry.as_ref().and_then(|t| t.distance))
+                .collect();
+            if distances.iter().all(Option::is_none) {
+                return Err(
+                    "Spacing by travelled distance needs GPS telemetry (a DJI .SRT file)"
+                        .to_string()
+                        .into(),
+                );
+            }
+            select_frames_by_distance(&sharpness_scores, threshold, &distances, min_distance)
+        }
+        None => select_frames_smart(&sharpness_scores, threshold, options.min_frame_distance),
+    };
 
     // Limit to max_frames if specified
     let selected_indices: Vec<usize> = if let Some(max) = options.max_frames {
@@ -184,15 +964,67 @@ pub async fn export_frames(
         selected_indices
     };
 
-    // Get actual frame numbers
-    let frame_numbers: Vec<usize> = selected_indices
+    // Sources return exported files in frame order
+    let mut selected: Vec<FrameData> = selected_indices
         .iter()
-        .map(|&idx| analysis_result.frames[idx].frame_number)
+        .map(|&idx| analysis_result.frames[idx].clone())
         .collect();
+    selected.sort_by_key(|f| f.frame_number);
 
-    // Export frames
-    let output_paths = extract_frames_batch(video_path, &frame_numbers, output_dir, &options.format)
-        .map_err(|e| e.to_string())?;
+    // Catch template mistakes before spending time on the export
+    let video_name = video_path
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if let Some(template) = &options.filename_template {
+        for frame in &selected {
+            render_filename(template, frame, "frame", &video_name)?;
+        }
+    }
+
+    let mut output_paths =
+        source.export(&selected, &decode_options, output_dir, &options.format)?;
+
+    // Video frames come out of ffmpeg without metadata; image sequence
+    // originals keep their own
+    if options.write_exif && !is_sequence && matches!(options.format.as_str(), "jpg" | "jpeg") {
+        let written =
+            output_paths.iter().zip(&selected).try_for_each(
+                |(path, frame)| match video_frame_exif(&analysis_result.video_info, frame) {
+                    Some(exif) => write_jpeg_exif(path, &exif),
+                    None => Ok(()),
+                },
+            );
+        // Do not leave an export behind where only some frames are tagged
+        if let Err(e) = written {
+            for path in &output_paths {
+                let _ = std::fs::remove_file(path);
+            }
+            return Err(e.into());
+        }
+    }
+
+    if let Some(template) = &options.filename_template {
+        let names = output_paths
+            .iter()
+            .zip(&selected)
+            .map(|(path, frame)| {
+                let default_name = path
+                    .file_stem()
+                    .map(|n| n.to_string_lossy())
+                    .unwrap_or_default();
+                render_filename(template, frame, &default_name, &video_name)
+            })
+            .collect::<Result<Vec<String>>>()?;
+        ensure_unique(&names)?;
+        output_paths = rename_outputs(&output_paths, &names)?;
+    }
+
+    if options.write_manifest {
+        let files: Vec<(PathBuf, &FrameData)> =
+            output_paths.iter().cloned().zip(&selected).collect();
+        write_manifest(output_dir, &files)?;
+    }
 
     // Convert paths to strings
     let path_strings: Vec<String> = output_paths
@@ -203,11 +1035,90 @@ pub async fn export_frames(
     Ok(path_strings)
 }
 
+/// Cancels a running analysis or export by the `job_id` it was started with.
+/// Returns false if no such job is running (e.g. it already finished).
+#[tauri::command]
+pub fn cancel_job(job_id: String) -> Result<bool, String> {
+    Ok(jobs::cancel_job(&job_id))
+}
+
+/// Lists cached analyses, most recently used first
+#[tauri::command]
+pub async fn list_cached_analyses() -> Result<Vec<CacheEntry>, String> {
+    let cache = AnalysisCache::open().map_err(|e| e.to_string())?;
+    cache.list().map_err(|e| e.to_string())
+}
+
+/// Loads a cached analysis by key without touching the video
+#[tauri::command]
+pub async fn get_cached_analysis(key: String) -> Result<AnalysisResult, String> {
+    let cache = AnalysisCache::open().map_err(|e| e.to_string())?;
+    let mut result: AnalysisResult = cache
+        .get(&key)
+        .ok_or_else(|| format!("No cached analysis {}", key))?;
+    result.cached = true;
+    Ok(result)
+}
+
+/// Removes one cached analysis, or all of them if no key is given.
+/// Returns the number of entries removed.
+#[tauri::command]
+pub async fn purge_analysis_cache(key: Option<String>) -> Result<usize, String> {
+    let cache = AnalysisCache::open().map_err(|e| e.to_string())?;
+    match key {
+        Some(key) => Ok(cache.remove(&key) as usize),
+        None => cache.clear().map_err(|e| e.to_string()),
+    }
+}
+
+/// Sets the analysis cache size cap and evicts entries over it.
+/// Returns the number of entries removed.
+#[tauri::command]
+pub async fn set_analysis_cache_limit(limit_mb: u64) -> Result<usize, String> {
+    let mut settings = AppSettings::load().map_err(|e| e.to_string())?;
+    settings.analysis_cache_limit_mb = limit_mb;
+    settings.save().map_err(|e| e.to_string())?;
+
+    let cache = AnalysisCache::open().map_err(|e| e.to_string())?;
+    cache
+        .enforce_limit(limit_mb * 1024 * 1024)
+        .map_err(|e| e.to_string())
+}
+
 /// Gets video metadata without full analysis
 #[tauri::command]
-pub async fn get_video_metadata(video_path: String) -> Result<VideoInfo, String> {
+pub async fn get_video_metadata(
+    video_path: String,
+    stream_index: Option<usize>,
+) -> Result<VideoInfo, String> {
+    let path = Path::new(&video_path);
+
+    if is_image_sequence(path) {
+        let images =
+            list_sequence_images(path, SequenceOrder::Filename).map_err(|e| e.to_string())?;
+        return get_sequence_info(&images).map_err(|e| e.to_string());
+    }
+
+    get_video_info(path, stream_index).map_err(|e| e.to_string())
+}
+
+/// Converts a time typed as seconds or as a timecode such as `01:00:10:12`
+/// to seconds from the start of the video
+#[tauri::command]
+pub async fn time_input_to_seconds(
+    video_path: String,
+    value: TimeInput,
+    stream_index: Option<usize>,
+) -> Result<f64, String> {
+    let info = get_video_metadata(video_path, stream_index).await?;
+    value.seconds(&info).map_err(|e| e.to_string())
+}
+
+/// Lists the video streams of a file so one can be picked for analysis
+#[tauri::command]
+pub async fn get_video_streams(video_path: String) -> Result<Vec<VideoStreamInfo>, String> {
     let path = Path::new(&video_path);
-    get_video_info(path).map_err(|e| e.to_string())
+    list_video_streams(path).map_err(|e| e.to_string())
 }
 
 /// Calculates a custom threshold based on desired frame count
@@ -216,7 +1127,31 @@ pub fn calculate_threshold_for_count(
     sharpness_scores: Vec<f64>,
     target_count: usize,
 ) -> Result<f64, String> {
-    Ok(calculate_auto_threshold(&sharpness_scores, Some(target_count)))
+    Ok(calculate_auto_threshold(
+        &sharpness_scores,
+        Some(target_count),
+    ))
+}
+
+/// Selects frames at or above the threshold, spaced by at least
+/// `min_distance` meters travelled. Failed frames have no score.
+#[tauri::command]
+pub fn select_frames_by_travel(
+    sharpness_scores: Vec<Option<f64>>,
+    distances: Vec<Option<f64>>,
+    threshold: f64,
+    min_distance: f64,
+) -> Result<Vec<usize>, String> {
+    let scores: Vec<f64> = sharpness_scores
+        .iter()
+        .map(|s| s.unwrap_or(f64::NEG_INFINITY))
+        .collect();
+    Ok(select_frames_by_distance(
+        &scores,
+        threshold,
+        &distances,
+        min_distance,
+    ))
 }
 
 /// Gets a frame image as base64 for preview
@@ -224,15 +1159,30 @@ pub fn calculate_threshold_for_count(
 pub async fn get_frame_preview(
     video_path: String,
     frame_number: usize,
+    options: Option<AnalysisOptions>,
 ) -> Result<String, String> {
+    use base64::{engine::general_purpose, Engine as _};
     use image::ImageFormat;
     use std::io::Cursor;
-    use base64::{engine::general_purpose, Engine as _};
 
     let path = Path::new(&video_path);
 
-    // Extract frame to memory
-    let img = extract_frame_to_memory(path, frame_number)
+    let options = options.unwrap_or_default();
+
+    let decode_options = DecodeOptions {
+        tone_mapping: options.tone_mapping,
+        deinterlace: options.deinterlace,
+        ..Default::default()
+    };
+    let source = open_source(
+        path,
+        options.stream_index,
+        options.sequence_order,
+        &decode_options,
+    )
+    .map_err(|e| e.to_string())?;
+    let img = source
+        .decode_frame(frame_number, &decode_options, RawPixelFormat::Rgb24)
         .map_err(|e| e.to_string())?;
 
     // Resize for preview (max 800px width to reduce data size)
@@ -257,7 +1207,7 @@ pub async fn get_frame_preview(
 }
 
 /// Settings management commands
-use crate::settings::{AppSettings, detect_ffmpeg_paths, get_install_instructions};
+use crate::settings::{detect_ffmpeg_paths, get_install_instructions, AppSettings};
 
 #[tauri::command]
 pub async fn get_settings() -> Result<AppSettings, String> {
@@ -293,6 +1243,34 @@ pub async fn validate_ffmpeg_path(path: String) -> Result<bool, String> {
     }
 }
 
+/// Checks a pair of FFmpeg/FFprobe binaries for everything the extractor
+/// needs. Paths that are not given fall back to auto-detected ones.
+#[tauri::command]
+pub async fn validate_ffmpeg_toolchain(
+    ffmpeg_path: Option<String>,
+    ffprobe_path: Option<String>,
+) -> Result<ToolchainReport, String> {
+    let (detected_ffmpeg, detected_ffprobe) = detect_ffmpeg_paths();
+    let pick = |path: Option<String>, detected: Option<PathBuf>| {
+        path.filter(|p| !p.trim().is_empty())
+            .map(PathBuf::from)
+            .or(detected)
+    };
+    let ffmpeg_path = pick(ffmpeg_path, detected_ffmpeg);
+    let ffprobe_path = pick(ffprobe_path, detected_ffprobe);
+
+    Ok(validate_toolchain(
+        ffmpeg_path.as_deref(),
+        ffprobe_path.as_deref(),
+    ))
+}
+
+/// Reports which hardware decoders work on this machine, probing on first call
+#[tauri::command]
+pub async fn get_hw_accel_status() -> Result<HwAccelProbe, String> {
+    Ok(hwaccel::probe().clone())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -304,8 +1282,202 @@ mod tests {
             threshold: Some(100.0),
             max_frames: Some(50),
             min_frame_distance: 5,
+            tone_mapping: ToneMapping::Auto,
+            deinterlace: Deinterlace::Auto,
+            bit_depth: None,
+            job_id: None,
+            segments: None,
+            filename_template: None,
+            write_manifest: false,
+            write_exif: true,
+            min_travel_distance: None,
         };
 
         assert_eq!(options.format, "jpg");
     }
+
+    #[test]
+    fn test_analysis_options_mode() {
+        let options: AnalysisOptions = serde_json::from_str(r#"{"mode":"adaptive"}"#).unwrap();
+        assert_eq!(options.mode, AnalysisMode::Adaptive);
+        assert_eq!(AnalysisOptions::default().mode, AnalysisMode::Uniform);
+    }
+
+    #[test]
+    fn test_export_options_defaults_from_frontend() {
+        // Older frontends do not send the tone mapping or bit depth fields
+        let json = r#"{"format":"png","threshold":null,"max_frames":null,"min_frame_distance":1}"#;
+        let options: ExportOptions = serde_json::from_str(json).unwrap();
+        assert_eq!(options.tone_mapping, ToneMapping::Auto);
+        assert_eq!(options.bit_depth, None);
+    }
+
+    #[test]
+    fn test_adaptive_analysis_on_mock_source() {
+        use crate::source::MockSource;
+
+        // Contrast, and so sharpness, peaks at frame 42, between two coarse samples.
+        // 200 frames give a refinement budget that fits the peak neighborhood.
+        let contrast = (0..200)
+            .map(|n: i32| (250 - (n - 42).abs() * 5).max(0) as u8)
+            .collect();
+        let source = MockSource::new(contrast);
+        let options = AnalysisOptions {
+            mode: AnalysisMode::Adaptive,
+            ..Default::default()
+        };
+
+        let events = Mutex::new(0);
+        let emit = |_: &AnalysisProgress| *events.lock().unwrap() += 1;
+        let result = run_analysis(
+            &source,
+            10,
+            &TimeSegments::default(),
+            &options,
+            &CancelToken::default(),
+            None,
+            &emit,
+        )
+        .unwrap();
+
+        assert!(result
+            .frames
+            .iter()
+            .filter(|f| !f.refined)
+            .map(|f| f.frame_number)
+            .eq((0..200).step_by(10)));
+        assert!(result.refined_frame_count > 0);
+        let best = result
+            .frames
+            .iter()
+            .max_by(|a, b| a.sharpness.partial_cmp(&b.sharpness).unwrap())
+            .unwrap();
+        assert_eq!((best.frame_number, best.refined), (42, true));
+        assert!(*events.lock().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_decode_failures_are_retried_and_excluded() {
+        use crate::source::MockSource;
+
+        // Sharp even frames, blurry odd ones. Frame 3 fails once and decodes
+        // on retry; frame 5 never decodes.
+        let contrast = (0..8).map(|n| if n % 2 == 0 { 200 } else { 40 }).collect();
+        let source = MockSource::new(contrast)
+            .failing(3, 1)
+            .failing(5, usize::MAX);
+        let emit = |_: &AnalysisProgress| {};
+        let result = run_analysis(
+            &source,
+            1,
+            &TimeSegments::default(),
+            &AnalysisOptions::default(),
+            &CancelToken::default(),
+            None,
+            &emit,
+        )
+        .unwrap();
+
+        assert_eq!(result.frames.len(), 8);
+        assert_eq!(result.frames[3].status, FrameStatus::Recovered);
+        assert!(result.frames[3].sharpness > 0.0);
+        assert_eq!(result.frames[5].status, FrameStatus::Failed);
+        assert_eq!(
+            result.frames[5].error.as_deref(),
+            Some("Corrupt packet at frame 5")
+        );
+
+        // The failed frame's placeholder score of 0 is left out of the threshold
+        let decoded: Vec<f64> = result
+            .frames
+            .iter()
+            .filter(|f| f.is_decoded())
+            .map(|f| f.sharpness)
+            .collect();
+        assert_eq!(decoded.len(), 7);
+        assert_eq!(
+            result.suggested_threshold,
+            calculate_auto_threshold(&decoded, None)
+        );
+        assert_eq!(result.suggested_frame_count, 4);
+        assert_eq!(result.decode_failures.failed_frames, 1);
+        assert_eq!(result.decode_failures.recovered_frames, 1);
+        assert_eq!(
+            result.decode_failures.errors,
+            vec!["Corrupt packet at frame 5"]
+        );
+    }
+
+    #[test]
+    fn test_retries_are_capped_on_a_broken_stream() {
+        use crate::source::MockSource;
+
+        // Every frame fails once: the first ones recover on retry, the rest
+        // are not retried at all
+        let frames = MAX_RETRIED_FRAMES + 8;
+        let source = (0..frames).fold(MockSource::new(vec![100; frames]), |source, n| {
+            source.failing(n, 1)
+        });
+        let emit = |_: &AnalysisProgress| {};
+        let result = run_analysis(
+            &source,
+            1,
+            &TimeSegments::default(),
+            &AnalysisOptions::default(),
+            &CancelToken::default(),
+            None,
+            &emit,
+        )
+        .unwrap();
+
+        assert_eq!(result.decode_failures.recovered_frames, MAX_RETRIED_FRAMES);
+        assert_eq!(result.decode_failures.failed_frames, 8);
+        assert!(result.frames[MAX_RETRIED_FRAMES..]
+            .iter()
+            .all(|f| f.status == FrameStatus::Failed));
+    }
+
+    #[test]
+    fn test_segments_limit_sampling() {
+        use crate::segments::TimeRange;
+        use crate::source::MockSource;
+
+        // 20 seconds at 10 fps: keep 0s to 10s except 3s to 5s, both ends
+        // of each segment included
+        let source = MockSource::new(vec![100; 200]);
+        let segments = TimeSegments {
+            include: vec![TimeRange {
+                start: 0.0,
+                end: 10.0,
+            }],
+            exclude: vec![TimeRange {
+                start: 3.0,
+                end: 5.0,
+            }],
+        };
+        let emit = |_: &AnalysisProgress| {};
+        for mode in [
+            AnalysisMode::Uniform,
+            AnalysisMode::UniformTime,
+            AnalysisMode::Keyframes,
+        ] {
+            let options = AnalysisOptions {
+                mode,
+                ..Default::default()
+            };
+            let result = run_analysis(
+                &source,
+                10,
+                &segments,
+                &options,
+                &CancelToken::default(),
+                None,
+                &emit,
+            )
+            .unwrap();
+            let sampled: Vec<usize> = result.frames.iter().map(|f| f.frame_number).collect();
+            assert_eq!(sampled, vec![0, 10, 20, 60, 70, 80, 90, 100]);
+            assert_eq!(result.segments, segments);
+        }
+    }
 }
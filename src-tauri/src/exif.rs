@@ -0,0 +1,586 @@
+//! Minimal EXIF support.
+//!
+//! EXIF data is a small TIFF structure: a byte-order header followed by
+//! image file directories (IFDs) of 12-byte tag entries. We only need a
+//! handful of tags, so this reads and writes them directly instead of
+//! pulling in a full metadata crate.
+
+use crate::location::{CaptureTime, GeoLocation};
+use crate::video::{FrameData, VideoInfo};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+const TAG_GPS_IFD_POINTER: u16 = 0x8825;
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+
+const TAG_GPS_VERSION_ID: u16 = 0x0000;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// Reads values out of a TIFF-structured EXIF blob
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> TiffReader<'a> {
+    /// Validates the TIFF header. Accepts blobs with or without the
+    /// "Exif\0\0" prefix used inside JPEG APP1 segments.
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+        let little_endian = match data.get(0..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let reader = Self {
+            data,
+            little_endian,
+        };
+        if reader.u16_at(2)? != 42 {
+            return None;
+        }
+        Some(reader)
+    }
+
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    /// Finds a tag in the IFD at `ifd_offset` and returns the offset of its entry
+    fn find_entry(&self, ifd_offset: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd_offset)? as usize;
+        (0..count)
+            .map(|i| ifd_offset + 2 + i * 12)
+            .find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+
+    /// Reads a LONG-valued tag, e.g. an IFD pointer
+    fn long(&self, ifd_offset: usize, tag: u16) -> Option<u32> {
+        let entry = self.find_entry(ifd_offset, tag)?;
+        self.u32_at(entry + 8)
+    }
+
+    /// Reads an ASCII tag, trimming the NUL terminator and padding
+    fn ascii(&self, ifd_offset: usize, tag: u16) -> Option<String> {
+        let entry = self.find_entry(ifd_offset, tag)?;
+        if self.u16_at(entry + 2)? != TYPE_ASCII {
+            return None;
+        }
+        let count = self.u32_at(entry + 4)? as usize;
+        // Values up to 4 bytes are stored inline, longer ones behind an offset
+        let start = if count <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        let bytes = self.data.get(start..start + count)?;
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end_matches('\0').trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+/// Capture time of a photo in seconds since the Unix epoch (camera local time,
+/// as EXIF does not record a zone). Uses DateTimeOriginal with its sub-second
+/// field, falling back to the IFD0 DateTime tag.
+pub fn capture_time(exif: &[u8]) -> Option<f64> {
+    let reader = TiffReader::new(exif)?;
+    let ifd0 = reader.first_ifd()?;
+
+    let exif_ifd = reader
+        .long(ifd0, TAG_EXIF_IFD_POINTER)
+        .map(|offset| offset as usize);
+
+    let original = exif_ifd.and_then(|ifd| {
+        let date_time = reader.ascii(ifd, TAG_DATE_TIME_ORIGINAL)?;
+        let sub_sec = reader.ascii(ifd, TAG_SUB_SEC_TIME_ORIGINAL);
+        Some((date_time, sub_sec))
+    });
+
+    let (date_time, sub_sec) = original.or_else(|| {
+        reader
+            .ascii(ifd0, TAG_DATE_TIME)
+            .map(|date_time| (date_time, None))
+    })?;
+
+    let seconds = parse_exif_date_time(&date_time)?;
+    let fraction = sub_sec
+        .and_then(|s| format!("0.{}", s.trim()).parse::<f64>().ok())
+        .unwrap_or(0.0);
+    Some(seconds as f64 + fraction)
+}
+
+/// Parses "YYYY:MM:DD HH:MM:SS" into seconds since the Unix epoch
+fn parse_exif_date_time(value: &str) -> Option<i64> {
+    let (date, time) = value.trim().split_once(' ')?;
+    let mut date_parts = date.split(':').map(|p| p.parse::<i64>().ok());
+    let year = date_parts.next()??;
+    let month = date_parts.next()??;
+    let day = date_parts.next()??;
+
+    let mut time_parts = time.split(':').map(|p| p.parse::<i64>().ok());
+    let hour = time_parts.next()??;
+    let minute = time_parts.next()??;
+    let second = time_parts.next()??;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Formats seconds since the Unix epoch as "YYYY:MM:DD HH:MM:SS"
+fn format_exif_date_time(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    format!(
+        "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01, the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// A tag to write, with its value encoded little-endian
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    fn ascii(tag: u16, text: &str) -> Self {
+        let mut value = text.as_bytes().to_vec();
+        value.push(0);
+        Self {
+            tag,
+            kind: TYPE_ASCII,
+            count: value.len() as u32,
+            value,
+        }
+    }
+
+    fn bytes(tag: u16, kind: u16, bytes: &[u8]) -> Self {
+        Self {
+            tag,
+            kind,
+            count: bytes.len() as u32,
+            value: bytes.to_vec(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Self {
+            tag,
+            kind: TYPE_LONG,
+            count: 1,
+            value: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn rationals(tag: u16, values: &[(u32, u32)]) -> Self {
+        Self {
+            tag,
+            kind: TYPE_RATIONAL,
+            count: values.len() as u32,
+            value: values
+                .iter()
+                .flat_map(|(num, den)| num.to_le_bytes().into_iter().chain(den.to_le_bytes()))
+                .collect(),
+        }
+    }
+
+    /// Bytes taken outside the IFD; values up to 4 bytes are stored inline
+    fn external_size(&self) -> usize {
+        if self.value.len() > 4 {
+            self.value.len().next_multiple_of(2)
+        } else {
+            0
+        }
+    }
+}
+
+/// Size of an IFD including its out-of-line values
+fn ifd_size(entries: &[Entry]) -> usize {
+    2 + entries.len() * 12 + 4 + entries.iter().map(Entry::external_size).sum::<usize>()
+}
+
+/// Appends an IFD with no successor. Offsets are relative to the start of
+/// `out`, which must be the TIFF header.
+fn write_ifd(out: &mut Vec<u8>, entries: &mut [Entry]) {
+    // TIFF requires entries in ascending tag order
+    entries.sort_by_key(|entry| entry.tag);
+    let mut value_offset = out.len() + 2 + entries.len() * 12 + 4;
+    let mut values = Vec::new();
+
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries.iter() {
+        out.extend_from_slice(&entry.tag.to_le_bytes());
+        out.extend_from_slice(&entry.kind.to_le_bytes());
+        out.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.value.len() <= 4 {
+            let mut inline = entry.value.clone();
+            inline.resize(4, 0);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(value_offset as u32).to_le_bytes());
+            values.extend_from_slice(&entry.value);
+            values.resize(values.len() + entry.external_size() - entry.value.len(), 0);
+            value_offset += entry.external_size();
+        }
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&values);
+}
+
+/// Degrees, minutes and seconds (to 1/10000") of an absolute angle
+fn dms(angle: f64) -> [(u32, u32); 3] {
+    let angle = angle.abs();
+    let degrees = angle.floor();
+    let minutes = ((angle - degrees) * 60.0).floor();
+    let seconds = ((angle - degrees) * 60.0 - minutes) * 60.0;
+    [
+        (degrees as u32, 1),
+        (minutes as u32, 1),
+        ((seconds * 10_000.0).round() as u32, 10_000),
+    ]
+}
+
+/// Builds a TIFF-structured EXIF blob with DateTimeOriginal (plus sub-second
+/// and UTC offset fields) and GPS position tags. None if there is nothing to write.
+pub fn build_exif(time: Option<CaptureTime>, location: Option<&GeoLocation>) -> Option<Vec<u8>> {
+    let mut exif_entries = Vec::new();
+    if let Some(time) = time {
+        let whole = time.local_seconds.floor();
+        let millis = (((time.local_seconds - whole) * 1000.0).round() as i64).min(999);
+        exif_entries.push(Entry::bytes(TAG_EXIF_VERSION, TYPE_UNDEFINED, b"0232"));
+        exif_entries.push(Entry::ascii(
+            TAG_DATE_TIME_ORIGINAL,
+            &format_exif_date_time(whole as i64),
+        ));
+        exif_entries.push(Entry::ascii(
+            TAG_SUB_SEC_TIME_ORIGINAL,
+            &format!("{:03}", millis),
+        ));
+        if let Some(offset) = time.utc_offset_minutes {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let offset = offset.abs();
+            exif_entries.push(Entry::ascii(
+                TAG_OFFSET_TIME_ORIGINAL,
+                &format!("{}{:02}:{:02}", sign, offset / 60, offset % 60),
+            ));
+        }
+    }
+
+    let mut gps_entries = Vec::new();
+    if let Some(location) = location {
+        gps_entries.push(Entry::bytes(TAG_GPS_VERSION_ID, TYPE_BYTE, &[2, 3, 0, 0]));
+        gps_entries.push(Entry::ascii(
+            TAG_GPS_LATITUDE_REF,
+            if location.latitude < 0.0 { "S" } else { "N" },
+        ));
+        gps_entries.push(Entry::rationals(TAG_GPS_LATITUDE, &dms(location.latitude)));
+        gps_entries.push(Entry::ascii(
+            TAG_GPS_LONGITUDE_REF,
+            if location.longitude < 0.0 { "W" } else { "E" },
+        ));
+        gps_entries.push(Entry::rationals(
+            TAG_GPS_LONGITUDE,
+            &dms(location.longitude),
+        ));
+        if let Some(altitude) = location.altitude {
+            gps_entries.push(Entry::bytes(
+                TAG_GPS_ALTITUDE_REF,
+                TYPE_BYTE,
+                &[u8::from(altitude < 0.0)],
+            ));
+            gps_entries.push(Entry::rationals(
+                TAG_GPS_ALTITUDE,
+                &[((altitude.abs() * 1000.0).round() as u32, 1000)],
+            ));
+        }
+    }
+
+    if exif_entries.is_empty() && gps_entries.is_empty() {
+        return None;
+    }
+
+    // IFD0 only points at the Exif and GPS IFDs, which follow it in that order
+    let pointer_count =
+        usize::from(!exif_entries.is_empty()) + usize::from(!gps_entries.is_empty());
+    let exif_offset = 8 + 2 + pointer_count * 12 + 4;
+    let gps_offset = exif_offset
+        + if exif_entries.is_empty() {
+            0
+        } else {
+            ifd_size(&exif_entries)
+        };
+    let mut ifd0 = Vec::new();
+    if !exif_entries.is_empty() {
+        ifd0.push(Entry::long(TAG_EXIF_IFD_POINTER, exif_offset as u32));
+    }
+    if !gps_entries.is_empty() {
+        ifd0.push(Entry::long(TAG_GPS_IFD_POINTER, gps_offset as u32));
+    }
+
+    let mut tiff = b"II".to_vec();
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    write_ifd(&mut tiff, &mut ifd0);
+    if !exif_entries.is_empty() {
+        write_ifd(&mut tiff, &mut exif_entries);
+    }
+    if !gps_entries.is_empty() {
+        write_ifd(&mut tiff, &mut gps_entries);
+    }
+    Some(tiff)
+}
+
+/// EXIF for a frame exported from a video: where the drone was according to
+/// its telemetry, or else the recording's location, and the creation time
+/// moved on by the frame's timestamp
+pub fn video_frame_exif(info: &VideoInfo, frame: &FrameData) -> Option<Vec<u8>> {
+    let time = info
+        .creation_time
+        .as_deref()
+        .and_then(CaptureTime::parse)
+        .map(|time| time.plus(frame.timestamp));
+    let flown = frame.telemetry.as_ref().and_then(|t| {
+        Some(GeoLocation {
+            latitude: t.latitude?,
+            longitude: t.longitude?,
+            altitude: t.absolute_altitude,
+        })
+    });
+    build_exif(time, flown.as_ref().or(info.location.as_ref()))
+}
+
+/// Inserts an EXIF APP1 segment into a JPEG file, after the JFIF APP0 segment
+/// if there is one
+pub fn write_jpeg_exif(path: &Path, tiff: &[u8]) -> Result<()> {
+    let jpeg = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        anyhow::bail!("{} is not a JPEG file", path.display());
+    }
+    let segment_length = 2 + 6 + tiff.len();
+    let segment_length =
+        u16::try_from(segment_length).context("EXIF data is too large for a JPEG segment")?;
+
+    let mut insert_at = 2;
+    if let (Some([0xFF, 0xE0]), Some(&[high, low])) = (jpeg.get(2..4), jpeg.get(4..6)) {
+        insert_at += 2 + u16::from_be_bytes([high, low]) as usize;
+    }
+    let insert_at = insert_at.min(jpeg.len());
+
+    let mut output = Vec::with_capacity(jpeg.len() + segment_length as usize + 2);
+    output.extend_from_slice(&jpeg[..insert_at]);
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&segment_length.to_be_bytes());
+    output.extend_from_slice(b"Exif\0\0");
+    output.extend_from_slice(tiff);
+    output.extend_from_slice(&jpeg[insert_at..]);
+
+    // Write next to the original and swap it in, so a failed write never
+    // leaves a truncated JPEG behind
+    let staged = path.with_extension("exif.tmp");
+    let written = fs::write(&staged, output).and_then(|_| fs::rename(&staged, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little-endian TIFF with IFD0 -> Exif IFD -> DateTimeOriginal + SubSecTimeOriginal
+    fn sample_exif() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"II");
+        data.extend_from_slice(&42u16.to_le_bytes());
+        data.extend_from_slice(&8u32.to_le_bytes());
+
+        // IFD0 at 8: one entry pointing at the Exif IFD at 26
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&TAG_EXIF_IFD_POINTER.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&26u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        // Exif IFD at 26: two entries, value area starts at 26 + 2 + 24 + 4 = 56
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&TAG_DATE_TIME_ORIGINAL.to_le_bytes());
+        data.extend_from_slice(&TYPE_ASCII.to_le_bytes());
+        data.extend_from_slice(&20u32.to_le_bytes());
+        data.extend_from_slice(&56u32.to_le_bytes());
+        data.extend_from_slice(&TAG_SUB_SEC_TIME_ORIGINAL.to_le_bytes());
+        data.extend_from_slice(&TYPE_ASCII.to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(b"25\0\0");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(b"2024:06:01 10:00:05\0");
+        data
+    }
+
+    #[test]
+    fn test_capture_time() {
+        let seconds = capture_time(&sample_exif()).unwrap();
+        let expected = days_from_civil(2024, 6, 1) as f64 * 86_400.0 + 36_005.25;
+        assert!((seconds - expected).abs() < 1e-9);
+
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend(sample_exif());
+        assert_eq!(capture_time(&prefixed), Some(seconds));
+
+        assert_eq!(capture_time(b"not exif"), None);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_days(11_017), (2000, 3, 1));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_build_exif() {
+        let time = CaptureTime::parse("2024-06-01T12:00:05+0200")
+            .unwrap()
+            .plus(1.5);
+        let location = GeoLocation {
+            latitude: -33.8568,
+            longitude: 151.2153,
+            altitude: Some(-2.5),
+        };
+        let tiff = build_exif(Some(time), Some(&location)).unwrap();
+
+        // Read back by the same code that orders image sequences
+        let expected = days_from_civil(2024, 6, 1) as f64 * 86_400.0 + 43_206.5;
+        assert_eq!(capture_time(&tiff), Some(expected));
+
+        let reader = TiffReader::new(&tiff).unwrap();
+        let ifd0 = reader.first_ifd().unwrap();
+        let exif_ifd = reader.long(ifd0, TAG_EXIF_IFD_POINTER).unwrap() as usize;
+        assert_eq!(
+            reader.ascii(exif_ifd, TAG_OFFSET_TIME_ORIGINAL).as_deref(),
+            Some("+02:00")
+        );
+        let gps = reader.long(ifd0, TAG_GPS_IFD_POINTER).unwrap() as usize;
+        assert_eq!(
+            reader.ascii(gps, TAG_GPS_LATITUDE_REF).as_deref(),
+            Some("S")
+        );
+        assert_eq!(
+            reader.ascii(gps, TAG_GPS_LONGITUDE_REF).as_deref(),
+            Some("E")
+        );
+        // 33° 51' 24.48" as rationals
+        let latitude = reader
+            .u32_at(reader.find_entry(gps, TAG_GPS_LATITUDE).unwrap() + 8)
+            .unwrap() as usize;
+        let values: Vec<u32> = (0..6)
+            .map(|i| reader.u32_at(latitude + i * 4).unwrap())
+            .collect();
+        assert_eq!(values, vec![33, 1, 51, 1, 244_800, 10_000]);
+
+        assert!(build_exif(None, None).is_none());
+    }
+
+    #[test]
+    fn test_write_jpeg_exif() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("frame.jpg");
+        // SOI, an APP0 segment with 2 bytes of data, EOI
+        fs::write(
+            &path,
+            [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, b'J', b'F', 0xFF, 0xD9],
+        )
+        .unwrap();
+        let tiff = build_exif(CaptureTime::parse("2024-06-01T10:00:00Z"), None).unwrap();
+        write_jpeg_exif(&path, &tiff).unwrap();
+
+        let jpeg = fs::read(&path).unwrap();
+        assert_eq!(&jpeg[8..10], &[0xFF, 0xE1]);
+        assert_eq!(
+            u16::from_be_bytes([jpeg[10], jpeg[11]]) as usize,
+            2 + 6 + tiff.len()
+        );
+        assert_eq!(&jpeg[12..18], b"Exif\0\0");
+        assert!(capture_time(&jpeg[12..jpeg.len() - 2]).is_some());
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xFF, 0xD9]);
+        assert!(!path.with_extension("exif.tmp").exists());
+    }
+}
@@ -0,0 +1,211 @@
+//! Naming of exported frames and the manifest written next to them.
+//!
+//! Export writes `frame_000123.jpg` (or the original file name for image
+//! sequences). A filename template renames the files afterwards using
+//! placeholders, so editors can find shots by timecode:
+//!
+//! - `{name}`: the default name
+//! - `{video}`: file name of the video without its extension
+//! - `{frame}`: frame number, zero-padded to six digits
+//! - `{time}`: timestamp in seconds with millisecond precision
+//! - `{timecode}`: SMPTE timecode with `-` separators, e.g. `01-00-10-12`
+//! - `{sharpness}`: sharpness score rounded to a whole number
+
+use crate::video::FrameData;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of the manifest in the export directory
+pub const MANIFEST_FILE: &str = "manifest.csv";
+
+/// Renders a filename template for one frame, without extension
+pub fn render_filename(
+    template: &str,
+    frame: &FrameData,
+    default_name: &str,
+    video_name: &str,
+) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in filename template {}", template))?;
+        let value = match &rest[open + 1..open + close] {
+            "name" => default_name.to_string(),
+            "video" => video_name.to_string(),
+            "frame" => format!("{:06}", frame.frame_number),
+            "time" => format!("{:.3}", frame.timestamp),
+            "timecode" => frame
+                .timecode
+                .as_deref()
+                .with_context(|| format!("Frame {} has no timecode", frame.frame_number))?
+                .replace([':', ';'], "-"),
+            "sharpness" => format!("{:.0}", frame.sharpness),
+            other => anyhow::bail!("Unknown placeholder {{{}}} in filename template", other),
+        };
+        name.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    name.push_str(rest);
+
+    if name.trim().is_empty() || name.contains(['/', '\\']) {
+        anyhow::bail!(
+            "Filename template {} does not give a valid file name",
+            template
+        );
+    }
+    Ok(name)
+}
+
+/// Checks that a list of rendered names has no duplicates
+pub fn ensure_unique(names: &[String]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            anyhow::bail!("Filename template gives several frames the name {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Renames exported files to `names`, keeping their extensions. Fails
+/// without renaming anything if a new name belongs to a file that was not
+/// part of this export.
+pub fn rename_outputs(paths: &[PathBuf], names: &[String]) -> Result<Vec<PathBuf>> {
+    let targets: Vec<PathBuf> = paths
+        .iter()
+        .zip(names)
+        .map(|(path, name)| match path.extension() {
+            Some(ext) => path.with_file_name(format!("{}.{}", name, ext.to_string_lossy())),
+            None => path.with_file_name(name),
+        })
+        .collect();
+    for target in &targets {
+        if target.exists() && !paths.contains(target) {
+            anyhow::bail!("{} already exists", target.display());
+        }
+    }
+
+    // One file's new name can be another's old name, so move everything
+    // aside first
+    let staged = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let staged = path.with_file_name(format!(".renaming_{}_{}", std::process::id(), i));
+            fs::rename(path, &staged)
+                .with_context(|| format!("Failed to rename {}", path.display()))?;
+            Ok(staged)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (staged, target) in staged.iter().zip(&targets) {
+        fs::rename(staged, target)
+            .with_context(|| format!("Failed to rename to {}", target.display()))?;
+    }
+    Ok(targets)
+}
+
+/// Writes a CSV manifest of exported files and the frames they came from
+pub fn write_manifest(output_dir: &Path, files: &[(PathBuf, &FrameData)]) -> Result<PathBuf> {
+    let mut csv = String::from("file,frame_number,timestamp,timecode,sharpness\n");
+    for (path, frame) in files {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{:.6},{},{:.4}\n",
+            csv_field(&file_name),
+            frame.frame_number,
+            frame.timestamp,
+            frame.timecode.as_deref().unwrap_or(""),
+            frame.sharpness
+        ));
+    }
+
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    fs::write(&manifest_path, csv)
+        .with_context(|| format!("Failed to write {}", manifest_path.display()))?;
+    Ok(manifest_path)
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> FrameData {
+        FrameData {
+            frame_number: 42,
+            timestamp: 1.75,
+            sharpness: 123.6,
+            timecode: Some("01:00:01;12".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_filename() {
+        let render = |template| render_filename(template, &frame(), "frame_000042", "clip");
+        assert_eq!(render("{name}").unwrap(), "frame_000042");
+        assert_eq!(render("{video}_{timecode}").unwrap(), "clip_01-00-01-12");
+        assert_eq!(
+            render("{frame}_{time}_{sharpness}").unwrap(),
+            "000042_1.750_124"
+        );
+        assert!(render("{unknown}").is_err());
+        assert!(render("{frame").is_err());
+        assert!(render("a/{frame}").is_err());
+
+        let untimed = FrameData::default();
+        assert!(render_filename("{timecode}", &untimed, "frame_000000", "clip").is_err());
+        assert!(ensure_unique(&["a".to_string(), "a".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_rename_outputs() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let paths = vec![dir.join("a.jpg"), dir.join("b.jpg")];
+        fs::write(&paths[0], "a").unwrap();
+        fs::write(&paths[1], "b").unwrap();
+
+        // Swapping names must not overwrite either file
+        let renamed = rename_outputs(&paths, &["b".to_string(), "a".to_string()]).unwrap();
+        assert_eq!(renamed, vec![dir.join("b.jpg"), dir.join("a.jpg")]);
+        assert_eq!(fs::read_to_string(dir.join("b.jpg")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("a.jpg")).unwrap(), "b");
+
+        // A file that was there before the export is left alone
+        fs::write(dir.join("c.jpg"), "c").unwrap();
+        assert!(rename_outputs(&paths, &["c".to_string(), "d".to_string()]).is_err());
+        assert_eq!(fs::read_to_string(dir.join("c.jpg")).unwrap(), "c");
+        assert!(paths.iter().all(|path| path.exists()));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let frame = frame();
+        let path = write_manifest(dir, &[(dir.join("shot, take 2.jpg"), &frame)]).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            contents.lines().nth(1).unwrap(),
+            "\"shot, take 2.jpg\",42,1.750000,01:00:01;12,123.6000"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Hardware decode capability probing.
+//!
+//! `ffmpeg -hwaccels` only lists what the build was compiled with, not what
+//! works on this machine. The first decode probes every candidate method
+//! against a real test clip once per run and keeps the ones that decoded
+//! cleanly. Each video is then checked with a short decode of its own stream,
+//! as a working method may still not support that file's codec or profile;
+//! videos the hardware decoder fails on are decoded in software.
+
+use crate::jobs::CancelToken;
+use crate::video::get_ffmpeg_path;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+/// Hardware decode methods, in order of preference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HwAccel {
+    VideoToolbox,
+    Vaapi,
+    Qsv,
+    Vulkan,
+    Cuda,
+}
+
+#[cfg(target_os = "macos")]
+const CANDIDATES: &[HwAccel] = &[HwAccel::VideoToolbox];
+
+#[cfg(not(target_os = "macos"))]
+const CANDIDATES: &[HwAccel] = &[HwAccel::Vaapi, HwAccel::Qsv, HwAccel::Vulkan, HwAccel::Cuda];
+
+impl HwAccel {
+    /// Name used by ffmpeg's `-hwaccel`
+    pub fn name(self) -> &'static str {
+        match self {
+            HwAccel::VideoToolbox => "videotoolbox",
+            HwAccel::Vaapi => "vaapi",
+            HwAccel::Qsv => "qsv",
+            HwAccel::Vulkan => "vulkan",
+            HwAccel::Cuda => "cuda",
+        }
+    }
+
+    fn args(self) -> Vec<String> {
+        vec!["-hwaccel".to_string(), self.name().to_string()]
+    }
+}
+
+/// Result of the one-time capability probe
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HwAccelProbe {
+    /// Methods the ffmpeg build was compiled with
+    pub compiled: Vec<String>,
+    /// Candidates that decoded the test clip cleanly, most preferred first
+    pub working: Vec<HwAccel>,
+    /// Why each compiled-in candidate that did not work was rejected
+    pub failures: Vec<String>,
+}
+
+/// How one video stream is decoded
+#[derive(Debug, Clone, Default)]
+pub struct FileDecode {
+    /// None decodes in software
+    pub method: Option<HwAccel>,
+    /// Hardware methods that failed on this file, with the reason
+    pub fallbacks: Vec<String>,
+}
+
+static PROBE: OnceLock<HwAccelProbe> = OnceLock::new();
+
+static FILES: OnceLock<Mutex<HashMap<(PathBuf, usize), FileDecode>>> = OnceLock::new();
+
+fn files() -> &'static Mutex<HashMap<(PathBuf, usize), FileDecode>> {
+    FILES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The capability probe, run on first use
+pub fn probe() -> &'static HwAccelProbe {
+    PROBE.get_or_init(run_probe)
+}
+
+fn run_probe() -> HwAccelProbe {
+    let mut probe = HwAccelProbe::default();
+    let Ok(ffmpeg_path) = get_ffmpeg_path() else {
+        return probe;
+    };
+
+    if let Ok(output) = Command::new(&ffmpeg_path)
+        .args(["-hide_banner", "-hwaccels"])
+        .output()
+    {
+        probe.compiled = parse_hwaccels(&String::from_utf8_lossy(&output.stdout));
+    }
+
+    let candidates: Vec<HwAccel> = CANDIDATES
+        .iter()
+        .copied()
+        .filter(|accel| probe.compiled.iter().any(|name| name == accel.name()))
+        .collect();
+    if candidates.is_empty() {
+        return probe;
+    }
+
+    let clip = std::env::temp_dir().join(format!("sfe_hwaccel_probe_{}.mp4", std::process::id()));
+    if let Err(e) = write_test_clip(&ffmpeg_path, &clip) {
+        probe
+            .failures
+            .push(format!("Could not create a test clip: {}", e));
+        return probe;
+    }
+
+    for accel in candidates {
+        match test_decode(
+            &ffmpeg_path,
+            accel,
+            &["-i", clip.to_str().unwrap()],
+            &CancelToken::default(),
+        ) {
+            Ok(()) => probe.working.push(accel),
+            Err(e) => probe.failures.push(format!("{}: {}", accel.name(), e)),
+        }
+    }
+
+    let _ = std::fs::remove_file(&clip);
+    probe
+}
+
+/// Method names from `ffmpeg -hwaccels` output
+fn parse_hwaccels(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| !line.starts_with("Hardware acceleration methods"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Encodes a short H.264 clip (MPEG-2 if libx264 is missing) to decode against
+fn write_test_clip(ffmpeg_path: &Path, clip: &Path) -> Result<(), String> {
+    let mut last_error = String::new();
+    for codec in ["libx264", "mpeg2video"] {
+        let output = Command::new(ffmpeg_path)
+            .args([
+                "-hide_banner",
+                "-loglevel",
+                "error",
+                "-f",
+                "lavfi",
+                "-i",
+                "testsrc2=size=320x240:rate=25",
+                "-frames:v",
+                "10",
+                "-c:v",
+                codec,
+                "-pix_fmt",
+                "yuv420p",
+                "-y",
+                clip.to_str().unwrap(),
+            ])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| e.to_string())?;
+        if output.status.success() {
+            return Ok(());
+        }
+        last_error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    }
+    Err(last_error)
+}
+
+/// Decodes `input_args` with the given method. Anything ffmpeg reports at
+/// error level counts as a failure, since a hardware decoder that fails to
+/// initialize makes ffmpeg quietly continue in software.
+fn test_decode(
+    ffmpeg_path: &Path,
+    accel: HwAccel,
+    input_args: &[&str],
+    cancel: &CancelToken,
+) -> Result<(), String> {
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args(["-hide_banner", "-loglevel", "error"])
+        .args(accel.args())
+        .args(input_args)
+        .args(["-f", "null", "-"]);
+    let output = cancel.output(&mut cmd).map_err(|e| e.to_string())?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        let reason = stderr.lines().next().unwrap_or("decode failed").trim();
+        return Err(reason.to_string());
+    }
+    Ok(())
+}
+
+/// How a video stream is decoded, checking the working methods against the
+/// file itself on first use. The check runs as part of the job; cancelling
+/// it leaves the file unchecked.
+pub fn file_decode(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<FileDecode> {
+    let key = (video_path.to_path_buf(), stream_index);
+    if let Some(decode) = files().lock().unwrap().get(&key) {
+        return Ok(decode.clone());
+    }
+
+    let mut decode = FileDecode::default();
+    if let Ok(ffmpeg_path) = get_ffmpeg_path() {
+        let map = format!("0:{}", stream_index);
+        let input_args = [
+            "-i",
+            video_path.to_str().unwrap(),
+            "-map",
+            &map,
+            "-frames:v",
+            "3",
+        ];
+        for &accel in &probe().working {
+            let result = test_decode(&ffmpeg_path, accel, &input_args, cancel);
+            // A killed test says nothing about the decoder
+            cancel.check()?;
+            match result {
+                Ok(()) => {
+                    decode.method = Some(accel);
+                    break;
+                }
+                Err(e) => decode.fallbacks.push(format!("{}: {}", accel.name(), e)),
+            }
+        }
+    }
+
+    files().lock().unwrap().insert(key, decode.clone());
+    Ok(decode)
+}
+
+/// `-hwaccel` arguments for decoding a video stream, empty for software
+pub fn hwaccel_args(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<Vec<String>> {
+    Ok(file_decode(video_path, stream_index, cancel)?
+        .method
+        .map(HwAccel::args)
+        .unwrap_or_default())
+}
+
+/// Switches a video stream to software decoding after its hardware decode failed
+pub fn record_failure(video_path: &Path, stream_index: usize, error: &str) {
+    let mut files = files().lock().unwrap();
+    let decode = files
+        .entry((video_path.to_path_buf(), stream_index))
+        .or_default();
+    if let Some(accel) = decode.method.take() {
+        let reason = error.lines().next().unwrap_or("decode failed").trim();
+        decode
+            .fallbacks
+            .push(format!("{}: {}", accel.name(), reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hwaccels() {
+        let listing = "Hardware acceleration methods:\nvdpau\ncuda\nvaapi\n\n";
+        assert_eq!(parse_hwaccels(listing), vec!["vdpau", "cuda", "vaapi"]);
+        assert!(parse_hwaccels("").is_empty());
+    }
+
+    #[test]
+    fn test_failure_falls_back_to_software() {
+        let path = Path::new("/videos/hwaccel_test.mp4");
+        files().lock().unwrap().insert(
+            (path.to_path_buf(), 0),
+            FileDecode {
+                method: Some(HwAccel::Cuda),
+                fallbacks: Vec::new(),
+            },
+        );
+        let cancel = CancelToken::default();
+        assert_eq!(
+            hwaccel_args(path, 0, &cancel).unwrap(),
+            vec!["-hwaccel", "cuda"]
+        );
+
+        record_failure(path, 0, "Failed setup for format cuda\nmore detail");
+        assert!(hwaccel_args(path, 0, &cancel).unwrap().is_empty());
+        assert_eq!(
+            file_decode(path, 0, &cancel).unwrap().fallbacks,
+            vec!["cuda: Failed setup for format cuda"]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Interlace detection.
+//!
+//! Combing between the two fields of an interlaced frame reads as extreme
+//! sharpness, so interlaced footage has to be deinterlaced before scoring.
+//! Containers often flag field order wrongly or not at all, so the first
+//! frames are run through ffmpeg's `idet` filter, which classifies each one
+//! as top field first, bottom field first or progressive. Results are cached
+//! per stream until the file changes.
+
+use crate::jobs::CancelToken;
+use crate::video::get_ffmpeg_path;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Frames `idet` looks at
+const DETECTION_FRAMES: usize = 120;
+
+/// Classified frames needed before the detection overrides the container flags
+const MIN_DETERMINED: u32 = 10;
+
+/// Frame counts from `idet`'s multi-frame detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterlaceDetection {
+    pub tff: u32,
+    pub bff: u32,
+    pub progressive: u32,
+    pub undetermined: u32,
+}
+
+impl InterlaceDetection {
+    /// "tff", "bff" or "progressive", or None if too few frames could be classified
+    pub fn field_order(&self) -> Option<&'static str> {
+        let interlaced = self.tff + self.bff;
+        if interlaced + self.progressive < MIN_DETERMINED {
+            return None;
+        }
+        Some(if interlaced <= self.progressive {
+            "progressive"
+        } else if self.tff >= self.bff {
+            "tff"
+        } else {
+            "bff"
+        })
+    }
+}
+
+type DetectionCache = Mutex<HashMap<(PathBuf, usize, Option<SystemTime>), InterlaceDetection>>;
+
+static DETECTIONS: OnceLock<DetectionCache> = OnceLock::new();
+
+/// Runs `idet` over the first frames of a video stream. None if ffmpeg
+/// printed no statistics; only cancelling the job is an error.
+pub fn detect_interlacing(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<Option<InterlaceDetection>> {
+    let modified = fs::metadata(video_path).and_then(|m| m.modified()).ok();
+    let key = (video_path.to_path_buf(), stream_index, modified);
+    let cache = DETECTIONS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(detection) = cache.lock().unwrap().get(&key) {
+        return Ok(Some(*detection));
+    }
+
+    // Failures are not cached, so a later analysis tries again
+    let detection = run_idet(video_path, stream_index, cancel)?;
+    if let Some(detection) = detection {
+        cache.lock().unwrap().insert(key, detection);
+    }
+    Ok(detection)
+}
+
+fn run_idet(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<Option<InterlaceDetection>> {
+    let Ok(ffmpeg_path) = get_ffmpeg_path() else {
+        return Ok(None);
+    };
+    // idet reports at info level on stderr
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "info",
+        "-nostats",
+        "-i",
+        &video_path.to_string_lossy(),
+        "-map",
+        &format!("0:{}", stream_index),
+        "-vf",
+        "idet",
+        "-frames:v",
+        &DETECTION_FRAMES.to_string(),
+        "-an",
+        "-f",
+        "null",
+        "-",
+    ]);
+    let output = cancel.output(&mut cmd)?;
+    Ok(parse_idet(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Parses the "Multi frame detection" summary line of `idet`
+fn parse_idet(stderr: &str) -> Option<InterlaceDetection> {
+    let line = stderr
+        .lines()
+        .rev()
+        .find(|line| line.contains("Multi frame detection:"))?;
+    let count = |label: &str| -> Option<u32> {
+        let start = line.find(label)? + label.len();
+        line[start..].split_whitespace().next()?.parse().ok()
+    };
+    Some(InterlaceDetection {
+        tff: count("TFF:")?,
+        bff: count("BFF:")?,
+        progressive: count("Progressive:")?,
+        undetermined: count("Undetermined:")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_idet() {
+        let stderr = "\
+[Parsed_idet_0 @ 0x600] Repeated Fields: Neither:   120 Top:     0 Bottom:     0
+[Parsed_idet_0 @ 0x600] Single frame detection: TFF:    40 BFF:     0 Progressive:    52 Undetermined:    28
+[Parsed_idet_0 @ 0x600] Multi frame detection: TFF:   101 BFF:     0 Progressive:    12 Undetermined:     7
+";
+        let detection = parse_idet(stderr).unwrap();
+        assert_eq!(
+            detection,
+            InterlaceDetection {
+                tff: 101,
+                bff: 0,
+                progressive: 12,
+                undetermined: 7
+            }
+        );
+        assert_eq!(detection.field_order(), Some("tff"));
+        assert!(parse_idet("no statistics").is_none());
+    }
+
+    #[test]
+    fn test_field_order_needs_enough_frames() {
+        let progressive = InterlaceDetection {
+            progressive: 90,
+            bff: 20,
+            ..Default::default()
+        };
+        assert_eq!(progressive.field_order(), Some("progressive"));
+
+        let bff = InterlaceDetection {
+            bff: 60,
+            tff: 5,
+            ..Default::default()
+        };
+        assert_eq!(bff.field_order(), Some("bff"));
+
+        let unclear = InterlaceDetection {
+            undetermined: 100,
+            tff: 3,
+            ..Default::default()
+        };
+        assert_eq!(unclear.field_order(), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! Cancellable analysis and export jobs.
+//!
+//! The frontend tags long-running commands with a job ID. `cancel_job` flips
+//! the job's `CancelToken`, which kills every ffmpeg/ffprobe process spawned
+//! through it and makes parallel workers stop at their next check. Commands
+//! then return `JobError::Cancelled` rather than an error message.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::Duration;
+
+/// How often waits poll a child for exit, so a cancel can kill it in between
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Error value used inside `anyhow` chains to mark a job as cancelled
+#[derive(Debug, thiserror::Error)]
+#[error("Job was cancelled")]
+pub struct Cancelled;
+
+/// Error returned to the frontend by cancellable commands
+#[derive(Debug, thiserror::Error, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum JobError {
+    /// The job was stopped through `cancel_job`; not a failure
+    #[error("cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<anyhow::Error> for JobError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.downcast_ref::<Cancelled>().is_some() {
+            JobError::Cancelled
+        } else {
+            // Alternate form keeps the whole chain, e.g. the io error or
+            // ffmpeg output behind "Failed to read ..."
+            JobError::Failed(format!("{:#}", error))
+        }
+    }
+}
+
+impl From<String> for JobError {
+    fn from(message: String) -> Self {
+        JobError::Failed(message)
+    }
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    processes: Mutex<Vec<Weak<Mutex<Child>>>>,
+}
+
+/// Shared cancellation flag of one job. Cloning gives another handle to the
+/// same job; the default token belongs to no job and is never cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    state: Arc<TokenState>,
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a `Cancelled` error once the job has been cancelled
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+
+    /// Marks the job cancelled and kills its running processes
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+
+        let processes = std::mem::take(&mut *self.state.processes.lock().unwrap());
+        for process in processes.iter().filter_map(Weak::upgrade) {
+            let _ = process.lock().unwrap().kill();
+        }
+    }
+
+    /// Spawns a process that is killed if the job is cancelled
+    pub fn spawn(&self, cmd: &mut Command) -> Result<JobProcess> {
+        self.check()?;
+
+        let child = cmd.spawn().with_context(|| {
+            format!(
+                "Failed to execute {}. Make sure FFmpeg is installed.",
+                cmd.get_program().to_string_lossy()
+            )
+        })?;
+        let child = Arc::new(Mutex::new(child));
+
+        {
+            let mut processes = self.state.processes.lock().unwrap();
+            processes.retain(|p| p.strong_count() > 0);
+            processes.push(Arc::downgrade(&child));
+        }
+
+        // A cancel between the check above and registration would have missed this child
+        if self.is_cancelled() {
+            let _ = child.lock().unwrap().kill();
+        }
+
+        Ok(JobProcess { child })
+    }
+
+    /// Cancellable replacement for `Command::output`
+    pub fn output(&self, cmd: &mut Command) -> Result<Output> {
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let process = self.spawn(cmd)?;
+
+        // Read both pipes on their own threads so neither can fill up and stall the child
+        let stdout = process.take_stdout().map(read_to_end_thread);
+        let stderr = process.take_stderr().map(read_to_end_thread);
+
+        let status = process.wait()?;
+        self.check()?;
+
+        Ok(Output {
+            status,
+            stdout: stdout.and_then(|h| h.join().ok()).unwrap_or_default(),
+            stderr: stderr.and_then(|h| h.join().ok()).unwrap_or_default(),
+        })
+    }
+}
+
+fn read_to_end_thread<R: Read + Send + 'static>(mut reader: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = reader.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+/// A child process owned by a job
+pub struct JobProcess {
+    child: Arc<Mutex<Child>>,
+}
+
+impl JobProcess {
+    pub fn take_stdout(&self) -> Option<ChildStdout> {
+        self.child.lock().unwrap().stdout.take()
+    }
+
+    pub fn take_stderr(&self) -> Option<ChildStderr> {
+        self.child.lock().unwrap().stderr.take()
+    }
+
+    pub fn kill(&self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    /// Waits for the process to exit without holding its lock, so a cancel
+    /// from another thread can still kill it
+    pub fn wait(&self) -> Result<ExitStatus> {
+        loop {
+            if let Some(status) = self
+                .child
+                .lock()
+                .unwrap()
+                .try_wait()
+                .context("Failed to wait for ffmpeg")?
+            {
+                return Ok(status);
+            }
+            std::thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Tokens of running jobs by ID
+static JOBS: OnceLock<Mutex<HashMap<String, CancelToken>>> = OnceLock::new();
+
+fn jobs() -> &'static Mutex<HashMap<String, CancelToken>> {
+    JOBS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Registration of a running job; unregisters the job when dropped
+pub struct JobGuard {
+    id: Option<String>,
+    token: CancelToken,
+}
+
+impl JobGuard {
+    pub fn token(&self) -> &CancelToken {
+        &self.token
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            jobs().lock().unwrap().remove(id);
+        }
+    }
+}
+
+/// Registers a job under the frontend's ID. Jobs without an ID still get a
+/// token so the code paths are the same, but nothing can cancel them.
+pub fn start_job(job_id: Option<&str>) -> JobGuard {
+    let token = CancelToken::default();
+    if let Some(id) = job_id {
+        jobs().lock().unwrap().insert(id.to_string(), token.clone());
+    }
+    JobGuard {
+        id: job_id.map(String::from),
+        token,
+    }
+}
+
+/// Cancels a running job. Returns false if no job has that ID (it may have finished).
+pub fn cancel_job(job_id: &str) -> bool {
+    match jobs().lock().unwrap().get(job_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_registered_job() {
+        let guard = start_job(Some("test-job"));
+        assert!(guard.token().check().is_ok());
+
+        assert!(cancel_job("test-job"));
+        let error = JobError::from(guard.token().check().unwrap_err().context("while decoding"));
+        assert!(matches!(error, JobError::Cancelled));
+
+        drop(guard);
+        assert!(!cancel_job("test-job"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cancel_kills_running_process() {
+        let token = CancelToken::default();
+        let canceller = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let result = token.output(Command::new("sleep").arg("10"));
+        assert!(result.unwrap_err().downcast_ref::<Cancelled>().is_some());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_job_error_serialization() {
+        let cancelled = serde_json::to_string(&JobError::Cancelled).unwrap();
+        assert_eq!(cancelled, r#"{"kind":"cancelled"}"#);
+        let failed = serde_json::to_string(&JobError::Failed("boom".to_string())).unwrap();
+        assert_eq!(failed, r#"{"kind":"failed","message":"boom"}"#);
+
+        let chained =
+            JobError::from(anyhow::anyhow!("disk full").context("Failed to write frame.png"));
+        assert_eq!(chained.to_string(), "Failed to write frame.png: disk full");
+    }
+}
@@ -1,12 +1,29 @@
+mod cache;
+mod checkpoint;
 mod commands;
-mod sharpness;
-mod video;
+mod exif;
+mod export;
 mod gpu_sharpness;
+mod hwaccel;
+mod interlace;
+mod jobs;
+mod location;
+mod segments;
+mod sequence;
 mod settings;
+mod sharpness;
+mod source;
+mod telemetry;
+mod timecode;
+mod toolchain;
+mod video;
 
 use commands::{
-    analyze_video, calculate_threshold_for_count, export_frames, get_frame_preview, get_video_metadata,
-    get_settings, save_settings, detect_ffmpeg, get_ffmpeg_install_instructions, validate_ffmpeg_path,
+    analyze_video, calculate_threshold_for_count, cancel_job, detect_ffmpeg, export_frames,
+    get_cached_analysis, get_ffmpeg_install_instructions, get_frame_preview, get_hw_accel_status,
+    get_settings, get_video_metadata, get_video_streams, list_cached_analyses,
+    purge_analysis_cache, save_settings, select_frames_by_travel, set_analysis_cache_limit,
+    time_input_to_seconds, validate_ffmpeg_path, validate_ffmpeg_toolchain,
 };
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -18,13 +35,23 @@ pub fn run() {
             analyze_video,
             export_frames,
             get_video_metadata,
+            get_video_streams,
+            cancel_job,
+            list_cached_analyses,
+            get_cached_analysis,
+            purge_analysis_cache,
+            set_analysis_cache_limit,
             calculate_threshold_for_count,
+            select_frames_by_travel,
             get_frame_preview,
             get_settings,
             save_settings,
             detect_ffmpeg,
             get_ffmpeg_install_instructions,
             validate_ffmpeg_path,
+            get_hw_accel_status,
+            validate_ffmpeg_toolchain,
+            time_input_to_seconds,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,199 @@
+//! Where and when a recording was made, from container metadata.
+//!
+//! Phones store the recording location as an ISO 6709 string such as
+//! `+37.3318-122.0312+010.000/`: latitude, longitude and an optional
+//! altitude in meters. iPhones use the `com.apple.quicktime.location.ISO6709`
+//! key, Android the `location` tag. Creation times are ISO 8601 and come in
+//! UTC (`creation_time`) or camera local time with an offset
+//! (`com.apple.quicktime.creationdate`).
+
+use crate::exif::days_from_civil;
+use serde::{Deserialize, Serialize};
+
+/// A position in WGS 84 degrees, with altitude in meters above sea level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+}
+
+/// Parses an ISO 6709 point. Degrees may be given as decimal degrees,
+/// degrees and decimal minutes or degrees, minutes and decimal seconds.
+pub fn parse_iso6709(value: &str) -> Option<GeoLocation> {
+    // Drop the terminator and an optional coordinate reference system
+    let value = value.trim().split(['/', 'C']).next()?;
+
+    let mut components = Vec::new();
+    for (i, c) in value.char_indices() {
+        if c == '+' || c == '-' {
+            components.push(i);
+        }
+    }
+    components.push(value.len());
+    let parts: Vec<&str> = components.windows(2).map(|w| &value[w[0]..w[1]]).collect();
+
+    let (latitude, longitude, altitude) = match parts.as_slice() {
+        [lat, lon] => (*lat, *lon, None),
+        [lat, lon, alt] => (*lat, *lon, Some(alt.parse::<f64>().ok()?)),
+        _ => return None,
+    };
+    let latitude = parse_angle(latitude, 2)?;
+    let longitude = parse_angle(longitude, 3)?;
+    if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+        return None;
+    }
+    Some(GeoLocation {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// Parses a signed ISO 6709 angle whose degrees have `degree_digits` digits.
+/// The length of the integer part tells whether minutes and seconds follow.
+fn parse_angle(value: &str, degree_digits: usize) -> Option<f64> {
+    let sign = match value.get(..1)? {
+        "+" => 1.0,
+        "-" => -1.0,
+        _ => return None,
+    };
+    let digits = &value[1..];
+    let integer_len = digits.find('.').unwrap_or(digits.len());
+    if !digits[..integer_len].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let degrees = |s: &str| s.parse::<f64>().ok();
+    let angle = if integer_len == degree_digits {
+        degrees(digits)?
+    } else if integer_len == degree_digits + 2 {
+        degrees(&digits[..degree_digits])? + degrees(&digits[degree_digits..])? / 60.0
+    } else if integer_len == degree_digits + 4 {
+        degrees(&digits[..degree_digits])?
+            + degrees(&digits[degree_digits..degree_digits + 2])? / 60.0
+            + degrees(&digits[degree_digits + 2..])? / 3600.0
+    } else {
+        return None;
+    };
+    Some(sign * angle)
+}
+
+/// A moment as shown on the camera's clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureTime {
+    /// Local wall-clock time in seconds since 1970-01-01 00:00:00
+    pub local_seconds: f64,
+    /// Offset of the local time from UTC, when known
+    pub utc_offset_minutes: Option<i32>,
+}
+
+impl CaptureTime {
+    /// Parses ISO 8601 date and time, e.g. `2024-06-01T10:00:00.000000Z`
+    /// or `2024-06-01T12:00:00+0200`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let date = value.get(..10)?;
+        let rest = value.get(10..)?.strip_prefix(['T', ' '])?;
+
+        let mut date_parts = date.split('-').map(|p| p.parse::<i64>().ok());
+        let year = date_parts.next()??;
+        let month = date_parts.next()??;
+        let day = date_parts.next()??;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+
+        let zone_start = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+        let (time, zone) = rest.split_at(zone_start);
+        let mut time_parts = time.split(':');
+        let hour = time_parts.next()?.parse::<i64>().ok()?;
+        let minute = time_parts.next()?.parse::<i64>().ok()?;
+        let second = time_parts.next().unwrap_or("0").parse::<f64>().ok()?;
+
+        let utc_offset_minutes = match zone {
+            "" => None,
+            "Z" | "z" => Some(0),
+            _ => {
+                let sign = if zone.starts_with('-') { -1 } else { 1 };
+                let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+                let hours = digits.get(..2)?.parse::<i32>().ok()?;
+                let minutes = digits
+                    .get(2..)
+                    .filter(|m| !m.is_empty())
+                    .map_or(Some(0), |m| m.parse::<i32>().ok())?;
+                Some(sign * (hours * 60 + minutes))
+            }
+        };
+
+        let whole = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60;
+        Some(Self {
+            local_seconds: whole as f64 + second,
+            utc_offset_minutes,
+        })
+    }
+
+    /// The same clock `seconds` later
+    pub fn plus(self, seconds: f64) -> Self {
+        Self {
+            local_seconds: self.local_seconds + seconds,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_iso6709() {
+        let iphone = parse_iso6709("+37.3318-122.0312+010.000/").unwrap();
+        assert_eq!(iphone.latitude, 37.3318);
+        assert_eq!(iphone.longitude, -122.0312);
+        assert_eq!(iphone.altitude, Some(10.0));
+
+        let android = parse_iso6709("-33.8568+151.2153/").unwrap();
+        assert_eq!(
+            (android.latitude, android.longitude, android.altitude),
+            (-33.8568, 151.2153, None)
+        );
+
+        // Degrees and minutes, degrees, minutes and seconds
+        let dm = parse_iso6709("+4830.0-00130.0/").unwrap();
+        assert_eq!((dm.latitude, dm.longitude), (48.5, -1.5));
+        let dms = parse_iso6709("+483000-0013000+2.5CRSWGS_84/").unwrap();
+        assert_eq!(
+            (dms.latitude, dms.longitude, dms.altitude),
+            (48.5, -1.5, Some(2.5))
+        );
+
+        assert!(parse_iso6709("+95.0+010.0/").is_none());
+        assert!(parse_iso6709("somewhere").is_none());
+    }
+
+    #[test]
+    fn test_parse_capture_time() {
+        let utc = CaptureTime::parse("2024-06-01T10:00:05.250000Z").unwrap();
+        let day = days_from_civil(2024, 6, 1) as f64 * 86_400.0;
+        assert_eq!(utc.local_seconds, day + 36_005.25);
+        assert_eq!(utc.utc_offset_minutes, Some(0));
+
+        let local = CaptureTime::parse("2024-06-01T12:00:05+0200").unwrap();
+        assert_eq!(local.local_seconds, day + 43_205.0);
+        assert_eq!(local.utc_offset_minutes, Some(120));
+        assert_eq!(
+            CaptureTime::parse("2024-06-01T12:00:05-03:30")
+                .unwrap()
+                .utc_offset_minutes,
+            Some(-210)
+        );
+        assert_eq!(
+            CaptureTime::parse("2024-06-01 12:00:05")
+                .unwrap()
+                .utc_offset_minutes,
+            None
+        );
+        assert!(CaptureTime::parse("June 1st").is_none());
+    }
+}
@@ -0,0 +1,252 @@
+//! Time segments to include in or exclude from an analysis.
+//!
+//! Captures often have unusable stretches, such as walking between rooms or
+//! the lens cap left on. An analysis keeps a frame when it falls within any
+//! include segment (or there are none) and within no exclude segment. Like
+//! `start_time`/`end_time`, segments come in as seconds from the start of the
+//! video or as timecodes and are converted to seconds before use.
+
+use crate::timecode::TimeInput;
+use crate::video::VideoInfo;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// A stretch of the timeline from `start` to `end`, both included, like the
+/// `start_time`/`end_time` range
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange<T = f64> {
+    pub start: T,
+    pub end: T,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: f64) -> bool {
+        time >= self.start && time <= self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeSegments<T = f64> {
+    /// Stretches to analyze; empty means the whole video
+    pub include: Vec<TimeRange<T>>,
+    /// Stretches to skip, even inside an include segment
+    pub exclude: Vec<TimeRange<T>>,
+}
+
+impl<T> Default for TimeSegments<T> {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl TimeSegments<TimeInput> {
+    /// Converts segments given in seconds or timecode to seconds
+    pub fn seconds(&self, info: &VideoInfo) -> Result<TimeSegments> {
+        let convert = |ranges: &[TimeRange<TimeInput>]| {
+            ranges
+                .iter()
+                .map(|range| {
+                    Ok(TimeRange {
+                        start: range.start.seconds(info)?,
+                        end: range.end.seconds(info)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(TimeSegments {
+            include: convert(&self.include)?,
+            exclude: convert(&self.exclude)?,
+        })
+    }
+}
+
+impl TimeSegments {
+    /// Checks the ranges and combines them with an optional outer time range,
+    /// which include segments are clipped to
+    pub fn resolve(&self, start_time: Option<f64>, end_time: Option<f64>) -> Result<Self> {
+        for range in self.include.iter().chain(&self.exclude) {
+            if !(range.start >= 0.0 && range.end > range.start) {
+                anyhow::bail!(
+                    "Invalid time segment {:.3}s to {:.3}s",
+                    range.start,
+                    range.end
+                );
+            }
+        }
+        if start_time.is_none() && end_time.is_none() {
+            return Ok(self.clone());
+        }
+
+        let window = TimeRange {
+            start: start_time.unwrap_or(0.0),
+            end: end_time.unwrap_or(f64::INFINITY),
+        };
+        let include = if self.include.is_empty() {
+            vec![window]
+        } else {
+            let clipped: Vec<TimeRange> = self
+                .include
+                .iter()
+                .map(|range| TimeRange {
+                    start: range.start.max(window.start),
+                    end: range.end.min(window.end),
+                })
+                .filter(|range| range.end > range.start)
+                .collect();
+            if clipped.is_empty() {
+                anyhow::bail!("No include segment overlaps the selected time range");
+            }
+            clipped
+        };
+
+        Ok(Self {
+            include,
+            exclude: self.exclude.clone(),
+        })
+    }
+
+    pub fn contains(&self, time: f64) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|range| range.contains(time)))
+            && !self.exclude.iter().any(|range| range.contains(time))
+    }
+
+    /// Earliest and latest time any include segment covers, for narrowing
+    /// sampling before frames are filtered one by one
+    pub fn bounds(&self) -> (Option<f64>, Option<f64>) {
+        if self.include.is_empty() {
+            return (None, None);
+        }
+        let start = self
+            .include
+            .iter()
+            .map(|r| r.start)
+            .fold(f64::INFINITY, f64::min);
+        let end = self
+            .include
+            .iter()
+            .map(|r| r.end)
+            .fold(f64::NEG_INFINITY, f64::max);
+        (Some(start), end.is_finite().then_some(end))
+    }
+
+    /// Human-readable form for checkpoint and cache keys
+    pub fn describe(&self) -> String {
+        let list = |ranges: &[TimeRange]| {
+            ranges
+                .iter()
+                .map(|r| format!("{}-{}", r.start, r.end))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            "include=[{}]|exclude=[{}]",
+            list(&self.include),
+            list(&self.exclude)
+        )
+    }
+}
+
+/// Splits `candidates` into runs of consecutive entries that `keep` accepts,
+/// dropping the rest. A run ends wherever a candidate was dropped.
+pub fn kept_runs(candidates: &[usize], keep: impl Fn(usize) -> bool) -> Vec<Vec<usize>> {
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut current = Vec::new();
+    for &candidate in candidates {
+        if keep(candidate) {
+            current.push(candidate);
+        } else if !current.is_empty() {
+            runs.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        runs.push(current);
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: f64, end: f64) -> TimeRange {
+        TimeRange { start, end }
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let segments = TimeSegments {
+            include: vec![range(10.0, 20.0), range(40.0, 50.0)],
+            exclude: vec![range(15.0, 16.0)],
+        };
+        assert!(segments.contains(10.0));
+        assert!(segments.contains(20.0));
+        assert!(!segments.contains(20.1));
+        assert!(!segments.contains(15.5));
+        assert!(!segments.contains(16.0));
+        assert!(segments.contains(16.1));
+        assert!(!segments.contains(30.0));
+        assert!(segments.contains(45.0));
+        assert_eq!(segments.bounds(), (Some(10.0), Some(50.0)));
+
+        let excluded_only = TimeSegments {
+            include: Vec::new(),
+            exclude: vec![range(5.0, 6.0)],
+        };
+        assert!(excluded_only.contains(0.0) && !excluded_only.contains(5.0));
+        assert_eq!(excluded_only.bounds(), (None, None));
+    }
+
+    #[test]
+    fn test_resolve_with_time_range() {
+        let segments = TimeSegments {
+            include: vec![range(10.0, 20.0), range(40.0, 50.0)],
+            exclude: Vec::new(),
+        };
+        let resolved = segments.resolve(Some(15.0), Some(45.0)).unwrap();
+        assert_eq!(resolved.include, vec![range(15.0, 20.0), range(40.0, 45.0)]);
+
+        assert!(segments.resolve(Some(25.0), Some(30.0)).is_err());
+        assert_eq!(
+            TimeSegments::default()
+                .resolve(Some(1.0), None)
+                .unwrap()
+                .bounds(),
+            (Some(1.0), None)
+        );
+
+        let inverted = TimeSegments {
+            include: Vec::new(),
+            exclude: vec![range(3.0, 2.0)],
+        };
+        assert!(inverted.resolve(None, None).is_err());
+    }
+
+    #[test]
+    fn test_timecode_segments() {
+        let info = VideoInfo {
+            fps: 25.0,
+            timecode: Some("10:00:00:00".to_string()),
+            ..Default::default()
+        };
+        let json = r#"{"include": [{"start": "10:00:01:00", "end": 4.5}], "exclude": [{"start": 2, "end": "3"}]}"#;
+        let segments: TimeSegments<TimeInput> = serde_json::from_str(json).unwrap();
+        let resolved = segments.seconds(&info).unwrap();
+        assert_eq!(resolved.include, vec![range(1.0, 4.5)]);
+        assert_eq!(resolved.exclude, vec![range(2.0, 3.0)]);
+
+        let before_start: TimeSegments<TimeInput> =
+            serde_json::from_str(r#"{"exclude": [{"start": "09:00:00:00", "end": 1}]}"#).unwrap();
+        assert!(before_start.seconds(&info).is_err());
+    }
+
+    #[test]
+    fn test_kept_runs() {
+        let runs = kept_runs(&[0, 10, 20, 30, 40, 50], |n| n != 20 && n != 30);
+        assert_eq!(runs, vec![vec![0, 10], vec![40, 50]]);
+        assert!(kept_runs(&[1, 2], |_| false).is_empty());
+    }
+}
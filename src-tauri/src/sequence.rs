@@ -0,0 +1,381 @@
+//! Folders of still images (burst photos, timelapses) as an analysis source.
+//!
+//! Each image becomes one frame: its frame number is its position in the
+//! ordered listing and its timestamp is seconds since the first image.
+
+use crate::exif;
+use crate::jobs::CancelToken;
+use crate::video::VideoInfo;
+use anyhow::{Context, Result};
+use image::{ImageDecoder, ImageReader};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File extensions picked up from a sequence folder
+const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff"];
+
+/// How images in a folder are ordered into a sequence
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceOrder {
+    /// Natural filename order, so IMG_2 sorts before IMG_10
+    #[default]
+    Filename,
+    /// EXIF DateTimeOriginal (with sub-seconds), for mixed cameras or renamed files
+    ExifTime,
+}
+
+/// One image of a sequence
+#[derive(Debug, Clone)]
+pub struct SequenceImage {
+    pub path: PathBuf,
+    /// Seconds since the first image (capture time, or one second per image)
+    pub timestamp: f64,
+}
+
+/// True if the path is a folder that should be treated as an image sequence
+pub fn is_image_sequence(path: &Path) -> bool {
+    path.is_dir()
+}
+
+fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|s| ext.eq_ignore_ascii_case(s))
+        })
+        .unwrap_or(false)
+}
+
+/// Lists the images of a sequence folder in the requested order
+pub fn list_sequence_images(dir: &Path, order: SequenceOrder) -> Result<Vec<SequenceImage>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read image folder {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_supported_image(p))
+        .collect();
+
+    if paths.is_empty() {
+        anyhow::bail!("No JPG, PNG or TIFF images found in {}", dir.display());
+    }
+
+    paths.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+
+    if order == SequenceOrder::ExifTime {
+        let times: Vec<Option<f64>> = paths.par_iter().map(|p| read_capture_time(p)).collect();
+
+        if times.iter().all(|t| t.is_some()) {
+            let mut timed: Vec<(PathBuf, f64)> =
+                paths.into_iter().zip(times.into_iter().flatten()).collect();
+            // Stable sort keeps filename order for images taken in the same instant
+            timed.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+
+            let first = timed[0].1;
+            return Ok(timed
+                .into_iter()
+                .map(|(path, time)| SequenceImage {
+                    path,
+                    timestamp: time - first,
+                })
+                .collect());
+        }
+
+        eprintln!("Some images have no EXIF capture time, falling back to filename order");
+    }
+
+    Ok(paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| SequenceImage {
+            path,
+            timestamp: i as f64,
+        })
+        .collect())
+}
+
+/// Builds a `VideoInfo` describing a sequence, so the rest of the app can
+/// treat it like a clip with one frame per image
+pub fn get_sequence_info(images: &[SequenceImage]) -> Result<VideoInfo> {
+    let first = images.first().context("Image sequence is empty")?;
+    let (width, height) = image::image_dimensions(&first.path)
+        .with_context(|| format!("Failed to read {}", first.path.display()))?;
+
+    let duration = images.last().map(|img| img.timestamp).unwrap_or(0.0);
+    let fps = if images.len() > 1 && duration > 0.0 {
+        (images.len() - 1) as f64 / duration
+    } else {
+        1.0
+    };
+
+    let extension = first
+        .path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+
+    Ok(VideoInfo {
+        duration,
+        fps,
+        avg_fps: fps,
+        width,
+        height,
+        total_frames: images.len() as u32,
+        sample_aspect_ratio: 1.0,
+        display_aspect_ratio: width as f64 / height.max(1) as f64,
+        display_width: width,
+        display_height: height,
+        codec_name: extension,
+        container_format: Some("image_sequence".to_string()),
+        ..Default::default()
+    })
+}
+
+/// Opens an image upright, applying its EXIF orientation
+pub fn open_image(path: &Path) -> Result<image::DynamicImage> {
+    let mut decoder = ImageReader::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .with_guessed_format()?
+        .into_decoder()
+        .with_context(|| format!("Unsupported image {}", path.display()))?;
+    let orientation = decoder.orientation()?;
+    let mut img = image::DynamicImage::from_decoder(decoder)
+        .with_context(|| format!("Failed to decode {}", path.display()))?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// Shrinks an image so its long edge is at most `max_long_edge` pixels
+pub fn downscale_to_long_edge(
+    img: image::DynamicImage,
+    max_long_edge: Option<u32>,
+) -> image::DynamicImage {
+    match max_long_edge {
+        Some(target) if target > 0 && img.width().max(img.height()) > target => {
+            img.resize(target, target, image::imageops::FilterType::Triangle)
+        }
+        _ => img,
+    }
+}
+
+/// Capture time from the image's EXIF block, if it has one
+fn read_capture_time(path: &Path) -> Option<f64> {
+    let mut decoder = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let exif = decoder.exif_metadata().ok()??;
+    exif::capture_time(&exif)
+}
+
+/// Exports selected sequence images. Files already in the requested format
+/// are copied byte for byte; others are decoded and re-encoded. If the job is
+/// cancelled, images already written are removed again.
+pub fn export_sequence_images(
+    images: &[PathBuf],
+    output_dir: &Path,
+    format: &str, // "jpg", "png" or "tiff"
+    high_bit_depth: bool,
+    cancel: &CancelToken,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output_dir)?;
+
+    let names = output_names(images, format)?;
+    let results: Vec<Result<PathBuf>> = images
+        .par_iter()
+        .zip(&names)
+        .map(|(source, name)| {
+            cancel.check()?;
+
+            let output_path = output_dir.join(name);
+            if is_same_format(source, format) {
+                fs::copy(source, &output_path)
+                    .with_context(|| format!("Failed to copy {}", source.display()))?;
+                return Ok(output_path);
+            }
+
+            let img = open_image(source)?;
+            let img = if high_bit_depth && format != "jpg" {
+                image::DynamicImage::ImageRgb16(img.to_rgb16())
+            } else {
+                image::DynamicImage::ImageRgb8(img.to_rgb8())
+            };
+            img.save(&output_path)
+                .with_context(|| format!("Failed to write {}", output_path.display()))?;
+            Ok(output_path)
+        })
+        .collect();
+
+    if cancel.is_cancelled() {
+        for path in results.iter().flatten() {
+            let _ = fs::remove_file(path);
+        }
+        cancel.check()?;
+    }
+
+    results.into_iter().collect()
+}
+
+fn is_same_format(source: &Path, format: &str) -> bool {
+    source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| normalize_extension(ext) == normalize_extension(format))
+        .unwrap_or(false)
+}
+
+/// Output file names for exported images: the original name for copies,
+/// the stem with the new extension for conversions. Sources that would end
+/// up with the same name (`a.png` and `a.jpg`, or the same name in two
+/// folders) get `_2`, `_3`... appended so none overwrites another.
+fn output_names(images: &[PathBuf], format: &str) -> Result<Vec<String>> {
+    let mut taken = std::collections::HashSet::new();
+    images
+        .iter()
+        .map(|source| {
+            let stem = source
+                .file_stem()
+                .context("Image has no file name")?
+                .to_string_lossy()
+                .to_string();
+            let extension = match source.extension() {
+                Some(ext) if is_same_format(source, format) => ext.to_string_lossy().to_string(),
+                _ => format.to_string(),
+            };
+
+            let mut name = format!("{}.{}", stem, extension);
+            let mut suffix = 1;
+            // Case-insensitive file systems treat A.jpg and a.jpg as one file
+            while !taken.insert(name.to_lowercase()) {
+                suffix += 1;
+                name = format!("{}_{}.{}", stem, suffix, extension);
+            }
+            Ok(name)
+        })
+        .collect()
+}
+
+fn normalize_extension(ext: &str) -> String {
+    match ext.to_ascii_lowercase().as_str() {
+        "jpeg" => "jpg".to_string(),
+        "tif" => "tiff".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Compares file names treating runs of digits as numbers
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(c) = a_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_a.push(c);
+                    a_chars.next();
+                }
+                let mut num_b = String::new();
+                while let Some(c) = b_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_b.push(c);
+                    b_chars.next();
+                }
+
+                // Compare by magnitude without overflowing on long digit runs
+                let trimmed_a = num_a.trim_start_matches('0');
+                let trimmed_b = num_b.trim_start_matches('0');
+                let ordering = trimmed_a
+                    .len()
+                    .cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b))
+                    .then_with(|| num_a.len().cmp(&num_b.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ordering = ca.to_ascii_lowercase().cmp(&cb.to_ascii_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_filename_order() {
+        let mut names = vec!["IMG_10.jpg", "img_2.JPG", "IMG_1.jpg", "IMG_002.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["IMG_1.jpg", "img_2.JPG", "IMG_002.jpg", "IMG_10.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_sequence_listing_and_export() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        for name in ["shot_10.png", "shot_9.png", "notes.txt"] {
+            let path = dir.join(name);
+            if name.ends_with(".png") {
+                image::RgbImage::new(8, 6).save(&path).unwrap();
+            } else {
+                fs::write(&path, "not an image").unwrap();
+            }
+        }
+
+        let images = list_sequence_images(dir, SequenceOrder::Filename).unwrap();
+        assert_eq!(images.len(), 2);
+        assert!(images[0].path.ends_with("shot_9.png"));
+
+        let info = get_sequence_info(&images).unwrap();
+        assert_eq!((info.width, info.height, info.total_frames), (8, 6, 2));
+
+        let out = dir.join("export");
+        let paths: Vec<PathBuf> = images.iter().map(|img| img.path.clone()).collect();
+        let exported =
+            export_sequence_images(&paths, &out, "jpg", false, &CancelToken::default()).unwrap();
+        assert!(exported
+            .iter()
+            .all(|p| p.exists() && p.extension().unwrap() == "jpg"));
+
+        // Sources sharing a stem do not overwrite each other
+        let names = output_names(
+            &[
+                PathBuf::from("a/x.png"),
+                PathBuf::from("b/x.png"),
+                PathBuf::from("x.JPG"),
+            ],
+            "png",
+        )
+        .unwrap();
+        assert_eq!(names, vec!["x.png", "x_2.png", "x_3.png"]);
+        let names = output_names(&[PathBuf::from("x.png"), PathBuf::from("X.jpg")], "jpg").unwrap();
+        assert_eq!(names, vec!["x.jpg", "X_2.jpg"]);
+    }
+}
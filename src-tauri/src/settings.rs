@@ -8,6 +8,13 @@ pub struct AppSettings {
     pub ffmpeg_path: Option<String>,
     pub ffprobe_path: Option<String>,
     pub first_run: bool,
+    /// Size cap of the analysis cache in megabytes
+    #[serde(default = "default_analysis_cache_limit_mb")]
+    pub analysis_cache_limit_mb: u64,
+}
+
+fn default_analysis_cache_limit_mb() -> u64 {
+    1024
 }
 
 impl Default for AppSettings {
@@ -16,6 +23,7 @@ impl Default for AppSettings {
             ffmpeg_path: None,
             ffprobe_path: None,
             first_run: true,
+            analysis_cache_limit_mb: default_analysis_cache_limit_mb(),
         }
     }
 }
@@ -23,8 +31,7 @@ impl Default for AppSettings {
 impl AppSettings {
     /// Get the settings file path
     fn settings_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir()
-            .context("Failed to get config directory")?;
+        let config_dir = dirs::config_dir().context("Failed to get config directory")?;
         let app_dir = config_dir.join("sharp-frame-extractor");
         fs::create_dir_all(&app_dir)?;
         Ok(app_dir.join("settings.json"))
@@ -69,9 +76,9 @@ pub fn detect_ffmpeg_paths() -> (Option<PathBuf>, Option<PathBuf>) {
     #[cfg(target_os = "macos")]
     {
         let homebrew_paths = vec![
-            "/opt/homebrew/bin/ffmpeg",      // Apple Silicon Homebrew
-            "/usr/local/bin/ffmpeg",          // Intel Homebrew
-            "/opt/local/bin/ffmpeg",          // MacPorts
+            "/opt/homebrew/bin/ffmpeg", // Apple Silicon Homebrew
+            "/usr/local/bin/ffmpeg",    // Intel Homebrew
+            "/opt/local/bin/ffmpeg",    // MacPorts
         ];
 
         for path in homebrew_paths {
@@ -224,4 +231,11 @@ mod tests {
         let parsed: AppSettings = serde_json::from_str(&json).unwrap();
         assert_eq!(settings.first_run, parsed.first_run);
     }
+
+    #[test]
+    fn test_settings_from_older_version() {
+        let json = r#"{"ffmpeg_path":null,"ffprobe_path":null,"first_run":false}"#;
+        let parsed: AppSettings = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.analysis_cache_limit_mb, 1024);
+    }
 }
@@ -90,8 +90,17 @@ use image::{DynamicImage, GrayImage};
 /// This is the most common method for blur detection and works well for
 /// identifying sharp frames suitable for 3D reconstruction (COLMAP, NeRF, 3DGS).
 pub fn calculate_sharpness(img: &DynamicImage) -> f64 {
-    let gray_img = img.to_luma8();
-    laplacian_variance(&gray_img)
+    match img {
+        // Frames decoded straight to grayscale need no conversion copy
+        DynamicImage::ImageLuma8(gray_img) => laplacian_variance(gray_img),
+        _ => laplacian_variance(&img.to_luma8()),
+    }
+}
+
+/// Sharpness normalized to `REFERENCE_LONG_EDGE`, so scores of clips analyzed
+/// at different resolutions can share one threshold
+pub fn calculate_normalized_sharpness(img: &DynamicImage) -> f64 {
+    normalize_sharpness(calculate_sharpness(img), img.width().max(img.height()))
 }
 
 /// Computes the variance of the Laplacian of a grayscale image.
@@ -105,44 +114,65 @@ fn laplacian_variance(img: &GrayImage) -> f64 {
         return 0.0;
     }
 
-    let mut laplacian_values = Vec::with_capacity((width * height) as usize);
+    let width = width as usize;
+    let pixels = img.as_raw();
 
     // Apply Laplacian kernel (using 3x3 kernel)
     // [ 0  1  0 ]
     // [ 1 -4  1 ]
     // [ 0  1  0 ]
-    for y in 1..height - 1 {
+    // Integer running sums are exact and avoid buffering a value per pixel
+    let mut sum: i64 = 0;
+    let mut sum_squares: i64 = 0;
+    for y in 1..height as usize - 1 {
+        let above = &pixels[(y - 1) * width..y * width];
+        let row = &pixels[y * width..(y + 1) * width];
+        let below = &pixels[(y + 1) * width..(y + 2) * width];
+
         for x in 1..width - 1 {
-            let center = img.get_pixel(x, y)[0] as i32;
-            let top = img.get_pixel(x, y - 1)[0] as i32;
-            let bottom = img.get_pixel(x, y + 1)[0] as i32;
-            let left = img.get_pixel(x - 1, y)[0] as i32;
-            let right = img.get_pixel(x + 1, y)[0] as i32;
-
-            let laplacian = top + bottom + left + right - 4 * center;
-            laplacian_values.push(laplacian as f64);
+            let laplacian =
+                above[x] as i64 + below[x] as i64 + row[x - 1] as i64 + row[x + 1] as i64
+                    - 4 * row[x] as i64;
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
         }
     }
 
     // Calculate variance of Laplacian values
-    if laplacian_values.is_empty() {
-        return 0.0;
-    }
+    let count = ((width - 2) * (height as usize - 2)) as f64;
+    let mean = sum as f64 / count;
+    (sum_squares as f64 / count - mean * mean).max(0.0)
+}
 
-    let mean: f64 = laplacian_values.iter().sum::<f64>() / laplacian_values.len() as f64;
-    let variance: f64 = laplacian_values
-        .iter()
-        .map(|&x| (x - mean).powi(2))
-        .sum::<f64>()
-        / laplacian_values.len() as f64;
+/// Long edge (in pixels) that normalized sharpness scores are expressed at
+pub const REFERENCE_LONG_EDGE: u32 = 1920;
 
-    variance
+/// Converts a raw Laplacian variance measured on a frame with the given long
+/// edge into a score comparable across resolutions.
+///
+/// The same scene looks crisper per pixel at lower resolutions, because blur
+/// and edges shrink along with the frame, so raw variance rises as frames get
+/// smaller. The Laplacian is a second derivative in pixel units, so it grows
+/// with the square of the downscale factor and its variance with the fourth
+/// power. Scores are rescaled by `(long_edge / REFERENCE_LONG_EDGE)^4`; frames
+/// at the reference size keep their raw score. Detail finer than a pixel at
+/// the lower resolution is averaged away, so heavily downscaled scores run a
+/// little low.
+pub fn normalize_sharpness(raw: f64, long_edge: u32) -> f64 {
+    if long_edge == 0 {
+        return raw;
+    }
+    let scale = long_edge as f64 / REFERENCE_LONG_EDGE as f64;
+    raw * scale.powi(4)
 }
 
 /// Determines an automatic threshold for frame selection based on sharpness scores.
 /// This uses statistical analysis to find frames that are significantly sharper
 /// than the mean, which is suitable for COLMAP and 3D reconstruction.
-pub fn calculate_auto_threshold(sharpness_scores: &[f64], target_frame_count: Option<usize>) -> f64 {
+pub fn calculate_auto_threshold(
+    sharpness_scores: &[f64],
+    target_frame_count: Option<usize>,
+) -> f64 {
     if sharpness_scores.is_empty() {
         return 0.0;
     }
@@ -196,6 +226,37 @@ pub fn select_frames_smart(
     selected_frames
 }
 
+/// Like `select_frames_smart`, but spaces selected frames by at least
+/// `min_distance` meters travelled rather than a number of frames. Frames
+/// without a travelled distance (no GPS fix) are never selected.
+pub fn select_frames_by_distance(
+    sharpness_scores: &[f64],
+    threshold: f64,
+    distances: &[Option<f64>],
+    min_distance: f64,
+) -> Vec<usize> {
+    let mut selected_frames = Vec::new();
+    let mut last_distance: Option<f64> = None;
+
+    for (idx, (&score, &distance)) in sharpness_scores.iter().zip(distances).enumerate() {
+        let Some(distance) = distance else {
+            continue;
+        };
+        if score >= threshold {
+            if let Some(last) = last_distance {
+                if distance - last < min_distance {
+                    continue;
+                }
+            }
+
+            selected_frames.push(idx);
+            last_distance = Some(distance);
+        }
+    }
+
+    selected_frames
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -208,10 +269,86 @@ mod tests {
         assert!(sharpness >= 0.0);
     }
 
+    #[test]
+    fn test_gray_sharpness_matches_rgb() {
+        let gray = GrayImage::from_fn(16, 16, |x, y| {
+            image::Luma([((x * 31 + y * 17) % 256) as u8])
+        });
+        let rgb = DynamicImage::ImageLuma8(gray.clone()).to_rgb8();
+        assert_eq!(
+            calculate_sharpness(&DynamicImage::ImageLuma8(gray)),
+            calculate_sharpness(&DynamicImage::ImageRgb8(rgb))
+        );
+    }
+
+    #[test]
+    fn test_laplacian_variance_of_checkerboard() {
+        // Every interior pixel has all four neighbors opposite: Laplacian of +-1020
+        let board = GrayImage::from_fn(9, 9, |x, y| {
+            image::Luma([if (x + y) % 2 == 0 { 255 } else { 0 }])
+        });
+        let variance = laplacian_variance(&board);
+        let mean = 1020.0 / 49.0; // 25 of 49 interior pixels are white (-1020)
+        assert!((variance - (1020.0f64.powi(2) - mean * mean)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_normalize_sharpness() {
+        assert_eq!(normalize_sharpness(100.0, REFERENCE_LONG_EDGE), 100.0);
+        assert_eq!(normalize_sharpness(100.0, 3840), 1600.0);
+        assert_eq!(normalize_sharpness(100.0, 960), 6.25);
+    }
+
+    #[test]
+    fn test_normalized_sharpness_matches_across_resolutions() {
+        // The same scene, a sinusoidal texture spanning the frame, at the
+        // reference size and at half of it
+        let render = |width: u32, height: u32| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+                let u = x as f64 / width as f64;
+                let v = y as f64 / height as f64;
+                let tau = std::f64::consts::TAU;
+                let value = 127.0 + 63.0 * (tau * 110.0 * u).sin() + 63.0 * (tau * 62.0 * v).sin();
+                image::Luma([value.round() as u8])
+            }))
+        };
+
+        let full = calculate_normalized_sharpness(&render(1920, 1080));
+        let half = calculate_normalized_sharpness(&render(960, 540));
+        assert!((half / full - 1.0).abs() < 0.15, "{} vs {}", half, full);
+        // The raw scores are more than ten times apart
+        assert!(
+            calculate_sharpness(&render(960, 540))
+                > 10.0 * calculate_sharpness(&render(1920, 1080))
+        );
+    }
+
     #[test]
     fn test_auto_threshold() {
         let scores = vec![10.0, 20.0, 30.0, 40.0, 50.0];
         let threshold = calculate_auto_threshold(&scores, None);
         assert!(threshold > 0.0);
     }
+
+    #[test]
+    fn test_select_frames_by_distance() {
+        let scores = vec![50.0, 60.0, 10.0, 70.0, 80.0, 90.0];
+        let distances = vec![
+            Some(0.0),
+            Some(4.0),
+            Some(8.0),
+            Some(12.0),
+            None,
+            Some(20.0),
+        ];
+        // Frame 2 is too blurry, frame 4 has no fix
+        assert_eq!(
+            select_frames_by_distance(&scores, 40.0, &distances, 5.0),
+            vec![0, 3, 5]
+        );
+        assert_eq!(
+            select_frames_by_distance(&scores, 40.0, &distances, 15.0),
+            vec![0, 5]
+        );
+    }
 }
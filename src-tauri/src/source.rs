@@ -0,0 +1,635 @@
+//! Inputs the analysis can run on, independent of how they are decoded.
+//!
+//! `VideoSource` is everything the analysis and export code needs from an
+//! input: its metadata, a timeline of numbered frames, and decoding of a set
+//! of frames into raw images. `FfmpegSource` drives the ffmpeg CLI and
+//! `ImageSequenceSource` reads folders of stills. Another decode backend,
+//! such as an in-process libav binding behind a cargo feature, only needs to
+//! implement the trait and be picked in `open_source`.
+
+use crate::jobs::{CancelToken, Cancelled};
+use crate::sequence::{
+    downscale_to_long_edge, export_sequence_images, get_sequence_info, is_image_sequence,
+    list_sequence_images, open_image, SequenceImage, SequenceOrder,
+};
+use crate::telemetry::{Telemetry, TelemetryTrack};
+use crate::timecode::offset_timecode;
+use crate::video::{
+    apply_frame_index, apply_hw_decode, decode_frame, detect_field_order, extract_frames_batch,
+    get_frame_index, get_video_info, keyframe_numbers, sample_frame_numbers, sample_frames_by_time,
+    stream_frames, stream_keyframes, DecodeOptions, Deinterlace, FrameData, FrameIndex,
+    RawPixelFormat, VideoInfo,
+};
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Decoded frames with their frame numbers, in ascending frame order
+pub type DecodedFrames = Box<dyn Iterator<Item = Result<(usize, DynamicImage)>> + Send>;
+
+/// An input made of numbered frames that can be decoded on demand
+pub trait VideoSource: Send + Sync {
+    fn info(&self) -> &VideoInfo;
+
+    /// Number of frames on the timeline
+    fn frame_count(&self) -> usize;
+
+    /// Timestamp recorded in `FrameData` for a frame
+    fn timestamp(&self, frame_number: usize) -> f64;
+
+    /// Seconds from the start of the source to a frame, as time ranges are given
+    fn position(&self, frame_number: usize) -> f64;
+
+    /// Frames to score when sampling every `sample_rate`-th frame of a time
+    /// range. Like segments, ranges include both their start and end.
+    fn sample(
+        &self,
+        sample_rate: usize,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize>;
+
+    /// Frames on screen every `interval` seconds of a time range
+    fn sample_by_time(
+        &self,
+        interval: f64,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize> {
+        sample_frames_by_time(
+            self.frame_count(),
+            |n| self.position(n),
+            interval,
+            start_time,
+            end_time,
+        )
+    }
+
+    /// Frames within the time range that decode on their own
+    fn keyframes(&self, start_time: Option<f64>, end_time: Option<f64>) -> Vec<usize>;
+
+    /// Original file behind a frame, for sources made of separate files
+    fn frame_path(&self, _frame_number: usize) -> Option<PathBuf> {
+        None
+    }
+
+    /// Flight telemetry at a frame, for sources with an SRT track
+    fn telemetry(&self, _frame_number: usize) -> Option<Telemetry> {
+        None
+    }
+
+    /// Where the telemetry comes from, if there is any
+    fn telemetry_source(&self) -> Option<&str> {
+        None
+    }
+
+    /// Timecode of a frame, counted on from the start timecode
+    fn timecode(&self, frame_number: usize) -> Option<String> {
+        let info = self.info();
+        let start = info.timecode.as_deref()?;
+        let elapsed = (self.position(frame_number) * info.fps).round().max(0.0) as u64;
+        offset_timecode(start, info.fps, elapsed)
+    }
+
+    /// Decodes the given frames, which must be sorted
+    fn decode(
+        &self,
+        frame_numbers: &[usize],
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames>;
+
+    /// Decodes consecutive entries of `keyframes()`. Sources without a faster
+    /// keyframe-only path decode them like any other frames.
+    fn decode_keyframes(
+        &self,
+        keyframes: &[usize],
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames> {
+        self.decode(keyframes, options, format)
+    }
+
+    /// Decodes a single frame
+    fn decode_frame(
+        &self,
+        frame_number: usize,
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DynamicImage> {
+        let (_, img) = self
+            .decode(&[frame_number], options, format)?
+            .next()
+            .with_context(|| format!("Frame {} could not be decoded", frame_number))??;
+        Ok(img)
+    }
+
+    /// Writes the given analyzed frames to `output_dir` as image files
+    fn export(
+        &self,
+        frames: &[FrameData],
+        options: &DecodeOptions,
+        output_dir: &Path,
+        format: &str,
+    ) -> Result<Vec<PathBuf>>;
+}
+
+/// Opens a video file or image folder with the matching source
+pub fn open_source(
+    path: &Path,
+    stream_index: Option<usize>,
+    sequence_order: SequenceOrder,
+    options: &DecodeOptions,
+) -> Result<Box<dyn VideoSource>> {
+    if is_image_sequence(path) {
+        return Ok(Box::new(ImageSequenceSource::open(path, sequence_order)?));
+    }
+    let mut info = get_video_info(path, stream_index)?;
+    if options.deinterlace == Deinterlace::Auto {
+        detect_field_order(path, &mut info, &options.cancel)?;
+    }
+    Ok(Box::new(FfmpegSource::new(path, info, &options.cancel)?))
+}
+
+/// A video file decoded by the ffmpeg CLI
+pub struct FfmpegSource {
+    path: PathBuf,
+    info: VideoInfo,
+    index: Arc<FrameIndex>,
+    telemetry: Option<TelemetryTrack>,
+}
+
+impl FfmpegSource {
+    /// Wraps a probed video, building its frame index and picking its
+    /// decoder if that has not been done yet
+    pub fn new(path: &Path, mut info: VideoInfo, cancel: &CancelToken) -> Result<Self> {
+        let index = get_frame_index(path, info.stream_index, cancel)?;
+        apply_frame_index(&mut info, &index);
+        apply_hw_decode(path, &mut info, cancel)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            info,
+            index,
+            telemetry: None,
+        })
+    }
+
+    /// Attaches flight telemetry to interpolate onto frames
+    pub fn with_telemetry(mut self, telemetry: Option<TelemetryTrack>) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+}
+
+impl VideoSource for FfmpegSource {
+    fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    fn timestamp(&self, frame_number: usize) -> f64 {
+        self.index.timestamp(frame_number)
+    }
+
+    fn position(&self, frame_number: usize) -> f64 {
+        self.index.timestamp(frame_number) - self.index.start_time()
+    }
+
+    fn sample(
+        &self,
+        sample_rate: usize,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize> {
+        sample_frame_numbers(&self.index, sample_rate, start_time, end_time)
+    }
+
+    fn keyframes(&self, start_time: Option<f64>, end_time: Option<f64>) -> Vec<usize> {
+        keyframe_numbers(&self.index, start_time, end_time)
+    }
+
+    fn telemetry(&self, frame_number: usize) -> Option<Telemetry> {
+        self.telemetry.as_ref()?.at(self.position(frame_number))
+    }
+
+    fn telemetry_source(&self) -> Option<&str> {
+        self.telemetry.as_ref().map(|track| track.source.as_str())
+    }
+
+    fn decode(
+        &self,
+        frame_numbers: &[usize],
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames> {
+        let stream = stream_frames(
+            &self.path,
+            &self.info,
+            &self.index,
+            options,
+            frame_numbers,
+            format,
+        )?;
+        Ok(Box::new(stream))
+    }
+
+    fn decode_keyframes(
+        &self,
+        keyframes: &[usize],
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames> {
+        let stream = stream_keyframes(
+            &self.path,
+            &self.info,
+            &self.index,
+            options,
+            keyframes,
+            format,
+        )?;
+        Ok(Box::new(stream))
+    }
+
+    fn decode_frame(
+        &self,
+        frame_number: usize,
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DynamicImage> {
+        decode_frame(&self.path, &self.info, options, frame_number, format)
+    }
+
+    fn export(
+        &self,
+        frames: &[FrameData],
+        options: &DecodeOptions,
+        output_dir: &Path,
+        format: &str,
+    ) -> Result<Vec<PathBuf>> {
+        let frame_numbers: Vec<usize> = frames.iter().map(|f| f.frame_number).collect();
+        extract_frames_batch(
+            &self.path,
+            &self.info,
+            &self.index,
+            options,
+            &frame_numbers,
+            output_dir,
+            format,
+        )
+    }
+}
+
+/// A folder of still images, one frame per image
+pub struct ImageSequenceSource {
+    images: Vec<SequenceImage>,
+    info: VideoInfo,
+}
+
+impl ImageSequenceSource {
+    pub fn open(dir: &Path, order: SequenceOrder) -> Result<Self> {
+        let images = list_sequence_images(dir, order)?;
+        let info = get_sequence_info(&images)?;
+        Ok(Self { images, info })
+    }
+}
+
+impl VideoSource for ImageSequenceSource {
+    fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    fn frame_count(&self) -> usize {
+        self.images.len()
+    }
+
+    fn timestamp(&self, frame_number: usize) -> f64 {
+        self.position(frame_number)
+    }
+
+    fn position(&self, frame_number: usize) -> f64 {
+        self.images
+            .get(frame_number)
+            .map(|img| img.timestamp)
+            .unwrap_or(0.0)
+    }
+
+    /// Every image in the range: a sequence is already a sparse set of shots
+    fn sample(
+        &self,
+        _sample_rate: usize,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize> {
+        self.images
+            .iter()
+            .enumerate()
+            .filter(|(_, img)| start_time.map(|t| img.timestamp >= t).unwrap_or(true))
+            .filter(|(_, img)| end_time.map(|t| img.timestamp <= t).unwrap_or(true))
+            .map(|(frame_number, _)| frame_number)
+            .collect()
+    }
+
+    fn sample_by_time(
+        &self,
+        _interval: f64,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize> {
+        self.sample(1, start_time, end_time)
+    }
+
+    fn keyframes(&self, start_time: Option<f64>, end_time: Option<f64>) -> Vec<usize> {
+        self.sample(1, start_time, end_time)
+    }
+
+    fn frame_path(&self, frame_number: usize) -> Option<PathBuf> {
+        self.images.get(frame_number).map(|img| img.path.clone())
+    }
+
+    fn decode(
+        &self,
+        frame_numbers: &[usize],
+        options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames> {
+        let pending = frame_numbers
+            .iter()
+            .map(|&n| {
+                let image = self
+                    .images
+                    .get(n)
+                    .with_context(|| format!("Image {} is out of range", n))?;
+                Ok((n, image.path.clone()))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Box::new(SequenceFrames {
+            pending,
+            ready: VecDeque::new(),
+            max_long_edge: options.analysis_resolution,
+            cancel: options.cancel.clone(),
+            format,
+        }))
+    }
+
+    fn export(
+        &self,
+        frames: &[FrameData],
+        options: &DecodeOptions,
+        output_dir: &Path,
+        format: &str,
+    ) -> Result<Vec<PathBuf>> {
+        // Prefer the path recorded at analysis time, in case the listing order
+        // changed since. Callers pair outputs with frames by position, so a
+        // frame without an image is an error rather than a gap.
+        let sources: Vec<PathBuf> = frames
+            .iter()
+            .map(|f| {
+                f.path
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .or_else(|| self.frame_path(f.frame_number))
+                    .with_context(|| {
+                        format!("Image {} is not part of the sequence", f.frame_number)
+                    })
+            })
+            .collect::<Result<_>>()?;
+        export_sequence_images(
+            &sources,
+            output_dir,
+            format,
+            options.high_bit_depth,
+            &options.cancel,
+        )
+    }
+}
+
+/// Decodes sequence images a batch at a time, each batch in parallel
+struct SequenceFrames {
+    pending: VecDeque<(usize, PathBuf)>,
+    ready: VecDeque<Result<(usize, DynamicImage)>>,
+    max_long_edge: Option<u32>,
+    cancel: CancelToken,
+    format: RawPixelFormat,
+}
+
+impl Iterator for SequenceFrames {
+    type Item = Result<(usize, DynamicImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ready.is_empty() && !self.pending.is_empty() {
+            if self.cancel.is_cancelled() {
+                self.pending.clear();
+                return Some(Err(Cancelled.into()));
+            }
+
+            let batch_size = rayon::current_num_threads().max(1) * 2;
+            let batch: Vec<(usize, PathBuf)> = self
+                .pending
+                .drain(..batch_size.min(self.pending.len()))
+                .collect();
+            self.ready = batch
+                .par_iter()
+                .map(|(frame_number, path)| {
+                    let img = downscale_to_long_edge(open_image(path)?, self.max_long_edge);
+                    Ok((*frame_number, convert(img, self.format)))
+                })
+                .collect::<Vec<_>>()
+                .into();
+        }
+        self.ready.pop_front()
+    }
+}
+
+/// Converts a decoded image to the pixel layout a caller asked for
+fn convert(img: DynamicImage, format: RawPixelFormat) -> DynamicImage {
+    match format {
+        RawPixelFormat::Gray => DynamicImage::ImageLuma8(img.to_luma8()),
+        RawPixelFormat::Rgb24 => DynamicImage::ImageRgb8(img.to_rgb8()),
+    }
+}
+
+/// Synthetic source for tests: a checkerboard whose contrast, and therefore
+/// sharpness, is set per frame
+#[cfg(test)]
+pub struct MockSource {
+    info: VideoInfo,
+    contrast: Vec<u8>,
+    /// Remaining failed decode attempts per frame
+    failures: std::sync::Mutex<std::collections::HashMap<usize, usize>>,
+}
+
+#[cfg(test)]
+impl MockSource {
+    pub fn new(contrast: Vec<u8>) -> Self {
+        let info = VideoInfo {
+            fps: 10.0,
+            duration: contrast.len() as f64 / 10.0,
+            width: 64,
+            height: 64,
+            display_width: 64,
+            display_height: 64,
+            total_frames: contrast.len() as u32,
+            ..Default::default()
+        };
+        Self {
+            info,
+            contrast,
+            failures: Default::default(),
+        }
+    }
+
+    /// Makes the next `attempts` decodes of a frame fail
+    pub fn failing(self, frame_number: usize, attempts: usize) -> Self {
+        self.failures.lock().unwrap().insert(frame_number, attempts);
+        self
+    }
+
+    fn render(&self, frame_number: usize) -> DynamicImage {
+        let half = self.contrast[frame_number] / 2;
+        DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, y| {
+            let value = if (x / 4 + y / 4) % 2 == 0 {
+                128 + half
+            } else {
+                128 - half
+            };
+            image::Luma([value])
+        }))
+    }
+}
+
+#[cfg(test)]
+impl VideoSource for MockSource {
+    fn info(&self) -> &VideoInfo {
+        &self.info
+    }
+
+    fn frame_count(&self) -> usize {
+        self.contrast.len()
+    }
+
+    fn timestamp(&self, frame_number: usize) -> f64 {
+        self.position(frame_number)
+    }
+
+    fn position(&self, frame_number: usize) -> f64 {
+        frame_number as f64 / self.info.fps
+    }
+
+    fn sample(
+        &self,
+        sample_rate: usize,
+        start_time: Option<f64>,
+        end_time: Option<f64>,
+    ) -> Vec<usize> {
+        (0..self.frame_count())
+            .filter(|&n| start_time.map(|t| self.position(n) >= t).unwrap_or(true))
+            .filter(|&n| end_time.map(|t| self.position(n) <= t).unwrap_or(true))
+            .step_by(sample_rate.max(1))
+            .collect()
+    }
+
+    fn keyframes(&self, start_time: Option<f64>, end_time: Option<f64>) -> Vec<usize> {
+        self.sample(10, start_time, end_time)
+    }
+
+    fn decode(
+        &self,
+        frame_numbers: &[usize],
+        _options: &DecodeOptions,
+        format: RawPixelFormat,
+    ) -> Result<DecodedFrames> {
+        let mut failures = self.failures.lock().unwrap();
+        let frames: Vec<Result<(usize, DynamicImage)>> = frame_numbers
+            .iter()
+            .map(
+                |&n| match failures.get_mut(&n).filter(|remaining| **remaining > 0) {
+                    Some(remaining) => {
+                        *remaining -= 1;
+                        Err(anyhow::anyhow!("Corrupt packet at frame {}", n))
+                    }
+                    None => Ok((n, convert(self.render(n), format))),
+                },
+            )
+            .collect();
+        Ok(Box::new(frames.into_iter()))
+    }
+
+    fn export(
+        &self,
+        _frames: &[FrameData],
+        _options: &DecodeOptions,
+        _output_dir: &Path,
+        _format: &str,
+    ) -> Result<Vec<PathBuf>> {
+        anyhow::bail!("MockSource cannot export")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_sequence_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for i in 0..5 {
+            image::RgbImage::new(40, 20)
+                .save(dir.join(format!("shot_{}.png", i)))
+                .unwrap();
+        }
+
+        let source = ImageSequenceSource::open(dir, SequenceOrder::Filename).unwrap();
+        assert_eq!(source.frame_count(), 5);
+        // Sequences score every image, whatever the sample rate
+        assert_eq!(source.sample(30, Some(1.0), Some(3.0)), vec![1, 2, 3]);
+
+        let options = DecodeOptions {
+            analysis_resolution: Some(20),
+            ..Default::default()
+        };
+        let decoded: Vec<(usize, DynamicImage)> = source
+            .decode(&[0, 2, 4], &options, RawPixelFormat::Gray)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            decoded.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+            vec![0, 2, 4]
+        );
+        assert!(decoded
+            .iter()
+            .all(|(_, img)| img.width() == 20 && img.height() == 10));
+        assert!(source.frame_path(4).unwrap().ends_with("shot_4.png"));
+
+        // Exports pair files with frames by position, so an unknown frame is an error
+        let unknown = FrameData {
+            frame_number: 9,
+            ..Default::default()
+        };
+        assert!(source
+            .export(&[unknown], &options, &dir.join("out"), "png")
+            .is_err());
+    }
+
+    #[test]
+    fn test_mock_source_contrast_drives_sharpness() {
+        use crate::sharpness::calculate_normalized_sharpness;
+
+        let source = MockSource::new(vec![10, 200]);
+        let score = |n| {
+            let img = source
+                .decode_frame(n, &DecodeOptions::default(), RawPixelFormat::Gray)
+                .unwrap();
+            calculate_normalized_sharpness(&img)
+        };
+        assert!(score(1) > score(0));
+    }
+}
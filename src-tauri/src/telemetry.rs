@@ -0,0 +1,435 @@
+//! DJI flight telemetry from SRT subtitles.
+//!
+//! DJI drones write a subtitle cue per frame (or per second on older models)
+//! with the aircraft position, altitude, gimbal angles and exposure, either
+//! to a `.SRT` file next to the video or to a subtitle stream inside it.
+//! Newer models use bracketed fields:
+//!
+//! ```text
+//! [iso: 100] [shutter: 1/1000.0] [fnum: 2.8] [latitude: 47.371200] [longitude: 8.523400]
+//! [rel_alt: 50.000 abs_alt: 470.500] [gb_yaw: 10.2 gb_pitch: -90.0 gb_roll: 0.0]
+//! ```
+//!
+//! older ones a comma-separated line such as
+//! `F/2.8, SS 320.00, ISO 100, EV 0, GPS (8.5234, 47.3712, 19), D 12.34m, H 50.00m`.
+//! Cues are interpolated onto analyzed frames by their position in the video.
+
+use crate::jobs::CancelToken;
+use crate::video::{get_ffmpeg_path, VideoInfo};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+/// Mean Earth radius in meters, for distances between GPS fixes
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Frames further than this from the first or last cue get no telemetry
+const MAX_EXTRAPOLATION: f64 = 1.0;
+
+/// Drone state at one frame
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Meters above the takeoff point
+    pub relative_altitude: Option<f64>,
+    /// Meters above sea level
+    pub absolute_altitude: Option<f64>,
+    /// Gimbal orientation in degrees
+    pub gimbal_yaw: Option<f64>,
+    pub gimbal_pitch: Option<f64>,
+    pub gimbal_roll: Option<f64>,
+    pub iso: Option<u32>,
+    /// Exposure time as written by the drone, e.g. "1/1000.0"
+    pub shutter: Option<String>,
+    pub f_number: Option<f64>,
+    /// Meters travelled along the GPS track since the first fix
+    pub distance: Option<f64>,
+}
+
+impl Telemetry {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn has_fix(&self) -> bool {
+        self.latitude.is_some() && self.longitude.is_some()
+    }
+}
+
+/// Telemetry cues of a video, by seconds from its start
+#[derive(Debug, Clone)]
+pub struct TelemetryTrack {
+    /// Where the cues came from: the SRT path or the subtitle stream
+    pub source: String,
+    samples: Vec<(f64, Telemetry)>,
+}
+
+impl TelemetryTrack {
+    /// Builds a track from parsed cues, adding the travelled distance.
+    /// None if no cue carried telemetry.
+    pub fn new(source: String, mut samples: Vec<(f64, Telemetry)>) -> Option<Self> {
+        samples.retain(|(_, telemetry)| !telemetry.is_empty());
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut distance = None;
+        let mut last_fix: Option<(f64, f64)> = None;
+        for (_, telemetry) in &mut samples {
+            if let (Some(lat), Some(lon)) = (telemetry.latitude, telemetry.longitude) {
+                let step = last_fix.map_or(0.0, |(last_lat, last_lon)| {
+                    haversine(last_lat, last_lon, lat, lon)
+                });
+                distance = Some(distance.unwrap_or(0.0) + step);
+                last_fix = Some((lat, lon));
+            }
+            telemetry.distance = distance;
+        }
+        Some(Self { source, samples })
+    }
+
+    /// Telemetry `time` seconds into the video. Positions, altitudes and
+    /// gimbal angles are interpolated between the surrounding cues, exposure
+    /// settings taken from the earlier one.
+    pub fn at(&self, time: f64) -> Option<Telemetry> {
+        let first = self.samples.first()?;
+        let last = self.samples.last()?;
+        if time < first.0 - MAX_EXTRAPOLATION || time > last.0 + MAX_EXTRAPOLATION {
+            return None;
+        }
+
+        let next = self.samples.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return Some(first.1.clone());
+        }
+        if next == self.samples.len() {
+            return Some(last.1.clone());
+        }
+        let (t0, a) = &self.samples[next - 1];
+        let (t1, b) = &self.samples[next];
+        let f = if t1 > t0 {
+            (time - t0) / (t1 - t0)
+        } else {
+            0.0
+        };
+
+        let lerp = |x: Option<f64>, y: Option<f64>| match (x, y) {
+            (Some(x), Some(y)) => Some(x + (y - x) * f),
+            (x, y) => x.or(y),
+        };
+        // Yaw wraps around at +-180 degrees, so interpolate the short way round
+        let yaw = match (a.gimbal_yaw, b.gimbal_yaw) {
+            (Some(x), Some(y)) => {
+                let delta = (y - x + 540.0).rem_euclid(360.0) - 180.0;
+                Some((x + delta * f + 540.0).rem_euclid(360.0) - 180.0)
+            }
+            (x, y) => x.or(y),
+        };
+        let fix = a.has_fix() && b.has_fix();
+
+        Some(Telemetry {
+            latitude: if fix {
+                lerp(a.latitude, b.latitude)
+            } else {
+                a.latitude.or(b.latitude)
+            },
+            longitude: if fix {
+                lerp(a.longitude, b.longitude)
+            } else {
+                a.longitude.or(b.longitude)
+            },
+            relative_altitude: lerp(a.relative_altitude, b.relative_altitude),
+            absolute_altitude: lerp(a.absolute_altitude, b.absolute_altitude),
+            gimbal_yaw: yaw,
+            gimbal_pitch: lerp(a.gimbal_pitch, b.gimbal_pitch),
+            gimbal_roll: lerp(a.gimbal_roll, b.gimbal_roll),
+            iso: a.iso,
+            shutter: a.shutter.clone(),
+            f_number: a.f_number,
+            distance: lerp(a.distance, b.distance),
+        })
+    }
+}
+
+/// Great-circle distance in meters between two GPS fixes
+fn haversine(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// Parses SRT subtitles into (cue start in seconds, telemetry) pairs
+pub fn parse_srt(text: &str) -> Vec<(f64, Telemetry)> {
+    let text = text.replace("\r\n", "\n");
+    text.split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+            let start = parse_srt_time(lines.next()?.split("-->").next()?)?;
+            let body: Vec<&str> = lines.collect();
+            Some((start, parse_cue(&strip_tags(&body.join(" ")))))
+        })
+        .collect()
+}
+
+/// Parses an SRT timestamp, `HH:MM:SS,mmm`
+fn parse_srt_time(value: &str) -> Option<f64> {
+    let mut parts = value.trim().split(':');
+    let hours = parts.next()?.parse::<f64>().ok()?;
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let seconds = parts.next()?.replace(',', ".").parse::<f64>().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Removes HTML-style markup such as `<font size="28">`
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                plain.push(' ');
+            }
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// Reads the telemetry fields of one cue in either DJI layout
+fn parse_cue(text: &str) -> Telemetry {
+    let mut telemetry = Telemetry::default();
+    let number = |value: &str| {
+        value
+            .trim_matches(|c: char| {
+                c.is_whitespace() || c == ',' || c == 'm' || c == ')' || c == '('
+            })
+            .parse::<f64>()
+            .ok()
+    };
+
+    // "GPS (lon, lat, satellites or altitude)" in the older layout
+    if let Some(gps) = text.find("GPS").map(|i| &text[i + 3..]) {
+        if let Some(inner) = gps
+            .trim_start()
+            .strip_prefix('(')
+            .and_then(|g| g.split(')').next())
+        {
+            let values: Vec<Option<f64>> = inner.split(',').map(number).collect();
+            if let [Some(lon), Some(lat), ..] = values[..] {
+                telemetry.longitude = Some(lon);
+                telemetry.latitude = Some(lat);
+            }
+        }
+    }
+
+    let spaced = text.replace(['[', ']'], " ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).copied().unwrap_or("");
+        if let Some(key) = token.strip_suffix(':') {
+            match key.to_ascii_lowercase().as_str() {
+                "latitude" => telemetry.latitude = number(next),
+                // Some firmware misspells it
+                "longitude" | "longtitude" => telemetry.longitude = number(next),
+                "rel_alt" => telemetry.relative_altitude = number(next),
+                "abs_alt" | "altitude" => telemetry.absolute_altitude = number(next),
+                "gb_yaw" => telemetry.gimbal_yaw = number(next),
+                "gb_pitch" => telemetry.gimbal_pitch = number(next),
+                "gb_roll" => telemetry.gimbal_roll = number(next),
+                "iso" => telemetry.iso = number(next).map(|iso| iso as u32),
+                "shutter" => telemetry.shutter = Some(next.trim_end_matches(',').to_string()),
+                "fnum" => telemetry.f_number = number(next),
+                _ => {}
+            }
+        } else {
+            match *token {
+                "ISO" => telemetry.iso = number(next).map(|iso| iso as u32),
+                "SS" => telemetry.shutter = number(next).map(|ss| format!("1/{}", ss)),
+                "H" => telemetry.relative_altitude = number(next),
+                _ => {
+                    if let Some(f_number) = token.strip_prefix("F/") {
+                        telemetry.f_number = number(f_number);
+                    } else if let Some(height) = token.strip_prefix("BAROMETER:") {
+                        telemetry.relative_altitude = number(height);
+                    }
+                }
+            }
+        }
+    }
+
+    // Written before the GPS has a fix
+    if telemetry.latitude == Some(0.0) && telemetry.longitude == Some(0.0) {
+        telemetry.latitude = None;
+        telemetry.longitude = None;
+    }
+    telemetry
+}
+
+/// Loads the telemetry of a video from an SRT file with the same name next
+/// to it or, failing that, from its first subtitle stream. None if neither
+/// exists or carries telemetry.
+pub fn load_telemetry(
+    video_path: &Path,
+    info: &VideoInfo,
+    cancel: &CancelToken,
+) -> Result<Option<TelemetryTrack>> {
+    if let Some(srt_path) = sidecar_srt(video_path) {
+        let text = String::from_utf8_lossy(&fs::read(&srt_path)?).to_string();
+        return Ok(TelemetryTrack::new(
+            srt_path.display().to_string(),
+            parse_srt(&text),
+        ));
+    }
+
+    let Some(stream_index) = info.subtitle_stream else {
+        return Ok(None);
+    };
+    let mut cmd = Command::new(get_ffmpeg_path()?);
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-i",
+        video_path.to_str().unwrap(),
+        "-map",
+        &format!("0:{}", stream_index),
+        "-f",
+        "srt",
+        "-",
+    ]);
+    let output = cancel.output(&mut cmd)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to read subtitle stream #{}: {}",
+            stream_index,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(TelemetryTrack::new(
+        format!("subtitle stream #{}", stream_index),
+        parse_srt(&text),
+    ))
+}
+
+/// Where `load_telemetry` would read from, for cache keys: the SRT file with
+/// its modification time, or the subtitle stream, which the video's own
+/// fingerprint already covers. Cheap enough to check before every cache lookup.
+pub fn telemetry_key(video_path: &Path, info: &VideoInfo) -> String {
+    if let Some(srt_path) = sidecar_srt(video_path) {
+        let modified = fs::metadata(&srt_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        return format!("{}@{}", srt_path.display(), modified);
+    }
+    info.subtitle_stream
+        .map_or("none".to_string(), |index| format!("stream#{}", index))
+}
+
+/// The SRT file with the same name as the video, if there is one
+fn sidecar_srt(video_path: &Path) -> Option<PathBuf> {
+    ["SRT", "srt"]
+        .iter()
+        .map(|extension| video_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAVIC3: &str = "1\r\n00:00:00,000 --> 00:00:00,033\r\n<font size=\"28\">FrameCnt: 1, DiffTime: 33ms\r\n2023-05-12 14:31:22.123\r\n[iso: 100] [shutter: 1/1000.0] [fnum: 2.8] [ev: 0] [latitude: 47.371200] [longitude: 8.523400] [rel_alt: 50.000 abs_alt: 470.500] [gb_yaw: 170.0 gb_pitch: -90.0 gb_roll: 0.0] </font>\r\n\r\n2\r\n00:00:01,000 --> 00:00:01,033\r\n<font size=\"28\">FrameCnt: 31, DiffTime: 33ms\r\n[iso: 200] [shutter: 1/500.0] [fnum: 2.8] [latitude: 47.372200] [longitude: 8.523400] [rel_alt: 60.000 abs_alt: 480.500] [gb_yaw: -170.0 gb_pitch: -80.0 gb_roll: 0.0] </font>\r\n";
+
+    #[test]
+    fn test_parse_srt_layouts() {
+        let cues = parse_srt(MAVIC3);
+        assert_eq!(cues.len(), 2);
+        let (start, first) = &cues[0];
+        assert_eq!(*start, 0.0);
+        assert_eq!(
+            (first.latitude, first.longitude),
+            (Some(47.3712), Some(8.5234))
+        );
+        assert_eq!(
+            (first.relative_altitude, first.absolute_altitude),
+            (Some(50.0), Some(470.5))
+        );
+        assert_eq!(
+            (first.gimbal_yaw, first.gimbal_pitch),
+            (Some(170.0), Some(-90.0))
+        );
+        assert_eq!(
+            (first.iso, first.shutter.as_deref(), first.f_number),
+            (Some(100), Some("1/1000.0"), Some(2.8))
+        );
+
+        let mini2 = "5\n00:00:04,000 --> 00:00:05,000\nF/2.8, SS 320.00, ISO 100, EV 0, DZOOM 1.000, GPS (8.5234, 47.3712, 19), D 12.34m, H 50.00m, H.S 0.00m/s\n";
+        let (start, old) = &parse_srt(mini2)[0];
+        assert_eq!(*start, 4.0);
+        assert_eq!((old.latitude, old.longitude), (Some(47.3712), Some(8.5234)));
+        assert_eq!(
+            (old.relative_altitude, old.iso, old.f_number),
+            (Some(50.0), Some(100), Some(2.8))
+        );
+        assert_eq!(old.shutter.as_deref(), Some("1/320"));
+
+        let no_fix = parse_cue("[latitude: 0.000000] [longitude: 0.000000] [iso: 100]");
+        assert_eq!((no_fix.latitude, no_fix.iso), (None, Some(100)));
+    }
+
+    #[test]
+    fn test_track_interpolation() {
+        let track = TelemetryTrack::new("test.SRT".to_string(), parse_srt(MAVIC3)).unwrap();
+
+        let middle = track.at(0.5).unwrap();
+        assert!((middle.latitude.unwrap() - 47.3717).abs() < 1e-9);
+        assert_eq!(middle.relative_altitude, Some(55.0));
+        // 170 to -170 degrees passes through 180, not 0
+        assert!((middle.gimbal_yaw.unwrap().abs() - 180.0).abs() < 1e-9);
+        assert_eq!(middle.iso, Some(100));
+
+        // 0.001 degrees of latitude is about 111 meters
+        let end = track.at(1.0).unwrap();
+        assert!((end.distance.unwrap() - 111.19).abs() < 0.1);
+        assert!((middle.distance.unwrap() - 55.6).abs() < 0.1);
+
+        assert_eq!(track.at(1.5).unwrap().latitude, end.latitude);
+        assert!(track.at(3.0).is_none());
+        assert!(TelemetryTrack::new(
+            "empty".to_string(),
+            parse_srt("1\n00:00:00,000 --> 00:00:01,000\nHello\n")
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_telemetry_key() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let video = dir.join("DJI_0001.MP4");
+        let info = VideoInfo {
+            subtitle_stream: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(telemetry_key(&video, &info), "stream#2");
+        assert_eq!(telemetry_key(&video, &VideoInfo::default()), "none");
+
+        // A sidecar file takes precedence and is keyed by path and modification time
+        fs::write(dir.join("DJI_0001.SRT"), MAVIC3).unwrap();
+        let key = telemetry_key(&video, &info);
+        assert!(key.starts_with(&format!("{}@", dir.join("DJI_0001.SRT").display())));
+    }
+}
@@ -0,0 +1,254 @@
+//! SMPTE timecode.
+//!
+//! Pro cameras record the timecode of a clip's first frame, either in a
+//! `tmcd` data track or as a `timecode` tag. Every other frame's timecode is
+//! counted on from it at the nominal rate (30 for 29.97 fps). Drop-frame
+//! timecode, written with a `;` before the frame field, skips frame labels 0
+//! and 1 (0 to 3 at 59.94) at the start of every minute not divisible by ten
+//! so the labels stay in step with wall-clock time.
+
+use crate::video::VideoInfo;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Fields of a parsed timecode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fields {
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+    frames: u64,
+    drop_frame: bool,
+}
+
+/// Parses `HH:MM:SS:FF`, or `HH:MM:SS;FF` (also `.` or `,`) for drop-frame
+fn parse(timecode: &str) -> Option<Fields> {
+    let timecode = timecode.trim();
+    let split = timecode.rfind([':', ';', '.', ','])?;
+    let drop_frame = timecode[split..].starts_with([';', '.', ',']);
+
+    let mut parts = timecode[..split].split(':').map(|p| p.parse::<u64>().ok());
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = timecode[split + 1..].parse::<u64>().ok()?;
+    if parts.next().is_some() || minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    Some(Fields {
+        hours,
+        minutes,
+        seconds,
+        frames,
+        drop_frame,
+    })
+}
+
+/// Frames per second timecode labels count, e.g. 30 for 29.97 fps
+fn nominal_rate(fps: f64) -> u64 {
+    (fps.round() as u64).max(1)
+}
+
+/// Frame labels skipped per minute by drop-frame timecode
+fn dropped_per_minute(rate: u64) -> u64 {
+    rate / 15
+}
+
+/// Frames from 00:00:00:00 to a timecode
+fn to_frame_count(fields: Fields, rate: u64) -> Result<u64> {
+    if fields.frames >= rate {
+        anyhow::bail!(
+            "Frame field {} is out of range at {} fps",
+            fields.frames,
+            rate
+        );
+    }
+    let total_minutes = fields.hours * 60 + fields.minutes;
+    let labels = (total_minutes * 60 + fields.seconds) * rate + fields.frames;
+    if !fields.drop_frame {
+        return Ok(labels);
+    }
+
+    let drop = dropped_per_minute(rate);
+    if fields.seconds == 0 && fields.minutes % 10 != 0 && fields.frames < drop {
+        anyhow::bail!(
+            "Frame label {} does not exist in drop-frame timecode",
+            fields.frames
+        );
+    }
+    Ok(labels - drop * (total_minutes - total_minutes / 10))
+}
+
+/// Timecode label of a frame count from 00:00:00:00, wrapping at 24 hours
+fn from_frame_count(frames: u64, rate: u64, drop_frame: bool) -> String {
+    let mut labels = frames;
+    if drop_frame {
+        let drop = dropped_per_minute(rate);
+        let per_ten_minutes = rate * 600 - drop * 9;
+        let per_minute = rate * 60 - drop;
+        let tens = frames / per_ten_minutes;
+        let rest = frames % per_ten_minutes;
+        labels += drop * 9 * tens;
+        if rest > drop {
+            labels += drop * ((rest - drop) / per_minute);
+        }
+    }
+
+    let frame = labels % rate;
+    let seconds = labels / rate;
+    format!(
+        "{:02}:{:02}:{:02}{}{:02}",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        if drop_frame { ';' } else { ':' },
+        frame
+    )
+}
+
+/// Normalizes a start timecode from metadata, or None if it does not parse
+pub fn parse_start_timecode(timecode: &str, fps: f64) -> Option<String> {
+    let fields = parse(timecode)?;
+    let rate = nominal_rate(fps);
+    let frames = to_frame_count(fields, rate).ok()?;
+    Some(from_frame_count(frames, rate, fields.drop_frame))
+}
+
+/// Timecode of the frame `elapsed_frames` after one labelled `start`
+pub fn offset_timecode(start: &str, fps: f64, elapsed_frames: u64) -> Option<String> {
+    let fields = parse(start)?;
+    let rate = nominal_rate(fps);
+    let frames = to_frame_count(fields, rate).ok()?;
+    Some(from_frame_count(
+        frames + elapsed_frames,
+        rate,
+        fields.drop_frame,
+    ))
+}
+
+/// Seconds from the start of a video to a timecode within it
+pub fn timecode_to_seconds(timecode: &str, info: &VideoInfo) -> Result<f64> {
+    let start = info
+        .timecode
+        .as_deref()
+        .context("This video has no embedded timecode")?;
+    let fields = parse(timecode).with_context(|| format!("Invalid timecode {}", timecode))?;
+    let start_fields = parse(start).context("Invalid start timecode")?;
+    if fields.drop_frame != start_fields.drop_frame {
+        anyhow::bail!(
+            "The video uses {} timecode",
+            if start_fields.drop_frame {
+                "drop-frame"
+            } else {
+                "non-drop-frame"
+            }
+        );
+    }
+
+    let rate = nominal_rate(info.fps);
+    let frames = to_frame_count(fields, rate)?;
+    let start_frames = to_frame_count(start_fields, rate)?;
+    let elapsed = frames.checked_sub(start_frames).with_context(|| {
+        format!(
+            "Timecode {} is before the start of the video ({})",
+            timecode, start
+        )
+    })?;
+    Ok(elapsed as f64 / info.fps)
+}
+
+/// A time given either in seconds from the start or as a timecode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TimeInput {
+    Seconds(f64),
+    Timecode(String),
+}
+
+impl TimeInput {
+    /// Seconds from the start of the video
+    pub fn seconds(&self, info: &VideoInfo) -> Result<f64> {
+        match self {
+            TimeInput::Seconds(seconds) => Ok(*seconds),
+            TimeInput::Timecode(text) => match text.trim().parse::<f64>() {
+                Ok(seconds) => Ok(seconds),
+                Err(_) => timecode_to_seconds(text, info),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_drop_frame() {
+        assert_eq!(
+            offset_timecode("01:00:00:00", 25.0, 0).unwrap(),
+            "01:00:00:00"
+        );
+        assert_eq!(
+            offset_timecode("01:00:00:00", 25.0, 26).unwrap(),
+            "01:00:01:01"
+        );
+        assert_eq!(
+            offset_timecode("23:59:59:23", 24.0, 1).unwrap(),
+            "00:00:00:00"
+        );
+        assert_eq!(
+            parse_start_timecode("1:02:03:04", 24.0).unwrap(),
+            "01:02:03:04"
+        );
+        assert!(parse_start_timecode("01:00:00:30", 30.0).is_none());
+        assert!(parse_start_timecode("not a timecode", 30.0).is_none());
+    }
+
+    #[test]
+    fn test_drop_frame() {
+        // 29.97 fps skips labels ;00 and ;01 at minutes not divisible by ten
+        assert_eq!(
+            offset_timecode("00:00:59;29", 29.97, 1).unwrap(),
+            "00:01:00;02"
+        );
+        assert_eq!(
+            offset_timecode("00:09:59;29", 29.97, 1).unwrap(),
+            "00:10:00;00"
+        );
+        // One hour of drop-frame labels is 107892 frames
+        assert_eq!(
+            offset_timecode("00:00:00;00", 29.97, 107_892).unwrap(),
+            "01:00:00;00"
+        );
+        assert!(parse_start_timecode("00:01:00;01", 29.97).is_none());
+    }
+
+    #[test]
+    fn test_time_input() {
+        let info = VideoInfo {
+            fps: 25.0,
+            timecode: Some("10:00:00:00".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(TimeInput::Seconds(2.5).seconds(&info).unwrap(), 2.5);
+        assert_eq!(
+            TimeInput::Timecode("4".to_string()).seconds(&info).unwrap(),
+            4.0
+        );
+        assert_eq!(
+            TimeInput::Timecode("10:00:01:05".to_string())
+                .seconds(&info)
+                .unwrap(),
+            1.2
+        );
+        assert!(TimeInput::Timecode("09:59:59:00".to_string())
+            .seconds(&info)
+            .is_err());
+        assert!(TimeInput::Timecode("10:00:01:05".to_string())
+            .seconds(&VideoInfo::default())
+            .is_err());
+
+        let json: Vec<TimeInput> = serde_json::from_str(r#"[1.5, "10:00:00:10"]"#).unwrap();
+        assert_eq!(json[1].seconds(&info).unwrap(), 0.4);
+    }
+}
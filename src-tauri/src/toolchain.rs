@@ -0,0 +1,454 @@
+//! Validation of the FFmpeg/FFprobe binaries the app is configured with.
+//!
+//! A binary that runs `-version` fine can still be unusable: a minimal build
+//! without the `select` filter, a static build missing the PNG encoder, or
+//! ffprobe picked by mistake in the ffmpeg field. The report lists every
+//! filter, encoder and pixel format the extractor relies on, whether this
+//! build has it, and plain-language problems for the settings dialog.
+
+use crate::video::get_ffmpeg_path;
+use serde::Serialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Oldest FFmpeg major version with everything we use (`tonemap`, `-noautorotate`...)
+const MIN_MAJOR_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityKind {
+    Filter,
+    Encoder,
+    PixelFormat,
+}
+
+/// Something the extractor needs from the FFmpeg build
+struct Requirement {
+    kind: CapabilityKind,
+    name: &'static str,
+    purpose: &'static str,
+    /// Missing required capabilities break core features; the rest only disable one
+    required: bool,
+}
+
+const fn requirement(
+    kind: CapabilityKind,
+    name: &'static str,
+    purpose: &'static str,
+    required: bool,
+) -> Requirement {
+    Requirement {
+        kind,
+        name,
+        purpose,
+        required,
+    }
+}
+
+const REQUIREMENTS: &[Requirement] = &[
+    requirement(
+        CapabilityKind::Filter,
+        "select",
+        "decoding only the sampled frames",
+        true,
+    ),
+    requirement(
+        CapabilityKind::Filter,
+        "scale",
+        "analysis resolution and aspect correction",
+        true,
+    ),
+    requirement(CapabilityKind::Filter, "setsar", "aspect correction", true),
+    requirement(CapabilityKind::Filter, "transpose", "rotated videos", true),
+    requirement(CapabilityKind::Filter, "hflip", "rotated videos", true),
+    requirement(CapabilityKind::Filter, "vflip", "rotated videos", true),
+    requirement(CapabilityKind::Filter, "format", "HDR tone mapping", false),
+    requirement(
+        CapabilityKind::Filter,
+        "zscale",
+        "HDR tone mapping with highlight compression",
+        false,
+    ),
+    requirement(
+        CapabilityKind::Filter,
+        "colorspace",
+        "BT.2020 SDR conversion; cannot tone-map PQ or HLG",
+        false,
+    ),
+    requirement(CapabilityKind::Filter, "tonemap", "HDR tone mapping", false),
+    requirement(CapabilityKind::Filter, "idet", "interlace detection", false),
+    requirement(CapabilityKind::Filter, "bwdif", "deinterlacing", false),
+    requirement(
+        CapabilityKind::Filter,
+        "yadif",
+        "deinterlacing without bwdif",
+        false,
+    ),
+    requirement(
+        CapabilityKind::Encoder,
+        "rawvideo",
+        "streaming frames to the analyzer",
+        true,
+    ),
+    requirement(CapabilityKind::Encoder, "mjpeg", "JPEG export", true),
+    requirement(CapabilityKind::Encoder, "png", "PNG export", true),
+    requirement(CapabilityKind::Encoder, "tiff", "TIFF export", false),
+    requirement(
+        CapabilityKind::Encoder,
+        "libx264",
+        "hardware decode probing",
+        false,
+    ),
+    requirement(
+        CapabilityKind::PixelFormat,
+        "gray",
+        "sharpness analysis",
+        true,
+    ),
+    requirement(CapabilityKind::PixelFormat, "rgb24", "frame previews", true),
+    requirement(
+        CapabilityKind::PixelFormat,
+        "rgb48be",
+        "16-bit PNG export",
+        false,
+    ),
+    requirement(
+        CapabilityKind::PixelFormat,
+        "rgb48le",
+        "16-bit TIFF export",
+        false,
+    ),
+    requirement(
+        CapabilityKind::PixelFormat,
+        "gbrpf32le",
+        "HDR tone mapping",
+        false,
+    ),
+];
+
+/// What one binary reported about itself
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BinaryReport {
+    pub path: Option<String>,
+    /// The binary ran and printed a version banner
+    pub runs: bool,
+    /// Version string from the banner, e.g. "6.1.1-3ubuntu5"
+    pub version: Option<String>,
+    /// None for git builds such as "N-113000-g1234abcd"
+    pub major_version: Option<u32>,
+    /// `configuration:` flags of the build
+    pub configuration: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    pub kind: CapabilityKind,
+    pub name: String,
+    /// What the extractor uses it for
+    pub purpose: String,
+    pub required: bool,
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ToolchainReport {
+    pub ffmpeg: BinaryReport,
+    pub ffprobe: BinaryReport,
+    pub capabilities: Vec<Capability>,
+    /// Incompatibilities that stop the app from working
+    pub problems: Vec<String>,
+    /// Missing optional features and other things worth knowing
+    pub warnings: Vec<String>,
+    /// No problems were found
+    pub ok: bool,
+}
+
+/// Validates a pair of binaries; `None` means none was configured or found
+pub fn validate_toolchain(
+    ffmpeg_path: Option<&Path>,
+    ffprobe_path: Option<&Path>,
+) -> ToolchainReport {
+    let mut report = ToolchainReport::default();
+
+    report.ffmpeg = inspect_binary(ffmpeg_path, "ffmpeg", "FFmpeg", &mut report.problems);
+    report.ffprobe = inspect_binary(ffprobe_path, "ffprobe", "FFprobe", &mut report.problems);
+
+    if let (Some(ffmpeg), Some(ffprobe)) = (&report.ffmpeg.version, &report.ffprobe.version) {
+        if ffmpeg != ffprobe {
+            report.warnings.push(format!(
+                "FFmpeg {} and FFprobe {} come from different builds; use the pair shipped together",
+                ffmpeg, ffprobe
+            ));
+        }
+    }
+
+    if let (true, Some(path)) = (report.ffmpeg.runs, ffmpeg_path) {
+        let filters = parse_filters(&run_listing(path, "-filters"));
+        let encoders = parse_table(&run_listing(path, "-encoders"));
+        let pixel_formats = parse_table(&run_listing(path, "-pix_fmts"));
+        report.capabilities = check_capabilities(&filters, &encoders, &pixel_formats);
+
+        for capability in report.capabilities.iter().filter(|c| !c.available) {
+            let message = format!(
+                "FFmpeg lacks the {} {}, needed for {}",
+                capability.name,
+                kind_label(capability.kind),
+                capability.purpose
+            );
+            if capability.required {
+                report.problems.push(message);
+            } else {
+                report.warnings.push(message);
+            }
+        }
+    }
+
+    report.ok = report.problems.is_empty();
+    report
+}
+
+/// Whether the configured ffmpeg has a filter. The listing is read once per run.
+pub fn has_filter(name: &str) -> bool {
+    static FILTERS: OnceLock<Vec<String>> = OnceLock::new();
+    FILTERS
+        .get_or_init(|| {
+            get_ffmpeg_path()
+                .map(|path| parse_filters(&run_listing(&path, "-filters")))
+                .unwrap_or_default()
+        })
+        .iter()
+        .any(|filter| filter == name)
+}
+
+fn kind_label(kind: CapabilityKind) -> &'static str {
+    match kind {
+        CapabilityKind::Filter => "filter",
+        CapabilityKind::Encoder => "encoder",
+        CapabilityKind::PixelFormat => "pixel format",
+    }
+}
+
+/// Runs `-version` and checks the banner names the expected program
+fn inspect_binary(
+    path: Option<&Path>,
+    program: &str,
+    label: &str,
+    problems: &mut Vec<String>,
+) -> BinaryReport {
+    let Some(path) = path else {
+        problems.push(format!(
+            "{} was not found. Install FFmpeg or set its path.",
+            label
+        ));
+        return BinaryReport::default();
+    };
+    let mut report = BinaryReport {
+        path: Some(path.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+
+    let output = match Command::new(path)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            problems.push(format!(
+                "{} at {} exited with {} on -version",
+                label,
+                path.display(),
+                output.status
+            ));
+            return report;
+        }
+        Err(e) => {
+            problems.push(format!(
+                "{} at {} could not be run: {}",
+                label,
+                path.display(),
+                e
+            ));
+            return report;
+        }
+    };
+
+    let banner = String::from_utf8_lossy(&output.stdout);
+    let Some((name, version)) = parse_version_banner(&banner) else {
+        problems.push(format!(
+            "{} does not look like {}: unrecognized -version output",
+            path.display(),
+            label
+        ));
+        return report;
+    };
+    report.runs = true;
+
+    if name != program {
+        problems.push(format!("{} is {}, not {}", path.display(), name, program));
+    }
+
+    report.major_version = major_version(&version);
+    if let Some(major) = report
+        .major_version
+        .filter(|&major| major < MIN_MAJOR_VERSION)
+    {
+        problems.push(format!(
+            "{} {} is too old (major version {}); version {}.0 or newer is required",
+            label, version, major, MIN_MAJOR_VERSION
+        ));
+    }
+    report.version = Some(version);
+    report.configuration = parse_configuration(&banner);
+    report
+}
+
+/// Program name and version from the first line of `-version` output,
+/// e.g. "ffmpeg version 6.1.1-3ubuntu5 Copyright ..."
+fn parse_version_banner(banner: &str) -> Option<(String, String)> {
+    let mut tokens = banner.lines().next()?.split_whitespace();
+    let name = tokens.next()?;
+    if tokens.next()? != "version" {
+        return None;
+    }
+    Some((name.to_string(), tokens.next()?.to_string()))
+}
+
+/// Major version of release builds ("6.1.1", "n6.1", "4.4.2-0ubuntu0.22.04.1");
+/// None for git snapshots ("N-113000-g…")
+fn major_version(version: &str) -> Option<u32> {
+    let digits: String = version
+        .trim_start_matches('n')
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Flags from the "configuration:" line of the banner
+fn parse_configuration(banner: &str) -> Vec<String> {
+    banner
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("configuration:"))
+        .map(|flags| flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn run_listing(path: &Path, flag: &str) -> String {
+    Command::new(path)
+        .args(["-hide_banner", flag])
+        .stdin(Stdio::null())
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        .unwrap_or_default()
+}
+
+/// Filter names from `ffmpeg -filters`, whose entries look like
+/// " TSC select            V->N       Select video frames to pass or not."
+fn parse_filters(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                [_, name, io, ..] if io.contains("->") => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Names from the second column of `-encoders` and `-pix_fmts` tables,
+/// which start after a dashed separator line
+fn parse_table(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1).map(String::from))
+        .collect()
+}
+
+fn check_capabilities(
+    filters: &[String],
+    encoders: &[String],
+    pixel_formats: &[String],
+) -> Vec<Capability> {
+    REQUIREMENTS
+        .iter()
+        .map(|requirement| {
+            let available = match requirement.kind {
+                CapabilityKind::Filter => filters,
+                CapabilityKind::Encoder => encoders,
+                CapabilityKind::PixelFormat => pixel_formats,
+            };
+            Capability {
+                kind: requirement.kind,
+                name: requirement.name.to_string(),
+                purpose: requirement.purpose.to_string(),
+                required: requirement.required,
+                available: available.iter().any(|name| name == requirement.name),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_banner() {
+        let banner =
+            "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\n\
+                      built with gcc 13 (Ubuntu 13.2.0-23ubuntu3)\n\
+                      configuration: --prefix=/usr --enable-gpl --enable-libx264\n";
+        assert_eq!(
+            parse_version_banner(banner),
+            Some(("ffmpeg".to_string(), "6.1.1-3ubuntu5".to_string()))
+        );
+        assert_eq!(
+            parse_configuration(banner),
+            vec!["--prefix=/usr", "--enable-gpl", "--enable-libx264"]
+        );
+
+        assert_eq!(major_version("6.1.1-3ubuntu5"), Some(6));
+        assert_eq!(major_version("n4.4"), Some(4));
+        assert_eq!(major_version("N-113000-g1234abcd"), None);
+        assert_eq!(parse_version_banner("usage: something"), None);
+    }
+
+    #[test]
+    fn test_parse_listings() {
+        let filters = "Filters:\n  T.. = Timeline support\n  A = Audio input/output\n\
+                       TSC select            V->N       Select video frames to pass or not.\n \
+                       ..C scale             V->V       Scale the input video size.\n";
+        assert_eq!(parse_filters(filters), vec!["select", "scale"]);
+
+        let encoders = "Encoders:\n V..... = Video\n ------\n \
+                        V....D mjpeg                MJPEG (Motion JPEG)\n \
+                        V....D png                  PNG (Portable Network Graphics) image\n";
+        assert_eq!(parse_table(encoders), vec!["mjpeg", "png"]);
+    }
+
+    #[test]
+    fn test_missing_capabilities() {
+        let names = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let capabilities = check_capabilities(
+            &names(&["select", "scale", "setsar", "transpose", "hflip", "vflip"]),
+            &names(&["rawvideo", "mjpeg"]),
+            &names(&["gray", "rgb24"]),
+        );
+
+        let missing = |required: bool| {
+            capabilities
+                .iter()
+                .filter(|c| !c.available && c.required == required)
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(missing(true), vec!["png"]);
+        assert!(missing(false).contains(&"tonemap"));
+    }
+}
@@ -1,33 +1,620 @@
+use crate::hwaccel::{file_decode, hwaccel_args, record_failure, HwAccel};
+use crate::interlace::detect_interlacing;
+use crate::jobs::{CancelToken, Cancelled, JobProcess};
+use crate::location::{parse_iso6709, GeoLocation};
+use crate::settings::AppSettings;
+use crate::telemetry::Telemetry;
+use crate::timecode::parse_start_timecode;
+use crate::toolchain::has_filter;
 use anyhow::{Context, Result};
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage, RgbImage};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::sync::OnceLock;
-use crate::settings::AppSettings;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdout, Command, Output, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct VideoInfo {
     pub duration: f64,
+    /// Nominal frame rate (`r_frame_rate`), the rate timecode counts at
     pub fps: f64,
+    /// Frames actually shown per second, from the frame timestamps once the
+    /// frame index is built, otherwise from the container
+    pub avg_fps: f64,
+    /// Frame durations vary, as in screen and phone recordings
+    pub is_vfr: bool,
+    /// Coded frame size as stored in the stream
     pub width: u32,
     pub height: u32,
     pub total_frames: u32,
+    /// Absolute index of the analyzed stream within the container
+    pub stream_index: usize,
+    /// Clockwise rotation (0, 90, 180 or 270) a player applies from the display matrix
+    pub rotation: u32,
+    /// Sample (pixel) aspect ratio, 1.0 for square pixels
+    pub sample_aspect_ratio: f64,
+    /// Display aspect ratio of the upright picture
+    pub display_aspect_ratio: f64,
+    /// Frame size as shown by a player, after pixel aspect correction and rotation
+    pub display_width: u32,
+    pub display_height: u32,
+    pub codec_name: Option<String>,
+    pub profile: Option<String>,
+    pub pix_fmt: Option<String>,
+    /// Bits per color component (8, 10, 12...)
+    pub bit_depth: Option<u32>,
+    /// Chroma subsampling derived from the pixel format, e.g. "4:2:0"
+    pub chroma_subsampling: Option<String>,
+    pub color_primaries: Option<String>,
+    pub color_transfer: Option<String>,
+    pub color_range: Option<String>,
+    pub color_space: Option<String>,
+    /// True for PQ (HDR10/Dolby Vision) and HLG transfer characteristics
+    pub is_hdr: bool,
+    /// "PQ" or "HLG" when `is_hdr` is set
+    pub hdr_format: Option<String>,
+    /// Video stream bitrate in bits/s, falling back to the container bitrate
+    pub bit_rate: Option<u64>,
+    pub container_format: Option<String>,
+    /// Container creation time as written by the camera (ISO 8601), in
+    /// local time with its UTC offset where the camera records one
+    pub creation_time: Option<String>,
+    /// Recording location from ISO 6709 container tags
+    pub location: Option<GeoLocation>,
+    /// SMPTE timecode of the first frame, from a tmcd track or timecode tag
+    pub timecode: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub has_audio: bool,
+    /// First subtitle stream, where DJI drones also write their telemetry
+    pub subtitle_stream: Option<usize>,
+    /// Every video stream in the container
+    pub video_streams: Vec<VideoStreamInfo>,
+    /// Human readable notes about inputs likely to give poor results
+    pub warnings: Vec<String>,
+    /// Hardware decoder used for this stream, None when decoding in software
+    pub hw_decode: Option<HwAccel>,
+    /// Hardware decoders that failed on this stream, with the reason
+    pub hw_decode_fallbacks: Vec<String>,
+    /// Fields are woven into frames, from `idet` or else the container flags
+    pub interlaced: bool,
+    /// "tff", "bff" or "progressive" when known
+    pub field_order: Option<String>,
+}
+
+/// Tone-mapping curve used to bring HDR frames down to SDR before scoring and JPEG export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapping {
+    /// Hable on HDR sources, nothing on SDR sources
+    #[default]
+    Auto,
+    /// Pass HDR values through untouched
+    Off,
+    Hable,
+    Reinhard,
+    Mobius,
+    Clip,
+}
+
+/// Deinterlacer applied before scoring and export, so combing between fields
+/// is not scored as detail
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deinterlace {
+    /// bwdif on videos detected as interlaced, nothing on progressive ones
+    #[default]
+    Auto,
+    Off,
+    /// Always deinterlace with bwdif (motion adaptive, sharper)
+    Bwdif,
+    /// Always deinterlace with yadif (for ffmpeg builds without bwdif)
+    Yadif,
+}
+
+impl Deinterlace {
+    /// Name of the ffmpeg filter to deinterlace with, or None
+    fn filter(self, info: &VideoInfo) -> Option<&'static str> {
+        match self {
+            Deinterlace::Auto if info.interlaced => Some("bwdif"),
+            Deinterlace::Auto | Deinterlace::Off => None,
+            Deinterlace::Bwdif => Some("bwdif"),
+            Deinterlace::Yadif => Some("yadif"),
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Name of the algorithm for ffmpeg's `tonemap` filter, or None when no tone mapping applies
+    fn algorithm(self, info: &VideoInfo) -> Option<&'static str> {
+        if !info.is_hdr {
+            return None;
+        }
+        match self {
+            ToneMapping::Off => None,
+            ToneMapping::Auto | ToneMapping::Hable => Some("hable"),
+            ToneMapping::Reinhard => Some("reinhard"),
+            ToneMapping::Mobius => Some("mobius"),
+            ToneMapping::Clip => Some("clip"),
+        }
+    }
+}
+
+/// How frames should be decoded, on top of what `VideoInfo` says about the stream
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub tone_mapping: ToneMapping,
+    pub deinterlace: Deinterlace,
+    /// Keep 16 bits per component (RGB48) and skip tone mapping so 10/12-bit
+    /// sources retain their full precision. Only meaningful for PNG/TIFF output.
+    pub high_bit_depth: bool,
+    /// Downscale frames during decode so their long edge is at most this many
+    /// pixels. Smaller frames are left alone.
+    pub analysis_resolution: Option<u32>,
+    /// Job the decode belongs to; cancelling it kills the ffmpeg processes
+    pub cancel: CancelToken,
+}
+
+impl DecodeOptions {
+    /// Size of the frames these options produce: the display size, reduced to
+    /// the analysis resolution if one is set (rounded to even dimensions)
+    pub fn output_dimensions(&self, info: &VideoInfo) -> (u32, u32) {
+        let (width, height) = (info.display_width, info.display_height);
+        match self.analysis_resolution {
+            Some(target) if target > 0 && width.max(height) > target => {
+                let scale = target as f64 / width.max(height) as f64;
+                let even = |v: u32| (((v as f64 * scale) / 2.0).round() as u32 * 2).max(2);
+                (even(width), even(height))
+            }
+            _ => (width, height),
+        }
+    }
+
+    /// Full ffmpeg filter chain for these options: deinterlacing, tone mapping
+    /// (it needs the original color metadata), then the display transforms.
+    pub fn filters(&self, info: &VideoInfo) -> Vec<String> {
+        let mut filters: Vec<String> = self.deinterlace_filter(info).into_iter().collect();
+        filters.extend(self.frame_filters(info));
+        filters
+    }
+
+    /// Filter chain that keeps only the frames matching a `select` expression.
+    /// Selecting first spares the dropped frames tone mapping, scaling and
+    /// rotation, but the deinterlacer has to see them: it interpolates from
+    /// the neighbouring frames.
+    pub fn select_filters(&self, info: &VideoInfo, select_expression: &str) -> Vec<String> {
+        let mut filters: Vec<String> = self.deinterlace_filter(info).into_iter().collect();
+        filters.push(format!("select='{}'", select_expression));
+        filters.extend(self.frame_filters(info));
+        filters
+    }
+
+    /// One output frame per input frame, so frame numbers stay valid
+    fn deinterlace_filter(&self, info: &VideoInfo) -> Option<String> {
+        let filter = self.deinterlace.filter(info)?;
+        let parity = match info.field_order.as_deref() {
+            Some("tff") => "tff",
+            Some("bff") => "bff",
+            _ => "auto",
+        };
+        Some(format!(
+            "{}=mode=send_frame:parity={}:deint=all",
+            filter, parity
+        ))
+    }
+
+    fn frame_filters(&self, info: &VideoInfo) -> Vec<String> {
+        let mut filters = Vec::new();
+
+        if !self.high_bit_depth && self.tone_mapping != ToneMapping::Off {
+            let algorithm = self.tone_mapping.algorithm(info);
+            let zscale = algorithm.is_some() && has_filter("zscale");
+            filters.extend(tone_map_filters(
+                info.color_transfer.as_deref(),
+                algorithm,
+                zscale,
+            ));
+        }
+
+        filters.extend(info.display_filters());
+
+        // Area averaging keeps fine detail proportional instead of aliasing it
+        let (width, height) = self.output_dimensions(info);
+        if (width, height) != (info.display_width, info.display_height) {
+            filters.push(format!("scale={}:{}:flags=area", width, height));
+        }
+
+        filters
+    }
+}
+
+/// Filters bringing frames down to SDR BT.709. PQ and HLG need zscale, which
+/// comes from an optional library many ffmpeg builds leave out; `colorspace`
+/// cannot linearize either curve, so without zscale they pass through as-is and
+/// `input_warnings` says so. `colorspace` only handles the BT.2020 SDR transfers.
+fn tone_map_filters(transfer: Option<&str>, algorithm: Option<&str>, zscale: bool) -> Vec<String> {
+    match (transfer, algorithm) {
+        // Linearize, map to BT.709 primaries, compress highlights, re-encode BT.709
+        // gamma at the full range the RGB output uses
+        (_, Some(algorithm)) if zscale => vec![
+            "zscale=t=linear:npl=100".to_string(),
+            "format=gbrpf32le".to_string(),
+            "zscale=p=bt709".to_string(),
+            format!("tonemap=tonemap={}:desat=0", algorithm),
+            "zscale=t=bt709:m=bt709:r=pc".to_string(),
+        ],
+        (Some(trc @ ("bt2020-10" | "bt2020-12")), _) => vec![format!(
+            "colorspace=all=bt709:iall=bt2020:itrc={}:range=pc:fast=1",
+            trc
+        )],
+        _ => Vec::new(),
+    }
+}
+
+/// Summary of one video stream in a container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoStreamInfo {
+    /// Absolute stream index within the container
+    pub index: usize,
+    pub codec_name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub bit_rate: Option<u64>,
+    pub pix_fmt: Option<String>,
+    /// Handler or title tag, often the only hint of what a secondary track is
+    pub title: Option<String>,
+    /// Embedded cover art or thumbnail rather than real video
+    pub attached_pic: bool,
+}
+
+impl VideoInfo {
+    /// ffmpeg filters that turn a decoded frame into what a player would show:
+    /// anamorphic pixels are stretched to square ones, then rotation is applied.
+    /// Decoding runs with `-noautorotate` so this chain is the only place rotation happens.
+    pub fn display_filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+
+        // A zero ratio means unknown (e.g. metadata from an older serialized result)
+        if self.sample_aspect_ratio > 0.0 && (self.sample_aspect_ratio - 1.0).abs() > 1e-3 {
+            // Dimensions before rotation
+            let (w, h) = if self.rotation % 180 == 90 {
+                (self.display_height, self.display_width)
+            } else {
+                (self.display_width, self.display_height)
+            };
+            filters.push(format!("scale={}:{}:flags=lanczos", w, h));
+            filters.push("setsar=1".to_string());
+        }
+
+        match self.rotation {
+            90 => filters.push("transpose=clock".to_string()),
+            180 => {
+                filters.push("hflip".to_string());
+                filters.push("vflip".to_string());
+            }
+            270 => filters.push("transpose=cclock".to_string()),
+            _ => {}
+        }
+
+        filters
+    }
+}
+
+/// Whether a frame's score came from decoded pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameStatus {
+    #[default]
+    Decoded,
+    /// Decoded by a retry after the first attempt failed
+    Recovered,
+    /// Could not be decoded; the sharpness of 0 is not a score
+    Failed,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FrameData {
     pub frame_number: usize,
     pub timestamp: f64,
     pub sharpness: f64,
     pub path: Option<String>,
+    /// Sampled by the adaptive refinement pass rather than the regular grid
+    #[serde(default)]
+    pub refined: bool,
+    #[serde(default)]
+    pub status: FrameStatus,
+    /// Last decode error, for recovered and failed frames
+    #[serde(default)]
+    pub error: Option<String>,
+    /// SMPTE timecode, for videos with a start timecode
+    #[serde(default)]
+    pub timecode: Option<String>,
+    /// Drone position, gimbal and exposure from DJI SRT telemetry
+    #[serde(default)]
+    pub telemetry: Option<Telemetry>,
+}
+
+impl FrameData {
+    /// Has a real sharpness score, so it can be used for thresholds and selection
+    pub fn is_decoded(&self) -> bool {
+        self.status != FrameStatus::Failed
+    }
+}
+
+/// Presentation timestamps for every frame of a video stream, in display order.
+/// Frame numbers used throughout the app are indices into this table, so they
+/// stay correct on variable-frame-rate footage where `n / fps` drifts.
+#[derive(Debug, Clone)]
+pub struct FrameIndex {
+    /// PTS in seconds of each frame, indexed by frame number
+    pub timestamps: Vec<f64>,
+    /// Frame numbers of keyframes
+    pub keyframes: Vec<usize>,
+    /// Container start time; ffmpeg's `-ss` is relative to this
+    pub seek_base: f64,
+}
+
+impl FrameIndex {
+    /// Builds an index from (pts, is_keyframe) pairs in any order
+    pub fn from_packets(mut packets: Vec<(f64, bool)>, seek_base: f64) -> Self {
+        // Packets arrive in decode order; display order is sorted by PTS
+        packets.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let timestamps = packets.iter().map(|&(pts, _)| pts).collect();
+        let keyframes = packets
+            .iter()
+            .enumerate()
+            .filter(|(_, &(_, key))| key)
+            .map(|(i, _)| i)
+            .collect();
+
+        Self {
+            timestamps,
+            keyframes,
+            seek_base,
+        }
+    }
+
+    /// Average frame rate between the first and last frame, and whether
+    /// frame durations vary. A frame duration counts as off when it differs
+    /// from the median by more than 15% plus a millisecond of timestamp
+    /// rounding. Off frames covering up to 5% of the time, such as the odd
+    /// dropped frame, are tolerated.
+    pub fn frame_rate(&self) -> Option<(f64, bool)> {
+        let n = self.timestamps.len();
+        let span = self.timestamps.last()? - self.start_time();
+        if n < 2 || span <= 0.0 {
+            return None;
+        }
+
+        let mut durations: Vec<f64> = self.timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+        durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median = durations[durations.len() / 2];
+        let tolerance = median * 0.15 + 0.001;
+        let off: f64 = durations
+            .iter()
+            .filter(|&&d| (d - median).abs() > tolerance)
+            .sum();
+
+        Some(((n - 1) as f64 / span, off > span * 0.05))
+    }
+
+    /// Fallback index for streams without usable timestamps: assumes constant frame rate
+    pub fn uniform(total_frames: usize, fps: f64) -> Self {
+        Self {
+            timestamps: (0..total_frames).map(|n| n as f64 / fps).collect(),
+            keyframes: vec![0],
+            seek_base: 0.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    /// PTS of the first frame
+    pub fn start_time(&self) -> f64 {
+        self.timestamps.first().copied().unwrap_or(0.0)
+    }
+
+    /// True PTS of a frame, extrapolating past the end if the index is short
+    pub fn timestamp(&self, frame_number: usize) -> f64 {
+        match self.timestamps.get(frame_number) {
+            Some(&pts) => pts,
+            None => {
+                let n = self.timestamps.len();
+                if n >= 2 {
+                    let step = (self.timestamps[n - 1] - self.timestamps[0]) / (n - 1) as f64;
+                    self.timestamps[n - 1] + step * (frame_number + 1 - n) as f64
+                } else {
+                    self.start_time()
+                }
+            }
+        }
+    }
+
+    /// First frame shown at or after `time` seconds from the start of the video
+    pub fn frame_at_time(&self, time: f64) -> usize {
+        let target = self.start_time() + time - 1e-6;
+        self.timestamps.partition_point(|&pts| pts < target)
+    }
+
+    /// Number of frames shown at or before `time` seconds from the start of the
+    /// video, so a range ending at `time` includes a frame shown exactly then
+    pub fn frames_until(&self, time: f64) -> usize {
+        let target = self.start_time() + time + 1e-6;
+        self.timestamps.partition_point(|&pts| pts <= target)
+    }
+
+    /// Frame with the PTS closest to `pts`, if that is less than half a frame away
+    pub fn frame_at_pts(&self, pts: f64) -> Option<usize> {
+        let after = self.timestamps.partition_point(|&t| t < pts);
+        let distance = |n: usize| (self.timestamps[n] - pts).abs();
+        let nearest = [after.checked_sub(1), Some(after)]
+            .into_iter()
+            .flatten()
+            .filter(|&n| n < self.timestamps.len())
+            .min_by(|&a, &b| distance(a).partial_cmp(&distance(b)).unwrap())?;
+
+        let gap = [nearest.checked_sub(1), Some(nearest + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|n| self.timestamps.get(n))
+            .map(|&t| (t - self.timestamps[nearest]).abs())
+            .fold(f64::INFINITY, f64::min);
+        (distance(nearest) <= (gap / 2.0).max(1e-3)).then_some(nearest)
+    }
+
+    /// Position to pass to ffmpeg's input `-ss` so that accurate seeking lands
+    /// exactly on `frame_number`. Seeks halfway between the previous frame and
+    /// this one, so float rounding in the PTS cannot skip or repeat a frame.
+    pub fn seek_position(&self, frame_number: usize) -> f64 {
+        let pts = self.timestamp(frame_number);
+        let target = if frame_number == 0 {
+            pts
+        } else {
+            (self.timestamp(frame_number - 1) + pts) / 2.0
+        };
+        (target - self.seek_base).max(0.0)
+    }
+}
+
+/// Top level of `ffprobe -show_streams -show_format -of json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeOutput {
+    streams: Vec<FfprobeStream>,
+    format: FfprobeFormat,
+}
+
+/// One entry of ffprobe's `streams` array. ffprobe prints most numeric
+/// fields as strings, so they are kept as strings and parsed on use.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeStream {
+    index: usize,
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    codec_tag_string: Option<String>,
+    profile: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    sample_aspect_ratio: Option<String>,
+    display_aspect_ratio: Option<String>,
+    color_range: Option<String>,
+    color_space: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    field_order: Option<String>,
+    r_frame_rate: Option<String>,
+    avg_frame_rate: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    nb_frames: Option<String>,
+    disposition: FfprobeDisposition,
+    tags: HashMap<String, String>,
+    side_data_list: Vec<FfprobeSideData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeDisposition {
+    attached_pic: u8,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeSideData {
+    rotation: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FfprobeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    tags: HashMap<String, String>,
+}
+
+impl FfprobeStream {
+    fn is_video(&self) -> bool {
+        self.codec_type.as_deref() == Some("video")
+    }
+
+    fn is_audio(&self) -> bool {
+        self.codec_type.as_deref() == Some("audio")
+    }
+
+    fn frame_rate(&self) -> Option<f64> {
+        self.r_frame_rate.as_deref().and_then(parse_ratio)
+    }
+
+    fn average_frame_rate(&self) -> Option<f64> {
+        self.avg_frame_rate.as_deref().and_then(parse_ratio)
+    }
+
+    fn bit_rate(&self) -> Option<u64> {
+        self.bit_rate.as_deref().and_then(|s| s.parse().ok())
+    }
+
+    /// Clockwise display rotation. Display matrix side data holds a
+    /// counter-clockwise angle; older files carry a clockwise `rotate` tag instead.
+    fn rotation(&self) -> u32 {
+        self.side_data_list
+            .iter()
+            .find_map(|side_data| side_data.rotation)
+            .map(|ccw| -ccw)
+            .or_else(|| tag(&self.tags, &["rotate"]).and_then(|s| s.parse::<f64>().ok()))
+            .map(normalize_rotation)
+            .unwrap_or(0)
+    }
+
+    fn summary(&self) -> VideoStreamInfo {
+        VideoStreamInfo {
+            index: self.index,
+            codec_name: self.codec_name.clone(),
+            width: self.width.unwrap_or(0),
+            height: self.height.unwrap_or(0),
+            fps: self.frame_rate().unwrap_or(0.0),
+            bit_rate: self.bit_rate(),
+            pix_fmt: self.pix_fmt.clone(),
+            title: tag(&self.tags, &["title", "handler_name"]),
+            attached_pic: self.disposition.attached_pic != 0,
+        }
+    }
+}
+
+/// Case-insensitive lookup of the first present tag out of several candidate keys
+fn tag(tags: &HashMap<String, String>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        tags.iter()
+            .find(|(k, v)| k.eq_ignore_ascii_case(key) && !v.trim().is_empty())
+            .map(|(_, v)| v.trim().to_string())
+    })
 }
 
 // Cache for FFmpeg binary paths to avoid repeated lookups
 static FFMPEG_PATH: OnceLock<PathBuf> = OnceLock::new();
 static FFPROBE_PATH: OnceLock<PathBuf> = OnceLock::new();
 
+// Cache of frame indexes keyed by file path, stream and modification time
+type FrameIndexKey = (PathBuf, usize, Option<SystemTime>);
+static FRAME_INDEX_CACHE: OnceLock<Mutex<HashMap<FrameIndexKey, Arc<FrameIndex>>>> =
+    OnceLock::new();
+
 /// Finds the FFmpeg binary path
 fn find_ffmpeg_binary() -> Result<PathBuf> {
     eprintln!("\n=== Looking for FFmpeg ===");
@@ -155,7 +742,7 @@ fn find_ffprobe_binary() -> Result<PathBuf> {
 }
 
 /// Gets or initializes the FFmpeg binary path
-fn get_ffmpeg_path() -> Result<PathBuf> {
+pub(crate) fn get_ffmpeg_path() -> Result<PathBuf> {
     // Try to get cached path
     if let Some(path) = FFMPEG_PATH.get() {
         return Ok(path.clone());
@@ -180,15 +767,19 @@ fn get_ffprobe_path() -> Result<PathBuf> {
     Ok(path)
 }
 
-/// Extracts video metadata using ffprobe
-pub fn get_video_info(video_path: &Path) -> Result<VideoInfo> {
+/// Extracts video metadata using ffprobe.
+/// `stream_index` is the absolute index of the video stream to describe;
+/// `None` picks the first video stream that is not embedded cover art.
+pub fn get_video_info(video_path: &Path, stream_index: Option<usize>) -> Result<VideoInfo> {
     let ffprobe_path = get_ffprobe_path()?;
     let output = Command::new(&ffprobe_path)
         .args([
-            "-v", "error",
-            "-select_streams", "v:0",
-            "-show_entries", "stream=width,height,r_frame_rate,duration,nb_frames",
-            "-of", "json",
+            "-v",
+            "error",
+            "-show_streams",
+            "-show_format",
+            "-of",
+            "json",
             video_path.to_str().unwrap(),
         ])
         .output()
@@ -199,261 +790,1508 @@ pub fn get_video_info(video_path: &Path) -> Result<VideoInfo> {
         anyhow::bail!("ffprobe failed: {}", error);
     }
 
-    let json_str = String::from_utf8_lossy(&output.stdout);
-    let json: serde_json::Value = serde_json::from_str(&json_str)
-        .context("Failed to parse ffprobe output")?;
+    let probe: FfprobeOutput =
+        serde_json::from_slice(&output.stdout).context("Failed to parse ffprobe output")?;
 
-    let stream = &json["streams"][0];
+    video_info_from_probe(&probe, stream_index)
+}
 
-    let width = stream["width"].as_u64().context("Missing width")? as u32;
-    let height = stream["height"].as_u64().context("Missing height")? as u32;
+/// Replaces the container's field order flags, which are often missing or
+/// wrong, with what `idet` sees in the frames. Only needed when deinterlacing
+/// is automatic. Interlaced formats stop at 1080 lines; larger videos skip this.
+/// Fails only when the job is cancelled; other detection errors keep the flags.
+pub fn detect_field_order(
+    video_path: &Path,
+    info: &mut VideoInfo,
+    cancel: &CancelToken,
+) -> Result<()> {
+    if info.height > 1080 {
+        return Ok(());
+    }
+    let detection = match detect_interlacing(video_path, info.stream_index, cancel) {
+        Ok(detection) => detection,
+        Err(e) if e.downcast_ref::<Cancelled>().is_some() => return Err(e),
+        Err(e) => {
+            eprintln!("Interlace detection failed: {}", e);
+            None
+        }
+    };
+    if let Some(order) = detection.and_then(|d| d.field_order()) {
+        info.interlaced = order != "progressive";
+        info.field_order = Some(order.to_string());
+        info.warnings = input_warnings(info);
+    }
+    Ok(())
+}
 
-    // Parse frame rate (e.g., "30/1" -> 30.0)
-    let fps_str = stream["r_frame_rate"].as_str().context("Missing frame rate")?;
-    let fps_parts: Vec<&str> = fps_str.split('/').collect();
-    let fps = if fps_parts.len() == 2 {
-        let num: f64 = fps_parts[0].parse()?;
-        let den: f64 = fps_parts[1].parse()?;
-        num / den
-    } else {
-        30.0 // default fallback
+/// Lists every video stream in a container so the user can pick one to analyze
+pub fn list_video_streams(video_path: &Path) -> Result<Vec<VideoStreamInfo>> {
+    Ok(get_video_info(video_path, None)?.video_streams)
+}
+
+/// Builds `VideoInfo` from parsed ffprobe output for the requested video stream
+fn video_info_from_probe(probe: &FfprobeOutput, stream_index: Option<usize>) -> Result<VideoInfo> {
+    let stream = match stream_index {
+        Some(index) => {
+            let stream = probe
+                .streams
+                .iter()
+                .find(|s| s.index == index)
+                .with_context(|| format!("Stream #{} does not exist", index))?;
+            if !stream.is_video() {
+                anyhow::bail!("Stream #{} is not a video stream", index);
+            }
+            stream
+        }
+        None => probe
+            .streams
+            .iter()
+            .find(|s| s.is_video() && s.disposition.attached_pic == 0)
+            .context("No video stream found")?,
     };
 
+    let width = stream.width.context("Missing width")?;
+    let height = stream.height.context("Missing height")?;
+
+    // Parse frame rate (e.g., "30/1" -> 30.0)
+    let fps = stream.frame_rate().unwrap_or(30.0);
+
     // Get duration (try from stream first, then format)
-    let duration = stream["duration"]
-        .as_str()
+    let duration = stream
+        .duration
+        .as_deref()
+        .or(probe.format.duration.as_deref())
         .and_then(|s| s.parse::<f64>().ok())
-        .unwrap_or_else(|| {
-            json["format"]["duration"]
-                .as_str()
-                .and_then(|s| s.parse::<f64>().ok())
-                .unwrap_or(0.0)
-        });
+        .unwrap_or(0.0);
+
+    // `r_frame_rate` is the finest timestamp step, which on variable frame
+    // rate recordings can be far above the rate frames are actually shown.
+    // A stream whose average rate is off from it is likely VFR; the frame
+    // index confirms this from the timestamps later. Field-rate streams report
+    // exactly twice the frame rate and are not VFR.
+    let avg_fps = stream.average_frame_rate().unwrap_or(fps);
+    let ratio = fps / avg_fps;
+    let is_vfr = (ratio - 1.0).abs() > 0.01 && (ratio - 2.0).abs() > 0.01;
 
     // Calculate total frames
-    let total_frames = stream["nb_frames"]
-        .as_str()
+    let total_frames = stream
+        .nb_frames
+        .as_deref()
+        .and_then(|s| s.parse::<u32>().ok())
+        .unwrap_or((duration * avg_fps).round() as u32);
+
+    let rotation = stream.rotation();
+    let sample_aspect_ratio = stream
+        .sample_aspect_ratio
+        .as_deref()
+        .and_then(parse_ratio)
+        .unwrap_or(1.0);
+
+    let (display_width, display_height) =
+        display_dimensions(width, height, sample_aspect_ratio, rotation);
+    let display_aspect_ratio = display_width as f64 / display_height.max(1) as f64;
+
+    let bit_depth = stream
+        .bits_per_raw_sample
+        .as_deref()
         .and_then(|s| s.parse::<u32>().ok())
-        .unwrap_or_else(|| (duration * fps) as u32);
+        .or_else(|| stream.pix_fmt.as_deref().and_then(pix_fmt_bit_depth));
 
-    Ok(VideoInfo {
+    // PQ covers HDR10 and Dolby Vision profile 8.1, HLG is what iPhones record
+    let hdr_format = match stream.color_transfer.as_deref() {
+        Some("smpte2084") => Some("PQ".to_string()),
+        Some("arib-std-b67") => Some("HLG".to_string()),
+        _ => None,
+    };
+
+    let bit_rate = stream.bit_rate().or_else(|| {
+        probe
+            .format
+            .bit_rate
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+    });
+
+    // Phones put make/model in QuickTime metadata keys, cameras in plain tags
+    let container_tags = &probe.format.tags;
+    // iPhones also record the local creation date, which EXIF needs
+    let creation_time = tag(
+        container_tags,
+        &["com.apple.quicktime.creationdate", "creation_time"],
+    )
+    .or_else(|| tag(&stream.tags, &["creation_time"]));
+    let location_keys = [
+        "com.apple.quicktime.location.ISO6709",
+        "location",
+        "location-eng",
+    ];
+    let location = tag(container_tags, &location_keys)
+        .or_else(|| tag(&stream.tags, &location_keys))
+        .and_then(|value| parse_iso6709(&value));
+    // Cameras tag the video stream or a tmcd data track; some muxers only the container
+    let timecode = tag(&stream.tags, &["timecode"])
+        .or_else(|| {
+            probe
+                .streams
+                .iter()
+                .filter(|s| s.codec_tag_string.as_deref() == Some("tmcd"))
+                .find_map(|s| tag(&s.tags, &["timecode"]))
+        })
+        .or_else(|| tag(container_tags, &["timecode"]))
+        .and_then(|tc| parse_start_timecode(&tc, fps));
+    let make = tag(
+        container_tags,
+        &[
+            "com.apple.quicktime.make",
+            "com.android.manufacturer",
+            "make",
+        ],
+    );
+    let model = tag(
+        container_tags,
+        &["com.apple.quicktime.model", "com.android.model", "model"],
+    );
+
+    // ffprobe gives the coded field first, then the displayed one ("tb": top
+    // coded first, bottom displayed first). Deinterlacers need display order.
+    let field_order = match stream.field_order.as_deref() {
+        Some("progressive") => Some("progressive"),
+        Some("tt") | Some("bt") => Some("tff"),
+        Some("bb") | Some("tb") => Some("bff"),
+        _ => None,
+    };
+
+    let mut info = VideoInfo {
         duration,
         fps,
+        avg_fps,
+        is_vfr,
         width,
         height,
         total_frames,
-    })
-}
+        stream_index: stream.index,
+        rotation,
+        sample_aspect_ratio,
+        display_aspect_ratio,
+        display_width,
+        display_height,
+        codec_name: stream.codec_name.clone(),
+        profile: stream.profile.clone(),
+        pix_fmt: stream.pix_fmt.clone(),
+        bit_depth,
+        chroma_subsampling: stream
+            .pix_fmt
+            .as_deref()
+            .and_then(pix_fmt_chroma_subsampling),
+        color_primaries: stream.color_primaries.clone(),
+        color_transfer: stream.color_transfer.clone(),
+        color_range: stream.color_range.clone(),
+        color_space: stream.color_space.clone(),
+        is_hdr: hdr_format.is_some(),
+        hdr_format,
+        bit_rate,
+        container_format: probe.format.format_name.clone(),
+        creation_time,
+        location,
+        timecode,
+        make,
+        model,
+        has_audio: probe.streams.iter().any(|s| s.is_audio()),
+        subtitle_stream: probe
+            .streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some("subtitle"))
+            .map(|s| s.index),
+        video_streams: probe
+            .streams
+            .iter()
+            .filter(|s| s.is_video())
+            .map(|s| s.summary())
+            .collect(),
+        warnings: Vec::new(),
+        hw_decode: None,
+        hw_decode_fallbacks: Vec::new(),
+        interlaced: field_order.is_some_and(|order| order != "progressive"),
+        field_order: field_order.map(String::from),
+    };
+    info.warnings = input_warnings(&info);
 
-/// Detects available hardware acceleration for FFmpeg
-fn detect_hw_accel() -> Vec<String> {
-    let mut accel_args = Vec::new();
+    Ok(info)
+}
 
-    // Try VideoToolbox (macOS)
-    #[cfg(target_os = "macos")]
-    {
-        accel_args.extend(vec!["-hwaccel".to_string(), "videotoolbox".to_string()]);
+/// Bits per component implied by an ffmpeg pixel format name
+fn pix_fmt_bit_depth(pix_fmt: &str) -> Option<u32> {
+    if pix_fmt.is_empty() {
+        return None;
     }
 
-    // Try CUDA (NVIDIA GPUs on Linux/Windows)
-    #[cfg(not(target_os = "macos"))]
+    // High bit depth formats end in an endianness suffix after the depth,
+    // e.g. yuv420p10le, p010le, gray12be, rgb48le
+    let Some(base) = pix_fmt
+        .strip_suffix("le")
+        .or_else(|| pix_fmt.strip_suffix("be"))
+    else {
+        return Some(8);
+    };
+    let digits_start = base.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let depth = match base[digits_start..].parse::<u32>() {
+        Ok(depth @ 9..=16) => depth,
+        // Packed RGB(A) names carry bits per pixel rather than per component
+        Ok(48) | Ok(64) => 16,
+        _ => 8,
+    };
+    Some(depth)
+}
+
+/// Chroma subsampling implied by an ffmpeg pixel format name
+fn pix_fmt_chroma_subsampling(pix_fmt: &str) -> Option<String> {
+    let subsampling = if pix_fmt.contains("420")
+        || pix_fmt.starts_with("nv12")
+        || pix_fmt.starts_with("p01")
     {
-        // Check if CUDA is available
-        if let Ok(ffmpeg_path) = get_ffmpeg_path() {
-            if Command::new(&ffmpeg_path).args(["-hwaccels"]).output()
-                .map(|out| String::from_utf8_lossy(&out.stdout).contains("cuda"))
-                .unwrap_or(false)
-            {
-                accel_args.extend(vec!["-hwaccel".to_string(), "cuda".to_string()]);
+        "4:2:0"
+    } else if pix_fmt.contains("422") || pix_fmt.starts_with("nv16") {
+        "4:2:2"
+    } else if pix_fmt.contains("444") || pix_fmt.starts_with("gbr") || pix_fmt.starts_with("rgb") {
+        "4:4:4"
+    } else if pix_fmt.contains("411") {
+        "4:1:1"
+    } else {
+        return None;
+    };
+    Some(subsampling.to_string())
+}
+
+/// Flags inputs that tend to produce poor reconstructions or surprising results
+fn input_warnings(info: &VideoInfo) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    // Bits per pixel per frame; below ~0.05 8-bit 4:2:0 footage is visibly mushy
+    if let Some(bit_rate) = info.bit_rate {
+        let pixels_per_second = info.width as f64 * info.height as f64 * info.fps;
+        if pixels_per_second > 0.0 {
+            let bits_per_pixel = bit_rate as f64 / pixels_per_second;
+            let eight_bit_420 = info.bit_depth.unwrap_or(8) <= 8
+                && info.chroma_subsampling.as_deref() == Some("4:2:0");
+            if eight_bit_420 && bits_per_pixel < 0.05 {
+                warnings.push(format!(
+                    "Low bitrate 8-bit 4:2:0 source ({:.1} Mbps, {:.3} bits/pixel): \
+                     compression artifacts may hide fine detail and lower sharpness scores",
+                    bit_rate as f64 / 1_000_000.0,
+                    bits_per_pixel
+                ));
             }
         }
     }
 
-    accel_args
+    if let Some(hdr_format) = &info.hdr_format {
+        if has_filter("zscale") {
+            warnings.push(format!(
+                "HDR ({}) source: frames are tone-mapped to SDR for analysis and JPEG export; \
+                 use 16-bit PNG/TIFF export to keep full precision",
+                hdr_format
+            ));
+        } else {
+            warnings.push(format!(
+                "HDR ({}) source, but ffmpeg lacks the zscale filter: tone mapping is unavailable, \
+                 so frames are analyzed and exported as washed-out SDR; use an ffmpeg build with \
+                 zscale or 16-bit PNG/TIFF export",
+                hdr_format
+            ));
+        }
+    }
+
+    if info.is_vfr {
+        warnings.push(format!(
+            "Variable frame rate ({:.2} fps on average, {:.2} fps nominal): \
+             sample by time for evenly spaced samples",
+            info.avg_fps, info.fps
+        ));
+    }
+
+    if info.interlaced {
+        warnings.push(format!(
+            "Interlaced source ({}): frames are deinterlaced before scoring and export, \
+             as combing between fields would otherwise score as sharp detail",
+            info.field_order.as_deref().unwrap_or("unknown field order")
+        ));
+    }
+
+    let real_streams = info
+        .video_streams
+        .iter()
+        .filter(|s| !s.attached_pic)
+        .count();
+    if real_streams > 1 {
+        warnings.push(format!(
+            "File contains {} video streams; analyzing stream #{}",
+            real_streams, info.stream_index
+        ));
+    }
+
+    warnings
+}
+
+/// Parses an ffprobe ratio such as "16:11" or "30000/1001".
+/// Returns None for unknown ("0:1", "N/A") or malformed ratios.
+fn parse_ratio(value: &str) -> Option<f64> {
+    let (num, den) = value.split_once([':', '/'])?;
+    let num: f64 = num.trim().parse().ok()?;
+    let den: f64 = den.trim().parse().ok()?;
+    if num <= 0.0 || den <= 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
+
+/// Snaps a clockwise angle in degrees to 0, 90, 180 or 270
+fn normalize_rotation(degrees: f64) -> u32 {
+    let quarter_turns = (degrees / 90.0).round() as i64;
+    (quarter_turns.rem_euclid(4) * 90) as u32
 }
 
-/// Extracts a single frame from a video at the specified frame number
-pub fn extract_frame(video_path: &Path, frame_number: usize, output_path: &Path) -> Result<()> {
-    // Get video info to calculate timestamp
-    let info = get_video_info(video_path)?;
-    let timestamp = frame_number as f64 / info.fps;
+/// Size of the picture as a player shows it. Non-square pixels are corrected
+/// by stretching the width (like ffmpeg's `scale=iw*sar:ih`), then rotation swaps axes.
+fn display_dimensions(
+    width: u32,
+    height: u32,
+    sample_aspect_ratio: f64,
+    rotation: u32,
+) -> (u32, u32) {
+    let mut display_width = width;
+    if (sample_aspect_ratio - 1.0).abs() > 1e-3 {
+        // Keep the width even, most encoders require it
+        display_width = ((width as f64 * sample_aspect_ratio / 2.0).round() as u32 * 2).max(2);
+    }
 
-    // Create output directory if it doesn't exist
-    if let Some(parent) = output_path.parent() {
-        fs::create_dir_all(parent)?;
+    if rotation % 180 == 90 {
+        (height, display_width)
+    } else {
+        (display_width, height)
     }
+}
 
-    // Build command with hardware acceleration
-    let ffmpeg_path = get_ffmpeg_path()?;
-    let mut cmd = Command::new(&ffmpeg_path);
-    let hw_accel = detect_hw_accel();
+/// Parses `ffprobe -of compact=p=0` packet output into (pts, is_keyframe) pairs
+/// and the container start time. Discarded packets and packets without a PTS are skipped.
+fn parse_packet_listing(listing: &str) -> (Vec<(f64, bool)>, Option<f64>) {
+    let mut packets = Vec::new();
+    let mut format_start = None;
+
+    for line in listing.lines() {
+        let mut pts = None;
+        let mut flags = None;
+        let mut start_time = None;
+
+        for field in line.split('|') {
+            match field.split_once('=') {
+                Some(("pts_time", value)) => pts = value.parse::<f64>().ok(),
+                Some(("flags", value)) => flags = Some(value),
+                Some(("start_time", value)) => start_time = value.parse::<f64>().ok(),
+                _ => {}
+            }
+        }
 
-    // Add hardware acceleration args if available
-    for arg in &hw_accel {
-        cmd.arg(arg);
+        match flags {
+            Some(flags) => {
+                if flags.contains('D') {
+                    continue;
+                }
+                if let Some(pts) = pts {
+                    packets.push((pts, flags.contains('K')));
+                }
+            }
+            None => {
+                if start_time.is_some() {
+                    format_start = start_time;
+                }
+            }
+        }
     }
 
-    // Add remaining args with threading support
+    (packets, format_start)
+}
+
+/// Scans the packets of a video stream with ffprobe and builds its frame index.
+/// This only demuxes (no decoding), so it is fast even for long recordings.
+fn build_frame_index(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<FrameIndex> {
+    let ffprobe_path = get_ffprobe_path()?;
+    let mut cmd = Command::new(&ffprobe_path);
     cmd.args([
-        "-threads", "1", // One thread per FFmpeg instance (we parallelize at process level)
-        "-ss", &timestamp.to_string(),
-        "-i", video_path.to_str().unwrap(),
-        "-vframes", "1",
-        "-q:v", "2", // High quality
-        "-y", // Overwrite output file
-        output_path.to_str().unwrap(),
+        "-v",
+        "error",
+        "-select_streams",
+        &stream_index.to_string(),
+        "-show_entries",
+        "packet=pts_time,flags:format=start_time",
+        "-of",
+        "compact=p=0",
+        video_path.to_str().unwrap(),
     ]);
-
-    let output = cmd.output()
-        .context("Failed to execute ffmpeg. Make sure FFmpeg is installed.")?;
+    let output = cancel.output(&mut cmd)?;
 
     if !output.status.success() {
         let error = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("ffmpeg failed: {}", error);
+        anyhow::bail!("ffprobe packet scan failed: {}", error);
+    }
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let (packets, format_start) = parse_packet_listing(&listing);
+
+    if packets.is_empty() {
+        // No usable timestamps (e.g. raw elementary streams), assume constant frame rate
+        let info = get_video_info(video_path, Some(stream_index))?;
+        return Ok(FrameIndex::uniform(info.total_frames as usize, info.fps));
     }
 
+    let seek_base = format_start.unwrap_or(0.0);
+    Ok(FrameIndex::from_packets(packets, seek_base))
+}
+
+/// Gets the frame index for a video stream, building it on first use
+pub fn get_frame_index(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+) -> Result<Arc<FrameIndex>> {
+    let modified = fs::metadata(video_path).and_then(|m| m.modified()).ok();
+    let key = (video_path.to_path_buf(), stream_index, modified);
+    let cache = FRAME_INDEX_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+    if let Some(index) = cache.lock().unwrap().get(&key) {
+        return Ok(index.clone());
+    }
+
+    // Build outside the lock so other videos are not blocked by a long scan
+    let index = Arc::new(build_frame_index(video_path, stream_index, cancel)?);
+    cache.lock().unwrap().insert(key, index.clone());
+    Ok(index)
+}
+
+/// Replaces the frame count and rate estimated from the container with the
+/// ones measured from the frame index
+pub fn apply_frame_index(info: &mut VideoInfo, index: &FrameIndex) {
+    info.total_frames = index.len() as u32;
+    if let Some((avg_fps, is_vfr)) = index.frame_rate() {
+        info.avg_fps = avg_fps;
+        info.is_vfr = is_vfr;
+    }
+    info.warnings = input_warnings(info);
+}
+
+/// Records how the video's stream is decoded. The first call for a file
+/// test-decodes it with each working hardware decoder, so this belongs to
+/// decoding frames rather than to reading metadata.
+pub fn apply_hw_decode(
+    video_path: &Path,
+    info: &mut VideoInfo,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let decode = file_decode(video_path, info.stream_index, cancel)?;
+    info.hw_decode = decode.method;
+    info.hw_decode_fallbacks = decode.fallbacks;
     Ok(())
 }
 
-/// Extracts a frame directly to memory (returns image data)
-pub fn extract_frame_to_memory(video_path: &Path, frame_number: usize) -> Result<DynamicImage> {
-    // Create temporary file
-    let temp_dir = std::env::temp_dir();
-    let temp_file = temp_dir.join(format!("frame_{}.jpg", frame_number));
+/// Runs a decode command built without `-hwaccel` using the video's
+/// hardware decoder. If the hardware decode fails, the video is switched to
+/// software decoding for good and the command is run again.
+fn output_with_fallback(
+    video_path: &Path,
+    stream_index: usize,
+    cancel: &CancelToken,
+    cmd: &Command,
+) -> Result<Output> {
+    let with_hwaccel = |hwaccel: &[String]| {
+        let mut cmd_with = Command::new(cmd.get_program());
+        cmd_with.args(hwaccel).args(cmd.get_args());
+        cmd_with
+    };
 
-    // Extract frame to temp file
-    extract_frame(video_path, frame_number, &temp_file)?;
+    let hwaccel = hwaccel_args(video_path, stream_index, cancel)?;
+    let output = cancel.output(&mut with_hwaccel(&hwaccel))?;
+    if output.status.success() || hwaccel.is_empty() {
+        return Ok(output);
+    }
 
-    // Load image from temp file
-    let img = image::open(&temp_file)
-        .context("Failed to load extracted frame")?;
+    record_failure(
+        video_path,
+        stream_index,
+        &String::from_utf8_lossy(&output.stderr),
+    );
+    cancel.output(&mut with_hwaccel(&[]))
+}
+
+/// Splits sorted frame numbers into segments that one ffmpeg process each can
+/// export in a single decode pass. Frames are grouped by the GOP they fall in;
+/// runs of adjacent GOPs are decoded together, since seeking past a GOP that
+/// has to be decoded anyway saves nothing, and long runs are split at GOP
+/// boundaries so that about `parallelism` segments can run side by side.
+pub fn plan_export_segments(
+    index: &FrameIndex,
+    frame_numbers: &[usize],
+    parallelism: usize,
+) -> Vec<Vec<usize>> {
+    // Frames grouped by the keyframe that starts their GOP
+    let mut groups: Vec<(usize, Vec<usize>)> = Vec::new();
+    for &frame in frame_numbers {
+        let gop = index
+            .keyframes
+            .partition_point(|&k| k <= frame)
+            .saturating_sub(1);
+        match groups.last_mut() {
+            Some((last, frames)) if *last == gop => frames.push(frame),
+            _ => groups.push((gop, vec![frame])),
+        }
+    }
 
-    // Clean up temp file
-    let _ = fs::remove_file(&temp_file);
+    // Runs of GOP groups without an unselected GOP between them
+    let mut runs: Vec<Vec<Vec<usize>>> = Vec::new();
+    let mut previous_gop = None;
+    for (gop, frames) in groups {
+        match runs.last_mut() {
+            Some(run) if previous_gop == Some(gop.saturating_sub(1)) => run.push(frames),
+            _ => runs.push(vec![frames]),
+        }
+        previous_gop = Some(gop);
+    }
 
-    Ok(img)
+    // Share out the workers in proportion to the number of GOPs in each run
+    let total_groups: usize = runs.iter().map(Vec::len).sum();
+    let mut segments = Vec::new();
+    for run in runs {
+        let pieces = (run.len() * parallelism.max(1))
+            .div_ceil(total_groups)
+            .clamp(1, run.len());
+        for chunk in run.chunks(run.len().div_ceil(pieces)) {
+            segments.push(chunk.concat());
+        }
+    }
+    segments
 }
 
-/// Extracts multiple frames in a single FFmpeg call for better performance
-pub fn extract_frames_to_memory_batch(
+/// Exports one segment of sorted frame numbers with a single ffmpeg process.
+/// ffmpeg seeks to the first frame, keeps only the selected ones and writes
+/// them under temporary sequential names, which are then renamed after the
+/// real frame numbers.
+fn export_segment(
     video_path: &Path,
+    info: &VideoInfo,
+    index: &FrameIndex,
+    options: &DecodeOptions,
     frame_numbers: &[usize],
-) -> Result<Vec<DynamicImage>> {
-    let temp_dir = std::env::temp_dir();
-    let batch_id = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
-    let temp_output_dir = temp_dir.join(format!("frame_batch_{}", batch_id));
-    fs::create_dir_all(&temp_output_dir)?;
-
-    // Create a filter expression for selecting specific frames
-    let select_expr = frame_numbers
-        .iter()
-        .map(|&frame_num| format!("eq(n\\,{})", frame_num))
-        .collect::<Vec<_>>()
-        .join("+");
+    output_dir: &Path,
+    format: &str,
+) -> Result<Vec<PathBuf>> {
+    let first = *frame_numbers.first().context("Empty export segment")?;
+    let temp_name = |i: usize| format!(".export-{:06}-{:06}.{}", first, i, format);
+
+    // Accurate seeking makes the first decoded frame `first`, so select by offset from it
+    let offsets: Vec<usize> = frame_numbers.iter().map(|&n| n - first).collect();
+    let filters = options.select_filters(info, &build_select_expression(&offsets));
 
-    // Build command with hardware acceleration
     let ffmpeg_path = get_ffmpeg_path()?;
     let mut cmd = Command::new(&ffmpeg_path);
-    let hw_accel = detect_hw_accel();
 
-    // Add hardware acceleration args
-    for arg in &hw_accel {
-        cmd.arg(arg);
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-threads",
+        "1", // One decoder thread per segment; segments already run in parallel
+        "-noautorotate",
+        "-ss",
+        &format!("{:.6}", index.seek_position(first)),
+        "-i",
+        video_path.to_str().unwrap(),
+        "-map",
+        &format!("0:{}", info.stream_index),
+        "-vf",
+        &filters.join(","),
+        "-vsync",
+        "0",
+        // Stop after the last selected frame instead of decoding to the end
+        "-frames:v",
+        &frame_numbers.len().to_string(),
+    ]);
+
+    // 16-bit output needs an explicit RGB48 layout the image encoder accepts
+    if options.high_bit_depth {
+        let is_tiff = format.eq_ignore_ascii_case("tif") || format.eq_ignore_ascii_case("tiff");
+        cmd.args(["-pix_fmt", if is_tiff { "rgb48le" } else { "rgb48be" }]);
     }
 
-    // Extract all frames in one go using select filter
     cmd.args([
-        "-i", video_path.to_str().unwrap(),
-        "-vf", &format!("select='{}'", select_expr),
-        "-vsync", "0",
-        "-q:v", "2",
-        "-f", "image2",
-        &format!("{}/frame_%06d.jpg", temp_output_dir.display()),
+        "-q:v",
+        "2", // High quality
+        "-start_number",
+        "0",
+        "-y", // Overwrite output files
+        output_dir
+            .join(format!(".export-{:06}-%06d.{}", first, format))
+            .to_str()
+            .unwrap(),
     ]);
 
-    let output = cmd.output()
-        .context("Failed to execute ffmpeg batch extraction")?;
+    let output = output_with_fallback(video_path, info.stream_index, &options.cancel, &cmd);
+    let written = (0..frame_numbers.len())
+        .map(|i| output_dir.join(temp_name(i)))
+        .take_while(|path| path.exists())
+        .collect::<Vec<_>>();
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("ffmpeg batch extraction failed: {}", error);
+    let result = output.and_then(|output| {
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("ffmpeg failed: {}", error);
+        }
+        if written.len() < frame_numbers.len() {
+            anyhow::bail!(
+                "ffmpeg wrote {} of {} frames starting at frame {}",
+                written.len(),
+                frame_numbers.len(),
+                first
+            );
+        }
+
+        frame_numbers
+            .iter()
+            .zip(&written)
+            .map(|(&frame_number, temp_path)| {
+                let output_path = output_dir.join(format!("frame_{:06}.{}", frame_number, format));
+                fs::rename(temp_path, &output_path)?;
+                Ok(output_path)
+            })
+            .collect()
+    });
+
+    if result.is_err() {
+        for path in &written {
+            let _ = fs::remove_file(path);
+        }
     }
+    result
+}
 
-    // Load all extracted frames
-    let mut images = Vec::new();
-    let entries = fs::read_dir(&temp_output_dir)?;
-    let mut paths: Vec<_> = entries
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .filter(|p| p.extension().map(|ext| ext == "jpg").unwrap_or(false))
-        .collect();
+/// Pixel layouts that frames can be piped out of ffmpeg in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawPixelFormat {
+    /// 8-bit luma only, all the sharpness metric needs
+    Gray,
+    /// 8-bit packed RGB
+    Rgb24,
+}
 
-    paths.sort(); // Ensure correct order
+impl RawPixelFormat {
+    /// Name of the matching ffmpeg `-pix_fmt`
+    fn ffmpeg_name(self) -> &'static str {
+        match self {
+            RawPixelFormat::Gray => "gray",
+            RawPixelFormat::Rgb24 => "rgb24",
+        }
+    }
 
-    for path in &paths {
-        let img = image::open(path)
-            .context("Failed to load extracted frame")?;
-        images.push(img);
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            RawPixelFormat::Gray => 1,
+            RawPixelFormat::Rgb24 => 3,
+        }
     }
 
-    // Clean up temp directory
-    let _ = fs::remove_dir_all(&temp_output_dir);
+    /// Size in bytes of one frame at the given dimensions
+    fn frame_size(self, width: u32, height: u32) -> usize {
+        width as usize * height as usize * self.bytes_per_pixel()
+    }
 
-    Ok(images)
+    /// Wraps a raw buffer into an image without copying or re-encoding
+    fn to_image(self, width: u32, height: u32, buffer: Vec<u8>) -> Result<DynamicImage> {
+        let img = match self {
+            RawPixelFormat::Gray => {
+                GrayImage::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8)
+            }
+            RawPixelFormat::Rgb24 => {
+                RgbImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8)
+            }
+        };
+        img.context("Decoded frame has unexpected size")
+    }
 }
 
-/// Extracts multiple frames efficiently using a single ffmpeg command
+/// Decodes a single frame straight into memory as a raw pixel buffer.
+/// Nothing touches the disk, so there is no lossy re-encode and concurrent
+/// callers cannot collide on temp file names.
+pub fn decode_frame(
+    video_path: &Path,
+    info: &VideoInfo,
+    options: &DecodeOptions,
+    frame_number: usize,
+    format: RawPixelFormat,
+) -> Result<DynamicImage> {
+    let index = get_frame_index(video_path, info.stream_index, &options.cancel)?;
+    let seek_position = index.seek_position(frame_number);
+
+    let ffmpeg_path = get_ffmpeg_path()?;
+    let mut cmd = Command::new(&ffmpeg_path);
+
+    // Rotation and aspect correction come from our own filter chain so the
+    // raw buffer has exactly the output size of the decode options
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-noautorotate",
+        "-ss",
+        &format!("{:.6}", seek_position),
+        "-i",
+        video_path.to_str().unwrap(),
+        "-map",
+        &format!("0:{}", info.stream_index),
+        "-frames:v",
+        "1",
+    ]);
+
+    let filters = options.filters(info);
+    if !filters.is_empty() {
+        cmd.args(["-vf", &filters.join(",")]);
+    }
+
+    cmd.args(["-f", "rawvideo", "-pix_fmt", format.ffmpeg_name(), "pipe:1"]);
+
+    let output = output_with_fallback(video_path, info.stream_index, &options.cancel, &cmd)?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("ffmpeg failed: {}", error);
+    }
+
+    let (width, height) = options.output_dimensions(info);
+    let expected = format.frame_size(width, height);
+    if output.stdout.len() < expected {
+        anyhow::bail!(
+            "ffmpeg returned {} bytes for frame {}, expected {}",
+            output.stdout.len(),
+            frame_number,
+            expected
+        );
+    }
+
+    let mut buffer = output.stdout;
+    buffer.truncate(expected);
+    format.to_image(width, height, buffer)
+}
+
+/// Exports frames as image files named after their real frame numbers.
+///
+/// Frames are split into GOP-aligned segments (see `plan_export_segments`)
+/// that are exported in parallel, each by one ffmpeg process decoding only
+/// its stretch of the video once. Returns the paths in frame number order.
 pub fn extract_frames_batch(
     video_path: &Path,
+    info: &VideoInfo,
+    index: &FrameIndex,
+    options: &DecodeOptions,
     frame_numbers: &[usize],
     output_dir: &Path,
-    format: &str, // "jpg" or "png"
+    format: &str, // "jpg", "png" or "tiff"
 ) -> Result<Vec<PathBuf>> {
     fs::create_dir_all(output_dir)?;
 
+    let mut frame_numbers = frame_numbers.to_vec();
+    frame_numbers.sort_unstable();
+    frame_numbers.dedup();
+
+    let segments = plan_export_segments(index, &frame_numbers, rayon::current_num_threads());
+    let results: Vec<Result<Vec<PathBuf>>> = segments
+        .par_iter()
+        .map(|segment| {
+            export_segment(
+                video_path, info, index, options, segment, output_dir, format,
+            )
+        })
+        .collect();
+
     let mut output_paths = Vec::new();
+    let mut first_error = None;
+    for result in results {
+        match result {
+            Ok(paths) => output_paths.extend(paths),
+            Err(e) if first_error.is_none() => first_error = Some(e),
+            Err(_) => {}
+        }
+    }
 
-    // Extract frames one by one (can be optimized with ffmpeg select filter)
-    for &frame_num in frame_numbers {
-        let output_path = output_dir.join(format!("frame_{:06}.{}", frame_num, format));
-        extract_frame(video_path, frame_num, &output_path)?;
-        output_paths.push(output_path);
+    if let Some(e) = first_error {
+        // A cancelled export should not leave a partial set of frames behind
+        if options.cancel.is_cancelled() {
+            for path in &output_paths {
+                let _ = fs::remove_file(path);
+            }
+            return Err(Cancelled.into());
+        }
+        return Err(e);
     }
 
     Ok(output_paths)
 }
 
-/// Samples frames from a video at regular intervals for analysis
-/// Optionally filters to a specific time range (in seconds)
-pub fn sample_frames(
+/// Builds an ffmpeg `select` expression matching exactly the given frame numbers.
+/// Evenly spaced runs are collapsed into `between`/`mod` terms so that sampling
+/// thousands of frames does not produce a filter string with thousands of `eq` terms.
+/// Expects `frame_numbers` to be sorted in ascending order.
+pub fn build_select_expression(frame_numbers: &[usize]) -> String {
+    let mut terms = Vec::new();
+    let mut i = 0;
+
+    while i < frame_numbers.len() {
+        let start = frame_numbers[i];
+
+        // Extend the run for as long as the spacing stays constant
+        let mut end_idx = i;
+        let mut step = 0;
+        if i + 1 < frame_numbers.len() {
+            step = frame_numbers[i + 1] - start;
+            end_idx = i + 1;
+            while end_idx + 1 < frame_numbers.len()
+                && frame_numbers[end_idx + 1] - frame_numbers[end_idx] == step
+            {
+                end_idx += 1;
+            }
+        }
+
+        let end = frame_numbers[end_idx];
+        match end_idx - i {
+            0 => terms.push(format!("eq(n\\,{})", start)),
+            1 => {
+                // A pair is cheaper to express as two equality checks
+                terms.push(format!("eq(n\\,{})", start));
+                terms.push(format!("eq(n\\,{})", end));
+            }
+            _ if step == 1 => terms.push(format!("between(n\\,{}\\,{})", start, end)),
+            _ => terms.push(format!(
+                "between(n\\,{}\\,{})*not(mod(n-{}\\,{}))",
+                start, end, start, step
+            )),
+        }
+
+        i = end_idx + 1;
+    }
+
+    if terms.is_empty() {
+        "0".to_string()
+    } else {
+        terms.join("+")
+    }
+}
+
+/// Longest wait for ffmpeg to log the timestamp of a frame it has written
+const TIMESTAMP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Streams sampled frames out of a single ffmpeg process as raw pixel buffers.
+/// Frames are yielded in presentation order together with their frame number.
+/// Each frame is identified by the timestamp ffmpeg's `showinfo` filter logs
+/// for it, so a frame the decoder drops or fails on only goes missing itself
+/// instead of shifting every later frame onto the wrong number.
+///
+/// If a hardware decode fails or ends before every frame arrived, the video
+/// is switched to software decoding and the stream starts over in software,
+/// skipping the frames already yielded. The ffmpeg child is killed when the
+/// stream is dropped before it finishes, or when the job it belongs to is
+/// cancelled.
+pub struct FrameStream {
+    ffmpeg: StreamProcess,
+    cancel: CancelToken,
+    /// Set while decoding in hardware
+    software: Option<SoftwareFallback>,
+    index: Arc<FrameIndex>,
+    /// Requested frame numbers, sorted
+    frame_numbers: Vec<usize>,
+    emitted: HashSet<usize>,
+    width: u32,
+    height: u32,
+    format: RawPixelFormat,
+    finished: bool,
+}
+
+/// The running ffmpeg process of a `FrameStream`
+struct StreamProcess {
+    process: JobProcess,
+    stdout: ChildStdout,
+    /// PTS in seconds of each frame written to stdout, in the same order
+    timestamps: Receiver<Option<f64>>,
+    stderr_reader: Option<JoinHandle<String>>,
+}
+
+/// The software decode command a hardware `FrameStream` restarts with
+struct SoftwareFallback {
+    video_path: PathBuf,
+    stream_index: usize,
+    cmd: Command,
+}
+
+impl StreamProcess {
+    fn spawn(cmd: &mut Command, cancel: &CancelToken) -> Result<Self> {
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let process = cancel.spawn(cmd)?;
+
+        let stdout = process
+            .take_stdout()
+            .context("Failed to capture ffmpeg output")?;
+
+        // Drain stderr on a separate thread so a chatty ffmpeg can never block
+        // the pipe, passing frame timestamps on and keeping the errors
+        let (sender, timestamps) = mpsc::channel();
+        let stderr_reader = process.take_stderr().map(|stderr| {
+            std::thread::spawn(move || {
+                let mut stderr = BufReader::new(stderr);
+                let mut showinfo = ShowinfoLog::default();
+                let mut errors = String::new();
+                let mut line = Vec::new();
+                while matches!(stderr.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                    let text = String::from_utf8_lossy(&line);
+                    if let Some(pts) = showinfo.frame_timestamp(&text) {
+                        let _ = sender.send(pts);
+                    } else if let Some(error) = log_error(&text) {
+                        errors.push_str(&error);
+                    }
+                    line.clear();
+                }
+                errors
+            })
+        });
+
+        Ok(Self {
+            process,
+            stdout,
+            timestamps,
+            stderr_reader,
+        })
+    }
+}
+
+impl FrameStream {
+    /// Reads exactly one frame from stdout. Returns Ok(false) on a clean end of stream.
+    fn read_frame(&mut self, buffer: &mut [u8]) -> std::io::Result<bool> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.ffmpeg.stdout.read(&mut buffer[filled..]) {
+                Ok(0) if filled == 0 => return Ok(false),
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "ffmpeg output ended in the middle of a frame",
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Frame number of the frame just read, or None if it is not one of the
+    /// requested frames (such as the neighbour of a frame the decoder dropped)
+    fn identify(&mut self) -> Result<Option<usize>> {
+        let pts = self
+            .ffmpeg
+            .timestamps
+            .recv_timeout(TIMESTAMP_TIMEOUT)
+            .map_err(|_| {
+                anyhow::anyhow!("ffmpeg did not report the timestamp of a decoded frame")
+            })?;
+        Ok(pts
+            .and_then(|pts| self.index.frame_at_pts(pts))
+            .filter(|n| self.frame_numbers.binary_search(n).is_ok() && !self.emitted.contains(n)))
+    }
+
+    /// Waits for ffmpeg to exit and turns a failed exit status into an error
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        let status = self.ffmpeg.process.wait()?;
+        self.cancel.check()?;
+        let stderr = self
+            .ffmpeg
+            .stderr_reader
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+
+        if !status.success() {
+            anyhow::bail!("ffmpeg streaming decode failed: {}", stderr.trim());
+        }
+        Ok(())
+    }
+
+    /// Kills the process after a failure it cannot recover from
+    fn abort(&mut self) {
+        self.finished = true;
+        self.ffmpeg.process.kill();
+    }
+
+    /// Gives up on hardware decoding for this video and starts the stream
+    /// again in software
+    fn restart_in_software(
+        &mut self,
+        software: SoftwareFallback,
+        error: &anyhow::Error,
+    ) -> Result<()> {
+        let SoftwareFallback {
+            video_path,
+            stream_index,
+            mut cmd,
+        } = software;
+        record_failure(&video_path, stream_index, &format!("{:#}", error));
+        self.abort();
+        self.ffmpeg = StreamProcess::spawn(&mut cmd, &self.cancel)?;
+        self.finished = false;
+        Ok(())
+    }
+}
+
+impl Iterator for FrameStream {
+    type Item = Result<(usize, DynamicImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.finished {
+                return None;
+            }
+
+            if self.emitted.len() >= self.frame_numbers.len() {
+                // Everything we asked for has arrived; just reap the process
+                return self.finish().err().map(Err);
+            }
+
+            let mut buffer = vec![0u8; self.format.frame_size(self.width, self.height)];
+            let error = match self.read_frame(&mut buffer) {
+                Ok(true) => match self.identify() {
+                    Ok(Some(frame_number)) => {
+                        self.emitted.insert(frame_number);
+                        let img = self.format.to_image(self.width, self.height, buffer);
+                        return Some(img.map(|img| (frame_number, img)));
+                    }
+                    Ok(None) => continue,
+                    Err(e) => {
+                        self.abort();
+                        e
+                    }
+                },
+                Ok(false) => match self.finish() {
+                    // The caller retries frames a software decode did not deliver
+                    Ok(()) if self.software.is_none() => return None,
+                    Ok(()) => {
+                        anyhow::anyhow!("Hardware decode ended before all frames were decoded")
+                    }
+                    Err(e) => e,
+                },
+                Err(_) if self.cancel.is_cancelled() => {
+                    self.finished = true;
+                    return Some(Err(Cancelled.into()));
+                }
+                Err(e) => {
+                    self.abort();
+                    anyhow::Error::new(e).context("Failed to read frame from ffmpeg")
+                }
+            };
+
+            match self.software.take() {
+                Some(software) if !self.cancel.is_cancelled() => {
+                    if let Err(e) = self.restart_in_software(software, &error) {
+                        self.finished = true;
+                        return Some(Err(e));
+                    }
+                }
+                _ => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.ffmpeg.process.kill();
+        }
+    }
+}
+
+/// Picks frame timestamps and errors out of ffmpeg's stderr when it runs
+/// with `-loglevel level+info` and a `showinfo` filter
+#[derive(Debug, Default)]
+struct ShowinfoLog {
+    /// Seconds per PTS tick, from the filter's `config in` line
+    time_base: Option<f64>,
+}
+
+impl ShowinfoLog {
+    /// For the line `showinfo` logs per frame, that frame's PTS in seconds
+    /// (None inside if it has none). Integer PTS are preferred over
+    /// `pts_time`, which is printed with only six significant digits.
+    fn frame_timestamp(&mut self, line: &str) -> Option<Option<f64>> {
+        if !line.contains("Parsed_showinfo") {
+            return None;
+        }
+        if let Some(time_base) = log_field(line, "time_base:") {
+            self.time_base = parse_ratio(time_base.trim_end_matches(','));
+            return None;
+        }
+        if !line.contains(" n:") {
+            return None;
+        }
+
+        let pts = log_field(line, " pts:")
+            .and_then(|pts| pts.parse::<i64>().ok())
+            .zip(self.time_base)
+            .map(|(pts, time_base)| pts as f64 * time_base)
+            .or_else(|| log_field(line, "pts_time:").and_then(|t| t.parse().ok()));
+        Some(pts)
+    }
+}
+
+/// Message of a line logged at error level or worse, without the level tag
+fn log_error(line: &str) -> Option<String> {
+    ["[error] ", "[fatal] ", "[panic] "]
+        .iter()
+        .find(|tag| line.contains(*tag))
+        .map(|tag| line.replacen(tag, "", 1))
+}
+
+/// First whitespace-separated value after `key` in a log line
+fn log_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(key)? + key.len();
+    line[start..].split_whitespace().next()
+}
+
+/// Starts a single ffmpeg process that decodes only the requested frames and
+/// pipes them to stdout as rawvideo in the given pixel format.
+pub fn stream_frames(
+    video_path: &Path,
+    info: &VideoInfo,
+    index: &Arc<FrameIndex>,
+    options: &DecodeOptions,
+    frame_numbers: &[usize],
+    format: RawPixelFormat,
+) -> Result<FrameStream> {
+    let mut frame_numbers = frame_numbers.to_vec();
+    frame_numbers.sort_unstable();
+    frame_numbers.dedup();
+
+    let mut filters = options.select_filters(info, &build_select_expression(&frame_numbers));
+    filters.push("showinfo".to_string());
+
+    let ffmpeg_path = get_ffmpeg_path()?;
+    let mut cmd = Command::new(&ffmpeg_path);
+
+    // Rotation and aspect correction come from our own filter chain so each
+    // raw frame has exactly the output size of the decode options.
+    // `-copyts` keeps the PTS the frame index was built from.
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "level+info",
+        "-nostats",
+        "-noautorotate",
+        "-copyts",
+        "-i",
+        video_path.to_str().unwrap(),
+        "-map",
+        &format!("0:{}", info.stream_index),
+        "-vf",
+        &filters.join(","),
+        "-vsync",
+        "0",
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        format.ffmpeg_name(),
+        "pipe:1",
+    ]);
+
+    spawn_frame_stream(
+        video_path,
+        info.stream_index,
+        cmd,
+        index,
+        options,
+        options.output_dimensions(info),
+        frame_numbers,
+        format,
+    )
+}
+
+/// Streams only the given keyframes, using the decoder's `-skip_frame nokey`
+/// so everything between them is skipped without being decoded. Much faster
+/// than `stream_frames` on long recordings, as inter frames are never touched.
+///
+/// `keyframes` must be consecutive entries of `index.keyframes`: the decoder
+/// emits every keyframe from the first one on.
+pub fn stream_keyframes(
+    video_path: &Path,
+    info: &VideoInfo,
+    index: &Arc<FrameIndex>,
+    options: &DecodeOptions,
+    keyframes: &[usize],
+    format: RawPixelFormat,
+) -> Result<FrameStream> {
+    let first = *keyframes.first().context("No keyframes to scan")?;
+
+    let ffmpeg_path = get_ffmpeg_path()?;
+    let mut cmd = Command::new(&ffmpeg_path);
+
+    cmd.args([
+        "-hide_banner",
+        "-loglevel",
+        "level+info",
+        "-nostats",
+        "-noautorotate",
+        "-copyts",
+        "-skip_frame",
+        "nokey",
+        "-ss",
+        &format!("{:.6}", index.seek_position(first)),
+        "-i",
+        video_path.to_str().unwrap(),
+        "-map",
+        &format!("0:{}", info.stream_index),
+    ]);
+    // Neighbouring keyframes differ everywhere, so a deinterlacer falls back
+    // to interpolating within the field
+    let mut filters = options.filters(info);
+    filters.push("showinfo".to_string());
+    cmd.args(["-vf", &filters.join(",")]);
+    // Stop after the last requested keyframe instead of running to the end
+    cmd.args([
+        "-frames:v",
+        &keyframes.len().to_string(),
+        "-vsync",
+        "0",
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        format.ffmpeg_name(),
+        "pipe:1",
+    ]);
+
+    spawn_frame_stream(
+        video_path,
+        info.stream_index,
+        cmd,
+        index,
+        options,
+        options.output_dimensions(info),
+        keyframes.to_vec(),
+        format,
+    )
+}
+
+/// Spawns a prepared rawvideo-to-stdout ffmpeg command, built without
+/// `-hwaccel`, as a `FrameStream` decoding with the video's hardware decoder
+#[allow(clippy::too_many_arguments)]
+fn spawn_frame_stream(
     video_path: &Path,
+    stream_index: usize,
+    cmd: Command,
+    index: &Arc<FrameIndex>,
+    options: &DecodeOptions,
+    (width, height): (u32, u32),
+    frame_numbers: Vec<usize>,
+    format: RawPixelFormat,
+) -> Result<FrameStream> {
+    let hwaccel = hwaccel_args(video_path, stream_index, &options.cancel)?;
+    let mut cmd_with = Command::new(cmd.get_program());
+    cmd_with.args(&hwaccel).args(cmd.get_args());
+    let ffmpeg = StreamProcess::spawn(&mut cmd_with, &options.cancel)?;
+
+    let software = (!hwaccel.is_empty()).then(|| SoftwareFallback {
+        video_path: video_path.to_path_buf(),
+        stream_index,
+        cmd,
+    });
+
+    Ok(FrameStream {
+        ffmpeg,
+        cancel: options.cancel.clone(),
+        software,
+        index: index.clone(),
+        frame_numbers,
+        emitted: HashSet::new(),
+        width,
+        height,
+        format,
+        finished: false,
+    })
+}
+
+/// Picks every `sample_rate`-th frame of the index within the optional time range
+pub fn sample_frame_numbers(
+    index: &FrameIndex,
     sample_rate: usize,
     start_time: Option<f64>,
     end_time: Option<f64>,
-) -> Result<Vec<usize>> {
-    let info = get_video_info(video_path)?;
-    let total_frames = info.total_frames as usize;
+) -> Vec<usize> {
+    let total_frames = index.len();
 
-    // Calculate frame range from time range
+    // Calculate frame range from time range using real presentation timestamps
     let start_frame = start_time
-        .map(|t| (t * info.fps).floor() as usize)
+        .map(|t| index.frame_at_time(t))
         .unwrap_or(0)
         .min(total_frames.saturating_sub(1));
 
     let end_frame = end_time
-        .map(|t| (t * info.fps).ceil() as usize)
+        .map(|t| index.frames_until(t))
         .unwrap_or(total_frames)
         .min(total_frames);
 
-    let mut frame_numbers = Vec::new();
-    for i in (start_frame..end_frame).step_by(sample_rate) {
-        frame_numbers.push(i);
+    (start_frame..end_frame)
+        .step_by(sample_rate.max(1))
+        .collect()
+}
+
+/// Picks the frame on screen every `interval` seconds within the optional
+/// time range, given the position in seconds of each of `frame_count`
+/// frames. Long-held frames of variable frame rate footage are picked once.
+pub fn sample_frames_by_time(
+    frame_count: usize,
+    position: impl Fn(usize) -> f64,
+    interval: f64,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+) -> Vec<usize> {
+    if frame_count == 0 || interval.is_nan() || interval <= 0.0 {
+        return Vec::new();
+    }
+    // Positions are sorted, so binary search frame numbers
+    let first_after = |time: f64| {
+        let (mut low, mut high) = (0, frame_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            if position(mid) < time - 1e-6 {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    };
+
+    let start = start_time.unwrap_or(0.0);
+    // Both ends are included
+    let end = end_time
+        .unwrap_or(f64::INFINITY)
+        .min(position(frame_count - 1))
+        + 1e-6;
+    let first = first_after(start);
+    let mut frames: Vec<usize> = Vec::new();
+    let mut step = 0;
+    loop {
+        // Multiplied rather than summed so rounding errors do not add up
+        let time = start + interval * step as f64;
+        if time > end || first >= frame_count {
+            break;
+        }
+        step += 1;
+        // The last frame shown at or before `time`, but none before the range
+        let frame = first_after(time + 2e-6).saturating_sub(1).max(first);
+        if position(frame) > end {
+            break;
+        }
+        if frames.last() != Some(&frame) {
+            frames.push(frame);
+        }
     }
+    frames
+}
+
+/// Keyframes of the index within the optional time range
+pub fn keyframe_numbers(
+    index: &FrameIndex,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+) -> Vec<usize> {
+    let start_frame = start_time.map(|t| index.frame_at_time(t)).unwrap_or(0);
+    let end_frame = end_time
+        .map(|t| index.frames_until(t))
+        .unwrap_or(usize::MAX);
 
-    Ok(frame_numbers)
+    index
+        .keyframes
+        .iter()
+        .copied()
+        .filter(|&n| n >= start_frame && n < end_frame)
+        .collect()
+}
+
+/// How much denser than the coarse grid refinement samples are
+const REFINE_DIVISIONS: usize = 8;
+
+/// Plans the second pass of adaptive sampling from coarse (frame number, score) pairs.
+///
+/// Two kinds of neighborhoods are re-sampled at `sample_rate / 8` spacing:
+/// local maxima in the top quartile, where an even sharper frame is likely
+/// just next to the sample, and gaps whose two bounding samples are both
+/// below the median, where the one crisp frame may have been stepped over.
+/// Peaks are refined first (sharpest first), then gaps (least blurry first),
+/// until `budget` extra frames have been planned. Returned frame numbers are
+/// sorted and never include a coarse sample.
+pub fn refine_frame_numbers(
+    coarse: &[(usize, f64)],
+    sample_rate: usize,
+    total_frames: usize,
+    budget: usize,
+) -> Vec<usize> {
+    if sample_rate <= 1 || coarse.len() < 2 || budget == 0 {
+        return Vec::new();
+    }
+
+    let step = (sample_rate / REFINE_DIVISIONS).max(1);
+
+    let mut sorted_scores: Vec<f64> = coarse.iter().map(|&(_, s)| s).collect();
+    sorted_scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted_scores[sorted_scores.len() / 2];
+    let upper_quartile = sorted_scores[sorted_scores.len() * 3 / 4];
+
+    // (priority, start, end) ranges to fill, exclusive of the coarse samples themselves
+    let mut peaks = Vec::new();
+    for (i, &(frame, score)) in coarse.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|j| coarse[j].1).unwrap_or(f64::MIN);
+        let next = coarse.get(i + 1).map(|c| c.1).unwrap_or(f64::MIN);
+        if score >= upper_quartile && score >= prev && score >= next {
+            let start = frame.saturating_sub(sample_rate - 1);
+            let end = (frame + sample_rate).min(total_frames);
+            peaks.push((score, start, end));
+        }
+    }
+    peaks.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+    let mut gaps = Vec::new();
+    for pair in coarse.windows(2) {
+        let ((a, score_a), (b, score_b)) = (pair[0], pair[1]);
+        if score_a < median && score_b < median && b > a + 1 {
+            gaps.push((score_a + score_b, a + 1, b));
+        }
+    }
+    gaps.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+    let sampled: std::collections::HashSet<usize> = coarse.iter().map(|&(f, _)| f).collect();
+    let mut planned = std::collections::BTreeSet::new();
+
+    for (_, start, end) in peaks.into_iter().chain(gaps) {
+        let candidates: Vec<usize> = (start..end)
+            .step_by(step)
+            .filter(|f| !sampled.contains(f) && !planned.contains(f))
+            .collect();
+        if planned.len() + candidates.len() > budget {
+            break;
+        }
+        planned.extend(candidates);
+    }
+
+    planned.into_iter().collect()
 }
 
 #[cfg(test)]
@@ -462,7 +2300,395 @@ mod tests {
 
     #[test]
     fn test_video_info_parsing() {
-        // This test would require a sample video file
-        // For now, it's a placeholder
+        let json = r#"{
+            "streams": [
+                {
+                    "index": 0, "codec_type": "video", "codec_name": "hevc", "profile": "Main 10",
+                    "width": 3840, "height": 2160, "pix_fmt": "yuv420p10le",
+                    "sample_aspect_ratio": "1:1", "color_range": "tv",
+                    "color_transfer": "arib-std-b67", "color_primaries": "bt2020",
+                    "r_frame_rate": "30/1", "duration": "12.5", "nb_frames": "375",
+                    "bit_rate": "45000000",
+                    "disposition": { "default": 1, "attached_pic": 0 },
+                    "tags": { "creation_time": "2024-06-01T10:00:00.000000Z" },
+                    "side_data_list": [ { "side_data_type": "Display Matrix", "rotation": -90 } ]
+                },
+                { "index": 1, "codec_type": "audio", "codec_name": "aac" },
+                {
+                    "index": 2, "codec_type": "video", "codec_name": "mjpeg",
+                    "width": 320, "height": 240, "disposition": { "attached_pic": 1 }
+                },
+                {
+                    "index": 3, "codec_type": "data", "codec_tag_string": "tmcd",
+                    "tags": { "timecode": "01:02:03:04" }
+                }
+            ],
+            "format": {
+                "format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "12.51",
+                "tags": {
+                    "com.apple.quicktime.make": "Apple", "com.apple.quicktime.model": "iPhone 15 Pro",
+                    "com.apple.quicktime.location.ISO6709": "+37.3318-122.0312+010.000/",
+                    "com.apple.quicktime.creationdate": "2024-06-01T12:00:00+0200"
+                }
+            }
+        }"#;
+        let probe: FfprobeOutput = serde_json::from_str(json).unwrap();
+        let info = video_info_from_probe(&probe, None).unwrap();
+
+        assert_eq!(info.codec_name.as_deref(), Some("hevc"));
+        assert_eq!(info.bit_depth, Some(10));
+        assert_eq!(info.chroma_subsampling.as_deref(), Some("4:2:0"));
+        assert_eq!(info.total_frames, 375);
+        assert_eq!((info.display_width, info.display_height), (2160, 3840));
+        assert_eq!(info.make.as_deref(), Some("Apple"));
+        assert_eq!(info.model.as_deref(), Some("iPhone 15 Pro"));
+        assert_eq!(info.timecode.as_deref(), Some("01:02:03:04"));
+        assert_eq!(
+            info.creation_time.as_deref(),
+            Some("2024-06-01T12:00:00+0200")
+        );
+        assert_eq!(
+            info.location.map(|l| (l.latitude, l.longitude)),
+            Some((37.3318, -122.0312))
+        );
+        assert!(info.has_audio);
+        assert_eq!(info.video_streams.len(), 2);
+        assert_eq!(info.hdr_format.as_deref(), Some("HLG"));
+        assert_eq!(info.warnings.len(), 1);
+        assert_eq!(info.stream_index, 0);
+
+        // Explicit selection of the cover art stream still works, non-video streams do not
+        let thumbnail = video_info_from_probe(&probe, Some(2)).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (320, 240));
+        assert!(video_info_from_probe(&probe, Some(1)).is_err());
+    }
+
+    #[test]
+    fn test_field_order_follows_display_order() {
+        let order = |field_order: &str| {
+            let json = format!(
+                r#"{{"streams": [{{"index": 0, "codec_type": "video", "width": 720, "height": 576,
+                    "r_frame_rate": "25/1", "field_order": "{}"}}], "format": {{}}}}"#,
+                field_order
+            );
+            let probe: FfprobeOutput = serde_json::from_str(&json).unwrap();
+            let info = video_info_from_probe(&probe, None).unwrap();
+            (info.interlaced, info.field_order)
+        };
+        assert_eq!(order("tt"), (true, Some("tff".to_string())));
+        assert_eq!(order("bt"), (true, Some("tff".to_string())));
+        assert_eq!(order("bb"), (true, Some("bff".to_string())));
+        assert_eq!(order("tb"), (true, Some("bff".to_string())));
+        assert_eq!(
+            order("progressive"),
+            (false, Some("progressive".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_tone_mapping_filters() {
+        let mut info = VideoInfo {
+            is_hdr: true,
+            ..Default::default()
+        };
+        let options = DecodeOptions::default();
+        assert!(tone_map_filters(Some("smpte2084"), Some("hable"), true)
+            .iter()
+            .any(|f| f == "tonemap=tonemap=hable:desat=0"));
+        // colorspace cannot linearize PQ or HLG, so nothing stands in for zscale
+        assert!(tone_map_filters(Some("smpte2084"), Some("hable"), false).is_empty());
+        assert!(tone_map_filters(Some("arib-std-b67"), Some("hable"), false).is_empty());
+        assert_eq!(
+            tone_map_filters(Some("bt2020-10"), None, false),
+            vec!["colorspace=all=bt709:iall=bt2020:itrc=bt2020-10:range=pc:fast=1".to_string()]
+        );
+        assert!(tone_map_filters(Some("bt709"), None, false).is_empty());
+
+        // 16-bit export keeps the HDR signal untouched
+        let precise = DecodeOptions {
+            high_bit_depth: true,
+            ..Default::default()
+        };
+        assert!(precise.filters(&info).is_empty());
+
+        info.is_hdr = false;
+        assert!(options.filters(&info).is_empty());
+    }
+
+    #[test]
+    fn test_deinterlace_runs_before_select() {
+        let mut info = VideoInfo {
+            interlaced: true,
+            field_order: Some("bff".to_string()),
+            ..Default::default()
+        };
+        let options = DecodeOptions::default();
+        assert_eq!(
+            options.select_filters(&info, "eq(n,3)"),
+            vec![
+                "bwdif=mode=send_frame:parity=bff:deint=all",
+                "select='eq(n,3)'"
+            ]
+        );
+
+        let off = DecodeOptions {
+            deinterlace: Deinterlace::Off,
+            ..Default::default()
+        };
+        assert!(off.filters(&info).is_empty());
+
+        // Progressive videos are only deinterlaced on request
+        info.interlaced = false;
+        info.field_order = None;
+        assert!(options.filters(&info).is_empty());
+        let forced = DecodeOptions {
+            deinterlace: Deinterlace::Yadif,
+            ..Default::default()
+        };
+        assert_eq!(
+            forced.filters(&info),
+            vec!["yadif=mode=send_frame:parity=auto:deint=all"]
+        );
+    }
+
+    #[test]
+    fn test_low_bitrate_warning() {
+        let info = VideoInfo {
+            width: 1920,
+            height: 1080,
+            fps: 30.0,
+            bit_depth: Some(8),
+            chroma_subsampling: Some("4:2:0".to_string()),
+            bit_rate: Some(2_000_000),
+            ..Default::default()
+        };
+        assert_eq!(input_warnings(&info).len(), 1);
+        assert_eq!(pix_fmt_bit_depth("p010le"), Some(10));
+        assert_eq!(pix_fmt_bit_depth("yuvj420p"), Some(8));
+        assert_eq!(pix_fmt_bit_depth("gray12le"), Some(12));
+    }
+
+    #[test]
+    fn test_frame_index_from_vfr_packets() {
+        // Decode order with B-frames, variable spacing and one discarded packet
+        let listing = "pts_time=0.000000|flags=K__\n\
+                       pts_time=0.100000|flags=___\n\
+                       pts_time=0.033000|flags=___\n\
+                       pts_time=0.250000|flags=K__\n\
+                       pts_time=-0.050000|flags=__D\n\
+                       start_time=0.000000\n";
+        let (packets, start) = parse_packet_listing(listing);
+        let index = FrameIndex::from_packets(packets, start.unwrap());
+
+        assert_eq!(index.timestamps, vec![0.0, 0.033, 0.1, 0.25]);
+        assert_eq!(index.keyframes, vec![0, 3]);
+        assert_eq!(index.frame_at_time(0.1), 2);
+        assert_eq!(index.frame_at_time(0.2), 3);
+        assert!((index.seek_position(2) - 0.0665).abs() < 1e-9);
+
+        let frames = sample_frame_numbers(&index, 2, Some(0.03), None);
+        assert_eq!(frames, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_streamed_frames_identified_by_pts() {
+        let mut log = ShowinfoLog::default();
+        let lines = [
+            "[Parsed_showinfo_2 @ 0x5581] [info] config in time_base: 1/90000, frame_rate: 30000/1001",
+            "[Parsed_showinfo_2 @ 0x5581] [info] n:   0 pts:  93003 pts_time:1.03337 duration:   3003",
+            "[Parsed_showinfo_2 @ 0x5581] [info]   color_range:tv color_space:bt709",
+            "[Parsed_showinfo_2 @ 0x5581] [info] n:   1 pts:12345678 pts_time:137.174",
+            "[h264 @ 0x5582] [error] Invalid NAL unit size",
+        ];
+        let parsed: Vec<Option<Option<f64>>> =
+            lines.iter().map(|line| log.frame_timestamp(line)).collect();
+        assert_eq!(parsed[0], None);
+        assert!((parsed[1].unwrap().unwrap() - 93003.0 / 90000.0).abs() < 1e-12);
+        assert_eq!(parsed[2], None);
+        assert!((parsed[3].unwrap().unwrap() - 12345678.0 / 90000.0).abs() < 1e-12);
+        assert_eq!(parsed[4], None);
+        assert_eq!(
+            log_error(lines[4]).as_deref(),
+            Some("[h264 @ 0x5582] Invalid NAL unit size")
+        );
+
+        // Without a time base line, pts_time is used
+        let mut log = ShowinfoLog::default();
+        let line = "[Parsed_showinfo_1 @ 0x1] [info] n:   4 pts:  12 pts_time:0.4";
+        assert_eq!(log.frame_timestamp(line), Some(Some(0.4)));
+
+        // A dropped frame only loses its own number
+        let index = FrameIndex::from_packets(
+            (0..10).map(|n| (1.0 + n as f64 / 30.0, n == 0)).collect(),
+            1.0,
+        );
+        assert_eq!(index.frame_at_pts(1.0 + 4.0 / 30.0 + 0.004), Some(4));
+        assert_eq!(index.frame_at_pts(1.0 + 9.0 / 30.0), Some(9));
+        assert_eq!(index.frame_at_pts(0.5), None);
+        assert_eq!(index.frame_at_pts(2.0), None);
+    }
+
+    #[test]
+    fn test_vfr_detection_and_time_sampling() {
+        // Constant 30 fps with millisecond timestamp rounding
+        let cfr = FrameIndex::from_packets(
+            (0..90)
+                .map(|n| ((n as f64 / 30.0 * 1000.0).round() / 1000.0, n == 0))
+                .collect(),
+            0.0,
+        );
+        let (avg_fps, is_vfr) = cfr.frame_rate().unwrap();
+        assert!((avg_fps - 30.0).abs() < 0.1);
+        assert!(!is_vfr);
+
+        // Screen recording: 30 fps bursts around a 2 second still
+        let mut timestamps: Vec<f64> = (0..30).map(|n| n as f64 / 30.0).collect();
+        timestamps.extend((0..30).map(|n| 3.0 + n as f64 / 30.0));
+        let vfr = FrameIndex::from_packets(timestamps.iter().map(|&t| (t, false)).collect(), 0.0);
+        let (avg_fps, is_vfr) = vfr.frame_rate().unwrap();
+        assert!(avg_fps < 16.0);
+        assert!(is_vfr);
+
+        // Index sampling bunches up in the bursts, time sampling holds the still frame once
+        assert_eq!(
+            sample_frame_numbers(&vfr, 15, None, None),
+            vec![0, 15, 30, 45]
+        );
+        let by_time = sample_frames_by_time(vfr.len(), |n| vfr.timestamp(n), 0.5, None, None);
+        assert_eq!(by_time, vec![0, 15, 29, 30, 45]);
+        let ranged =
+            sample_frames_by_time(vfr.len(), |n| vfr.timestamp(n), 0.5, Some(1.2), Some(3.6));
+        assert_eq!(ranged, vec![30, 36]);
+        let to_end =
+            sample_frames_by_time(vfr.len(), |n| vfr.timestamp(n), 0.5, Some(3.0), Some(3.5));
+        assert_eq!(to_end, vec![30, 45]);
+    }
+
+    #[test]
+    fn test_keyframe_numbers_in_range() {
+        // One keyframe per second at 10 fps
+        let packets = (0..50).map(|n| (n as f64 / 10.0, n % 10 == 0)).collect();
+        let index = FrameIndex::from_packets(packets, 0.0);
+
+        assert_eq!(
+            keyframe_numbers(&index, None, None),
+            vec![0, 10, 20, 30, 40]
+        );
+        assert_eq!(
+            keyframe_numbers(&index, Some(0.5), Some(3.0)),
+            vec![10, 20, 30]
+        );
+        assert_eq!(
+            sample_frame_numbers(&index, 5, Some(1.0), Some(2.0)),
+            vec![10, 15, 20]
+        );
+    }
+
+    #[test]
+    fn test_display_geometry() {
+        assert_eq!(normalize_rotation(-90.0), 270);
+        assert_eq!(normalize_rotation(90.0), 90);
+        assert_eq!(normalize_rotation(-180.0), 180);
+        assert_eq!(parse_ratio("16:11"), Some(16.0 / 11.0));
+        assert_eq!(parse_ratio("0:1"), None);
+
+        // Portrait phone clip: coded landscape, displayed upright
+        assert_eq!(display_dimensions(1920, 1080, 1.0, 90), (1080, 1920));
+        // Anamorphic DV: 720x480 with 32:27 pixels is shown as 854x480
+        assert_eq!(display_dimensions(720, 480, 32.0 / 27.0, 0), (854, 480));
+    }
+
+    #[test]
+    fn test_analysis_resolution() {
+        let info = VideoInfo {
+            display_width: 2160,
+            display_height: 3840,
+            ..Default::default()
+        };
+        let options = DecodeOptions {
+            analysis_resolution: Some(1280),
+            ..Default::default()
+        };
+        assert_eq!(options.output_dimensions(&info), (720, 1280));
+        assert_eq!(
+            options.filters(&info).last().unwrap(),
+            "scale=720:1280:flags=area"
+        );
+
+        // Never upscales
+        let large = DecodeOptions {
+            analysis_resolution: Some(8192),
+            ..Default::default()
+        };
+        assert_eq!(large.output_dimensions(&info), (2160, 3840));
+        assert!(large.filters(&info).is_empty());
+    }
+
+    #[test]
+    fn test_raw_buffer_to_image() {
+        let rgb = RawPixelFormat::Rgb24.to_image(4, 2, vec![7u8; 24]).unwrap();
+        assert_eq!((rgb.width(), rgb.height()), (4, 2));
+        assert!(matches!(rgb, DynamicImage::ImageRgb8(_)));
+
+        let gray = RawPixelFormat::Gray.to_image(4, 2, vec![0u8; 8]).unwrap();
+        assert!(matches!(gray, DynamicImage::ImageLuma8(_)));
+
+        assert!(RawPixelFormat::Gray.to_image(4, 2, vec![0u8; 7]).is_err());
+    }
+
+    #[test]
+    fn test_refinement_targets_peaks_and_blurry_gaps() {
+        // Coarse grid every 10 frames: a peak at 40, a blurry stretch at 60..80
+        let scores = [50.0, 55.0, 52.0, 60.0, 200.0, 58.0, 10.0, 12.0, 11.0, 54.0];
+        let coarse: Vec<(usize, f64)> = scores
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| (i * 10, s))
+            .collect();
+
+        let refined = refine_frame_numbers(&coarse, 10, 100, 100);
+        assert!(refined.contains(&39) && refined.contains(&41));
+        assert!(refined.contains(&61) && refined.contains(&75));
+        assert!(refined.iter().all(|f| f % 10 != 0));
+
+        // The peak neighborhood alone is 18 frames, so a tight budget keeps only it
+        let limited = refine_frame_numbers(&coarse, 10, 100, 20);
+        assert!(limited.iter().all(|&f| (31..50).contains(&f)));
+
+        assert!(refine_frame_numbers(&coarse, 1, 100, 100).is_empty());
+    }
+
+    #[test]
+    fn test_export_segments_follow_gops() {
+        // Keyframe every 10 frames
+        let packets = (0..100).map(|n| (n as f64 / 10.0, n % 10 == 0)).collect();
+        let index = FrameIndex::from_packets(packets, 0.0);
+        let frames = [3, 5, 12, 25, 71, 95];
+
+        // Adjacent GOPs share a decode; skipped GOPs start a new segment
+        assert_eq!(
+            plan_export_segments(&index, &frames, 1),
+            vec![vec![3, 5, 12, 25], vec![71], vec![95]]
+        );
+        // With more workers, long runs split at GOP boundaries
+        assert_eq!(
+            plan_export_segments(&index, &frames, 8),
+            vec![vec![3, 5], vec![12], vec![25], vec![71], vec![95]]
+        );
+        assert!(plan_export_segments(&index, &[], 4).is_empty());
+    }
+
+    #[test]
+    fn test_select_expression_collapses_runs() {
+        let frames: Vec<usize> = (0..300).step_by(30).collect();
+        let expr = build_select_expression(&frames);
+        assert_eq!(expr, "between(n\\,0\\,270)*not(mod(n-0\\,30))");
+
+        assert_eq!(build_select_expression(&[5]), "eq(n\\,5)");
+        assert_eq!(build_select_expression(&[]), "0");
+        assert_eq!(
+            build_select_expression(&[1, 2, 3, 10]),
+            "between(n\\,1\\,3)+eq(n\\,10)"
+        );
     }
 }
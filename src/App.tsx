@@ -28,36 +28,72 @@ import {
   Clock,
 } from 'lucide-react';
 import type {
+  AnalysisMode,
   AnalysisResult,
   AnalysisProgress,
   ExportOptions,
   ExportFormat,
+  FrameData,
+  JobError,
   SelectionMode,
   SelectionSettings,
+  TimeSegments,
+  VideoInfo,
 } from './types';
 
 import './index.css';
 import { SettingsDialog } from './SettingsDialog';
 
+function isCancelled(error: unknown): boolean {
+  return (error as JobError)?.kind === 'cancelled';
+}
+
+function errorMessage(error: unknown): string {
+  const jobError = error as JobError;
+  return jobError?.kind === 'failed' ? jobError.message : String(error);
+}
+
+const VIDEO_EXTENSIONS = ['mp4', 'mov', 'avi', 'mkv', 'webm'];
+
+function isVideoFile(path: string): boolean {
+  const extension = path.split('.').pop()?.toLowerCase() ?? '';
+  return VIDEO_EXTENSIONS.includes(extension);
+}
+
+function isDecoded(frame: FrameData): boolean {
+  return frame.status !== 'failed';
+}
+
 interface AppSettings {
   ffmpeg_path: string | null;
   ffprobe_path: string | null;
   first_run: boolean;
+  analysis_cache_limit_mb: number;
 }
 
 function App() {
   const [videoPath, setVideoPath] = useState<string | null>(null);
   const [videoUrl, setVideoUrl] = useState<string | null>(null);
+  const [isSequence, setIsSequence] = useState(false);
   const [analyzing, setAnalyzing] = useState(false);
   const [analysisResult, setAnalysisResult] = useState<AnalysisResult | null>(null);
   const [progress, setProgress] = useState<AnalysisProgress | null>(null);
   const [threshold, setThreshold] = useState<number>(0);
   const [maxFrames, setMaxFrames] = useState<number | undefined>(undefined);
   const [exportFormat, setExportFormat] = useState<ExportFormat>('png');
+  const [filenameTemplate, setFilenameTemplate] = useState<string>('');
+  const [writeManifest, setWriteManifest] = useState<boolean>(false);
+  const [writeExif, setWriteExif] = useState<boolean>(true);
   const [minFrameDistance, setMinFrameDistance] = useState<number>(1);
+  // Meters flown between selected frames, for videos with GPS telemetry (0 = off)
+  const [minTravelDistance, setMinTravelDistance] = useState<number>(0);
+  // Frames the backend picked for that spacing, null when it does not apply
+  const [travelSelection, setTravelSelection] = useState<number[] | null>(null);
   const [sampleRate, setSampleRate] = useState<number>(1);
   const [useGpu, setUseGpu] = useState<boolean>(true);
+  const [analysisMode, setAnalysisMode] = useState<AnalysisMode>('uniform');
   const [exporting, setExporting] = useState(false);
+  const jobIdRef = useRef<string | null>(null);
   const [showSettings, setShowSettings] = useState(false);
   const [isFirstRun, setIsFirstRun] = useState(false);
 
@@ -86,6 +122,9 @@ function App() {
   const [startTimeInput, setStartTimeInput] = useState<string>('0');
   const [endTimeInput, setEndTimeInput] = useState<string>('0');
 
+  // Stretches to analyze or skip, added from the time range selection
+  const [segments, setSegments] = useState<TimeSegments>({ include: [], exclude: [] });
+
   const videoRef = useRef<HTMLVideoElement>(null);
 
   // Check for first run on mount
@@ -121,25 +160,14 @@ function App() {
           const paths = event.payload.paths;
           console.log('Dropped paths:', paths);
           if (paths && paths.length > 0) {
-            const filePath = paths[0];
-            // Check if it's a video file
-            if (filePath.match(/\.(mp4|mov|avi|mkv|webm)$/i)) {
-              setVideoPath(filePath);
-              setVideoUrl(convertFileSrc(filePath));
-              setAnalysisResult(null);
-              setProgress(null);
-              setManuallySelectedFrames(new Set());
-
-              // Get video info to set initial time range
-              try {
-                const info = await invoke<any>('get_video_metadata', { videoPath: filePath });
-                setVideoDuration(info.duration);
-                setStartTime(0);
-                setEndTime(info.duration);
-                setStartTimeInput('0');
-                setEndTimeInput(info.duration.toString());
-              } catch (error) {
-                console.error('Failed to get video metadata:', error);
+            const droppedPath = paths[0];
+            if (isVideoFile(droppedPath)) {
+              await openSource(droppedPath);
+            } else {
+              // Folders of images open as a sequence; anything else is ignored
+              const info = await invoke<VideoInfo>('get_video_metadata', { videoPath: droppedPath }).catch(() => null);
+              if (info?.container_format === 'image_sequence') {
+                await openSource(droppedPath, info);
               }
             }
           }
@@ -158,51 +186,70 @@ function App() {
     };
   }, []);
 
+  /** Opens a video file or a folder of images; `info` skips fetching the metadata again */
+  const openSource = async (path: string, info?: VideoInfo) => {
+    setVideoPath(path);
+    setVideoUrl(isVideoFile(path) ? convertFileSrc(path) : null);
+    setIsSequence(!isVideoFile(path));
+    setAnalysisResult(null);
+    setProgress(null);
+    setManuallySelectedFrames(new Set());
+
+    // Get video info to set initial time range
+    try {
+      const metadata = info ?? await invoke<VideoInfo>('get_video_metadata', { videoPath: path });
+      setVideoDuration(metadata.duration);
+      setStartTime(0);
+      setEndTime(metadata.duration);
+      setStartTimeInput('0');
+      setEndTimeInput(metadata.duration.toString());
+      setSegments({ include: [], exclude: [] });
+    } catch (error) {
+      console.error('Failed to get video metadata:', error);
+    }
+  };
+
   const handleBrowseFile = async () => {
     const selected = await openPath({
       multiple: false,
       filters: [{
         name: 'Video',
-        extensions: ['mp4', 'mov', 'avi', 'mkv', 'webm']
+        extensions: VIDEO_EXTENSIONS
       }]
     });
 
     if (selected) {
-      const filePath = selected as string;
-      setVideoPath(filePath);
-      setVideoUrl(convertFileSrc(filePath));
-      setAnalysisResult(null);
-      setProgress(null);
-      setManuallySelectedFrames(new Set());
+      await openSource(selected as string);
+    }
+  };
 
-      // Get video info to set initial time range
-      try {
-        const info = await invoke<any>('get_video_metadata', { videoPath: filePath });
-        setVideoDuration(info.duration);
-        setStartTime(0);
-        setEndTime(info.duration);
-        setStartTimeInput('0');
-        setEndTimeInput(info.duration.toString());
-      } catch (error) {
-        console.error('Failed to get video metadata:', error);
-      }
+  const handleBrowseFolder = async () => {
+    const selected = await openPath({ directory: true, multiple: false });
+    if (selected) {
+      await openSource(selected as string);
     }
   };
 
   const analyzeVideo = async () => {
     if (!videoPath) return;
 
+    const jobId = crypto.randomUUID();
+    jobIdRef.current = jobId;
+
     try {
       setAnalyzing(true);
       setProgress(null);
 
-      // Pass time range to backend if set (null if using full video)
+      // Pass time range to backend if set (null if using full video). Include
+      // segments replace the time range rather than being clipped to it.
+      const useTimeRange = segments.include.length === 0 && (startTime > 0 || endTime > 0);
       const result = await invoke<AnalysisResult>('analyze_video', {
         videoPath,
         sampleRate,
         useGpu,
-        startTime: startTime > 0 || endTime > 0 ? startTime : null,
-        endTime: startTime > 0 || endTime > 0 ? endTime : null,
+        startTime: useTimeRange ? startTime : null,
+        endTime: useTimeRange ? endTime : null,
+        options: { job_id: jobId, segments, mode: analysisMode },
       });
 
       setAnalysisResult(result);
@@ -210,16 +257,29 @@ function App() {
       setSelectionSettings(prev => ({ ...prev, mode: 'threshold' }));
       setSelectionMode('threshold');
     } catch (error) {
-      console.error('Analysis failed:', error);
-      alert(`Analysis failed: ${error}`);
+      if (!isCancelled(error)) {
+        console.error('Analysis failed:', error);
+        alert(`Analysis failed: ${errorMessage(error)}`);
+      }
     } finally {
+      jobIdRef.current = null;
       setAnalyzing(false);
     }
   };
 
+  const cancelJob = async () => {
+    if (!jobIdRef.current) return;
+    try {
+      await invoke<boolean>('cancel_job', { jobId: jobIdRef.current });
+    } catch (error) {
+      console.error('Failed to cancel:', error);
+    }
+  };
+
   const handleReset = () => {
     setVideoPath(null);
     setVideoUrl(null);
+    setIsSequence(false);
     setAnalysisResult(null);
     setProgress(null);
     setThreshold(0);
@@ -244,6 +304,30 @@ function App() {
     setEndTime(0);
     setStartTimeInput('0');
     setEndTimeInput('0');
+    setSegments({ include: [], exclude: [] });
+  };
+
+  // Time fields take seconds, or a timecode for videos with embedded timecode
+  const parseTimeInput = async (input: string): Promise<number> => {
+    if (!input.includes(':') || !videoPath) return parseFloat(input);
+    try {
+      return await invoke<number>('time_input_to_seconds', { videoPath, value: input.trim() });
+    } catch (error) {
+      alert(`Invalid time: ${errorMessage(error)}`);
+      return NaN;
+    }
+  };
+
+  const addSegment = (kind: keyof TimeSegments) => {
+    if (endTime <= startTime) return;
+    setSegments(prev => ({
+      ...prev,
+      [kind]: [...prev[kind], { start: startTime, end: endTime }].sort((a, b) => a.start - b.start),
+    }));
+  };
+
+  const removeSegment = (kind: keyof TimeSegments, index: number) => {
+    setSegments(prev => ({ ...prev, [kind]: prev[kind].filter((_, i) => i !== index) }));
   };
 
   const handleFrameClick = async (frameIndex: number) => {
@@ -281,10 +365,35 @@ function App() {
     });
   };
 
+  // Spacing by travelled distance is done by the backend, the same way export does it
+  useEffect(() => {
+    const hasTrack = analysisResult?.frames.some(f => f.telemetry?.distance != null);
+    if (!analysisResult || !hasTrack || minTravelDistance <= 0) {
+      setTravelSelection(null);
+      return;
+    }
+
+    let stale = false;
+    invoke<number[]>('select_frames_by_travel', {
+      sharpnessScores: analysisResult.frames.map(f => (isDecoded(f) ? f.sharpness : null)),
+      distances: analysisResult.frames.map(f => f.telemetry?.distance ?? null),
+      threshold,
+      minDistance: minTravelDistance,
+    })
+      .then(indices => {
+        if (!stale) setTravelSelection(indices);
+      })
+      .catch(error => console.error('Failed to select frames by travelled distance:', error));
+    return () => {
+      stale = true;
+    };
+  }, [analysisResult, threshold, minTravelDistance]);
+
   const getSelectedFrameIndices = (): number[] => {
     if (!analysisResult) return [];
 
-    const sharpnessScores = analysisResult.frames.map(f => f.sharpness);
+    // Frames that failed to decode have no real score and are never selected
+    const sharpnessScores = analysisResult.frames.map(f => (isDecoded(f) ? f.sharpness : -Infinity));
 
     switch (selectionMode) {
       case 'manual':
@@ -300,7 +409,9 @@ function App() {
           const batchEnd = Math.min(i + batchSize, analysisResult.frames.length);
           const batchScores = sharpnessScores.slice(i, batchEnd);
           const maxIdx = batchScores.indexOf(Math.max(...batchScores));
-          selected.push(i + maxIdx);
+          if (batchScores[maxIdx] !== -Infinity) {
+            selected.push(i + maxIdx);
+          }
           i = batchEnd + batchBuffer;
         }
 
@@ -309,15 +420,19 @@ function App() {
 
       case 'bestN': {
         // Select top N frames by sharpness
-        const indexed = sharpnessScores.map((score, idx) => ({ score, idx }));
+        const indexed = sharpnessScores
+          .map((score, idx) => ({ score, idx }))
+          .filter(item => item.score !== -Infinity);
         indexed.sort((a, b) => b.score - a.score);
         return indexed.slice(0, selectionSettings.bestN).map(item => item.idx).sort((a, b) => a - b);
       }
 
       case 'topPercentage': {
         // Select top X% of frames by sharpness
-        const count = Math.ceil(analysisResult.frames.length * selectionSettings.topPercentage / 100);
-        const indexed = sharpnessScores.map((score, idx) => ({ score, idx }));
+        const indexed = sharpnessScores
+          .map((score, idx) => ({ score, idx }))
+          .filter(item => item.score !== -Infinity);
+        const count = Math.ceil(indexed.length * selectionSettings.topPercentage / 100);
         indexed.sort((a, b) => b.score - a.score);
         return indexed.slice(0, count).map(item => item.idx).sort((a, b) => a - b);
       }
@@ -327,10 +442,13 @@ function App() {
         // Threshold-based selection
         let framesAboveThreshold = analysisResult.frames
           .map((f, idx) => ({ ...f, idx }))
-          .filter((f) => f.sharpness >= threshold);
+          .filter((f) => isDecoded(f) && f.sharpness >= threshold);
 
-        // Only apply min distance if it's greater than 1
-        if (minFrameDistance > 1) {
+        if (travelSelection) {
+          // Spaced by meters flown; frames without a GPS fix cannot be placed
+          framesAboveThreshold = travelSelection.map(idx => ({ ...analysisResult.frames[idx], idx }));
+        } else if (minFrameDistance > 1) {
+          // Only apply min distance if it's greater than 1
           const selectedFrames: typeof framesAboveThreshold = [];
           let lastSelected: number | null = null;
 
@@ -395,7 +513,12 @@ function App() {
         threshold: 0, // Not used when we pre-filter frames
         max_frames: undefined, // Already filtered
         min_frame_distance: 1, // Already filtered
+        job_id: crypto.randomUUID(),
+        filename_template: filenameTemplate.trim() || undefined,
+        write_manifest: writeManifest,
+        write_exif: writeExif,
       };
+      jobIdRef.current = options.job_id ?? null;
 
       const exportedPaths = await invoke<string[]>('export_frames', {
         videoPath,
@@ -406,9 +529,12 @@ function App() {
 
       alert(`Successfully exported ${exportedPaths.length} frames to:\n${outputDir}`);
     } catch (error) {
-      console.error('Export failed:', error);
-      alert(`Export failed: ${error}`);
+      if (!isCancelled(error)) {
+        console.error('Export failed:', error);
+        alert(`Export failed: ${errorMessage(error)}`);
+      }
     } finally {
+      jobIdRef.current = null;
       setExporting(false);
     }
   };
@@ -426,6 +552,10 @@ function App() {
       timestamp: frame.timestamp.toFixed(2),
       isSelected: selectedSet.has(idx) || (selectionMode === 'manual' && manuallySelectedFrames.has(idx)),
       isManuallySelected: manuallySelectedFrames.has(idx),
+      failed: !isDecoded(frame),
+      error: frame.error,
+      timecode: frame.timecode,
+      telemetry: frame.telemetry,
     }));
   };
 
@@ -433,6 +563,7 @@ function App() {
     if (!analysisResult) return null;
 
     const info = analysisResult.video_info;
+    const failures = analysisResult.decode_failures;
 
     return (
       <div className="card space-y-2">
@@ -446,6 +577,7 @@ function App() {
           </div>
           <div>
             <span className="font-medium">FPS:</span> {info.fps.toFixed(2)}
+            {info.is_vfr && info.avg_fps ? ` (variable, ${info.avg_fps.toFixed(2)} avg)` : ''}
           </div>
           <div>
             <span className="font-medium">Resolution:</span> {info.width}x{info.height}
@@ -459,7 +591,65 @@ function App() {
           <div>
             <span className="font-medium">Selected:</span> {getSelectedFrameCount()}
           </div>
+          <div>
+            <span className="font-medium">Decoding:</span> {info.hw_decode ?? 'software'}
+          </div>
+          {info.timecode && (
+            <div>
+              <span className="font-medium">Start Timecode:</span> {info.timecode}
+            </div>
+          )}
+          {info.interlaced && (
+            <div>
+              <span className="font-medium">Interlaced:</span> {info.field_order ?? 'yes'}
+            </div>
+          )}
+          {analysisResult.telemetry_source && (
+            <div className="col-span-2">
+              <span className="font-medium">Telemetry:</span> {analysisResult.telemetry_source}
+            </div>
+          )}
+          {info.location && (
+            <div>
+              <span className="font-medium">Location:</span>{' '}
+              {info.location.latitude.toFixed(5)}, {info.location.longitude.toFixed(5)}
+              {info.location.altitude != null && ` (${info.location.altitude.toFixed(0)} m)`}
+            </div>
+          )}
         </div>
+        {analysisResult.segments &&
+          (analysisResult.segments.include.length > 0 || analysisResult.segments.exclude.length > 0) && (
+          <p className="text-xs text-gray-500 dark:text-gray-400">
+            Segments:{' '}
+            {[
+              ...analysisResult.segments.include.map(r => `${r.start.toFixed(1)}s–${r.end.toFixed(1)}s`),
+              ...analysisResult.segments.exclude.map(r => `not ${r.start.toFixed(1)}s–${r.end.toFixed(1)}s`),
+            ].join(', ')}
+          </p>
+        )}
+        {failures && failures.failed_frames > 0 && (
+          <div className="text-sm text-red-600 dark:text-red-400">
+            <p>
+              {failures.failed_frames} frame{failures.failed_frames === 1 ? '' : 's'} could not be decoded
+              and {failures.failed_frames === 1 ? 'is' : 'are'} left out of the selection. The file may be damaged.
+            </p>
+            <ul className="text-xs list-disc list-inside">
+              {failures.errors.map((error) => (
+                <li key={error}>{error}</li>
+              ))}
+            </ul>
+          </div>
+        )}
+        {failures && failures.recovered_frames > 0 && (
+          <p className="text-xs text-gray-500 dark:text-gray-400">
+            {failures.recovered_frames} frame{failures.recovered_frames === 1 ? '' : 's'} decoded on a retry
+          </p>
+        )}
+        {info.hw_decode_fallbacks && info.hw_decode_fallbacks.length > 0 && (
+          <p className="text-xs text-gray-500 dark:text-gray-400">
+            Hardware decode fell back: {info.hw_decode_fallbacks.join('; ')}
+          </p>
+        )}
       </div>
     );
   };
@@ -541,6 +731,25 @@ function App() {
                 </p>
               </div>
             )}
+
+            {showSettings && analysisResult?.frames.some(f => f.telemetry?.distance != null) && (
+              <div>
+                <label className="block text-sm font-medium mb-2">
+                  Min Travel Distance: {minTravelDistance === 0 ? 'off' : `${minTravelDistance} m`}
+                </label>
+                <input
+                  type="range"
+                  min={0}
+                  max={50}
+                  value={minTravelDistance}
+                  onChange={(e) => setMinTravelDistance(parseInt(e.target.value))}
+                  className="w-full"
+                />
+                <p className="text-xs text-gray-500 mt-1">
+                  Meters flown between selected frames (from the drone's GPS telemetry); replaces the frame distance
+                </p>
+              </div>
+            )}
           </div>
         )}
 
@@ -717,6 +926,11 @@ function App() {
               <div>
                 <span className="font-medium">Sharpness:</span> {frame.sharpness.toFixed(2)}
               </div>
+              {frame.timecode && (
+                <div>
+                  <span className="font-medium">Timecode:</span> {frame.timecode}
+                </div>
+              )}
               <div>
                 <span className="font-medium">Frame Name:</span> frame_{String(frame.frame_number).padStart(6, '0')}
               </div>
@@ -794,41 +1008,57 @@ function App() {
               <Upload size={48} className="mx-auto text-gray-400" />
               <div>
                 <p className="text-lg font-medium">
-                  Drag & drop a video file
+                  Drag & drop a video file or a folder of images
+                </p>
+                <p className="text-sm text-gray-500">
+                  or click to browse, or{' '}
+                  <button
+                    onClick={(e) => {
+                      e.stopPropagation();
+                      handleBrowseFolder();
+                    }}
+                    className="text-blue-500 hover:underline"
+                  >
+                    open an image folder
+                  </button>
                 </p>
-                <p className="text-sm text-gray-500">or click to browse</p>
               </div>
               <p className="text-xs text-gray-400">
-                Supported formats: MP4, MOV, AVI, MKV, WebM
+                Supported formats: MP4, MOV, AVI, MKV, WebM; JPEG, PNG and TIFF sequences
               </p>
             </div>
           </div>
         )}
 
         {/* Video Player */}
-        {videoUrl && (
+        {videoPath && (
           <div className="card space-y-4">
             <div className="flex items-center justify-between">
-              <h3 className="text-lg font-semibold">Video Preview</h3>
+              <h3 className="text-lg font-semibold">{isSequence ? 'Image Sequence' : 'Video Preview'}</h3>
               <button
                 onClick={() => {
                   setVideoPath(null);
                   setVideoUrl(null);
+                  setIsSequence(false);
                   setAnalysisResult(null);
                   setManuallySelectedFrames(new Set());
                 }}
                 className="btn-secondary text-sm"
               >
-                Remove Video
+                {isSequence ? 'Remove Folder' : 'Remove Video'}
               </button>
             </div>
-            <video
-              ref={videoRef}
-              src={videoUrl}
-              controls
-              className="w-full rounded-lg bg-black"
-              style={{ maxHeight: '400px' }}
-            />
+            {videoUrl ? (
+              <video
+                ref={videoRef}
+                src={videoUrl}
+                controls
+                className="w-full rounded-lg bg-black"
+                style={{ maxHeight: '400px' }}
+              />
+            ) : (
+              <p className="text-sm text-gray-600 dark:text-gray-400 break-all">{videoPath}</p>
+            )}
             {!analyzing && !analysisResult && (
               <>
                 <div className="bg-blue-50 dark:bg-blue-900/20 p-4 rounded-lg space-y-3">
@@ -919,7 +1149,7 @@ function App() {
                         <div className="grid grid-cols-2 gap-3">
                           <div>
                             <label className="block text-xs font-medium mb-1 text-gray-600 dark:text-gray-400">
-                              Start Time (seconds)
+                              Start Time (seconds or timecode)
                             </label>
                             <input
                               type="text"
@@ -928,14 +1158,14 @@ function App() {
                               onChange={(e) => {
                                 const input = e.target.value;
                                 setStartTimeInput(input);
-                                const val = parseFloat(input);
+                                const val = input.includes(':') ? NaN : parseFloat(input);
                                 if (!isNaN(val) && val >= 0 && val < endTime) {
                                   setStartTime(val);
                                 }
                               }}
-                              onBlur={(e) => {
+                              onBlur={async (e) => {
                                 // Ensure valid value on blur
-                                const val = parseFloat(e.target.value);
+                                const val = await parseTimeInput(e.target.value);
                                 if (isNaN(val) || val < 0) {
                                   setStartTime(0);
                                   setStartTimeInput('0');
@@ -961,7 +1191,7 @@ function App() {
                           </div>
                           <div>
                             <label className="block text-xs font-medium mb-1 text-gray-600 dark:text-gray-400">
-                              End Time (seconds)
+                              End Time (seconds or timecode)
                             </label>
                             <input
                               type="text"
@@ -970,14 +1200,14 @@ function App() {
                               onChange={(e) => {
                                 const input = e.target.value;
                                 setEndTimeInput(input);
-                                const val = parseFloat(input);
+                                const val = input.includes(':') ? NaN : parseFloat(input);
                                 if (!isNaN(val) && val > startTime && val <= videoDuration) {
                                   setEndTime(val);
                                 }
                               }}
-                              onBlur={(e) => {
+                              onBlur={async (e) => {
                                 // Ensure valid value on blur
-                                const val = parseFloat(e.target.value);
+                                const val = await parseTimeInput(e.target.value);
                                 if (isNaN(val) || val > videoDuration) {
                                   setEndTime(videoDuration);
                                   setEndTimeInput(videoDuration.toString());
@@ -1003,8 +1233,48 @@ function App() {
                         </div>
 
                         <p className="text-xs text-gray-600 dark:text-gray-400">
-                          Analyze frames from {startTime.toFixed(2)}s to {endTime.toFixed(2)}s
+                          {segments.include.length > 0
+                            ? 'Analyze frames in the include segments below'
+                            : `Analyze frames from ${startTime.toFixed(2)}s to ${endTime.toFixed(2)}s`}
                         </p>
+
+                        {/* Include/exclude segments taken from the selection above */}
+                        <div className="flex gap-2">
+                          <button
+                            onClick={() => addSegment('include')}
+                            className="px-2 py-1 text-xs rounded bg-blue-100 dark:bg-blue-900/40 hover:bg-blue-200 dark:hover:bg-blue-900/60"
+                          >
+                            Add as include segment
+                          </button>
+                          <button
+                            onClick={() => addSegment('exclude')}
+                            className="px-2 py-1 text-xs rounded bg-red-100 dark:bg-red-900/40 hover:bg-red-200 dark:hover:bg-red-900/60"
+                          >
+                            Add as exclude segment
+                          </button>
+                        </div>
+                        {(['include', 'exclude'] as const).map((kind) =>
+                          segments[kind].map((range, index) => (
+                            <div
+                              key={`${kind}-${index}`}
+                              className="flex items-center justify-between text-xs text-gray-700 dark:text-gray-300"
+                            >
+                              <span>
+                                <span className={kind === 'include' ? 'text-blue-600' : 'text-red-600'}>
+                                  {kind === 'include' ? 'Include' : 'Exclude'}
+                                </span>{' '}
+                                {range.start.toFixed(2)}s to {range.end.toFixed(2)}s
+                              </span>
+                              <button
+                                onClick={() => removeSegment(kind, index)}
+                                className="text-gray-500 hover:text-red-600"
+                                title="Remove segment"
+                              >
+                                <X size={14} />
+                              </button>
+                            </div>
+                          ))
+                        )}
                       </div>
                     ) : (
                       <p className="text-xs text-gray-500 dark:text-gray-400 italic">
@@ -1035,6 +1305,33 @@ function App() {
                       {sampleRate === 1 ? ' (all frames will be analyzed)' : ` (faster analysis, may miss some sharp frames)`}
                     </p>
                   </div>
+
+                  <div>
+                    <label htmlFor="analysisMode" className="block text-sm font-medium mb-2">
+                      Sampling Mode
+                    </label>
+                    <select
+                      id="analysisMode"
+                      value={analysisMode}
+                      onChange={(e) => setAnalysisMode(e.target.value as AnalysisMode)}
+                      className="input-field w-full"
+                    >
+                      <option value="uniform">Uniform: every N frames</option>
+                      <option value="uniform_time">Uniform in time: for variable frame rate recordings</option>
+                      <option value="adaptive">Adaptive: coarse pass, then refine around sharp peaks</option>
+                      <option value="keyframes">Keyframes: score only keyframes (fastest)</option>
+                    </select>
+                    {analysisMode === 'adaptive' && (
+                      <p className="text-xs text-gray-600 dark:text-gray-400 mt-2">
+                        Samples every {sampleRate} frames first, then looks closer where a sharper frame is likely
+                      </p>
+                    )}
+                    {analysisMode === 'keyframes' && (
+                      <p className="text-xs text-gray-600 dark:text-gray-400 mt-2">
+                        Decodes only the keyframes; the sample rate is ignored
+                      </p>
+                    )}
+                  </div>
                   <div className="flex items-center gap-3">
                     <input
                       type="checkbox"
@@ -1068,6 +1365,10 @@ function App() {
               <h3 className="text-lg font-semibold">
                 {progress ? 'Analyzing Sharpness...' : 'Extracting Frames...'}
               </h3>
+              <button onClick={cancelJob} className="btn-secondary ml-auto flex items-center gap-2">
+                <X size={16} />
+                Cancel
+              </button>
             </div>
             {progress ? (
               <div className="space-y-3">
@@ -1139,9 +1440,31 @@ function App() {
                               <p className="text-sm">
                                 <strong>Time:</strong> {data.timestamp}s
                               </p>
-                              <p className="text-sm">
-                                <strong>Sharpness:</strong> {data.sharpness}
-                              </p>
+                              {data.timecode && (
+                                <p className="text-sm">
+                                  <strong>Timecode:</strong> {data.timecode}
+                                </p>
+                              )}
+                              {data.telemetry?.latitude != null && data.telemetry?.longitude != null && (
+                                <p className="text-sm">
+                                  <strong>GPS:</strong> {data.telemetry.latitude.toFixed(6)}, {data.telemetry.longitude.toFixed(6)}
+                                  {data.telemetry.relative_altitude != null && ` · ${data.telemetry.relative_altitude.toFixed(1)} m`}
+                                </p>
+                              )}
+                              {data.telemetry?.gimbal_pitch != null && (
+                                <p className="text-sm">
+                                  <strong>Gimbal:</strong> pitch {data.telemetry.gimbal_pitch.toFixed(1)}°, yaw {data.telemetry.gimbal_yaw?.toFixed(1) ?? '–'}°
+                                </p>
+                              )}
+                              {data.failed ? (
+                                <p className="text-sm text-red-600">
+                                  <strong>Decode failed:</strong> {data.error}
+                                </p>
+                              ) : (
+                                <p className="text-sm">
+                                  <strong>Sharpness:</strong> {data.sharpness}
+                                </p>
+                              )}
                               {data.isSelected && (
                                 <p className="text-sm text-amber-600 font-medium">
                                   ✓ Selected for export
@@ -1176,7 +1499,7 @@ function App() {
                       {getChartData().map((entry, index) => (
                         <Cell
                           key={`cell-${index}`}
-                          fill={entry.isSelected ? '#f59e0b' : '#3b82f6'}
+                          fill={entry.failed ? '#ef4444' : entry.isSelected ? '#f59e0b' : '#3b82f6'}
                           style={{ cursor: 'pointer' }}
                         />
                       ))}
@@ -1205,6 +1528,38 @@ function App() {
                 </select>
               </div>
 
+              <div>
+                <label className="block text-sm font-medium mb-2">File Names</label>
+                <input
+                  type="text"
+                  value={filenameTemplate}
+                  onChange={(e) => setFilenameTemplate(e.target.value)}
+                  placeholder={analysisResult.video_info.timecode ? '{video}_{timecode}' : 'frame_{frame}'}
+                  className="input-field w-full"
+                />
+                <p className="text-xs text-gray-600 dark:text-gray-400 mt-1">
+                  Placeholders: {'{name} {video} {frame} {time} {timecode} {sharpness}'}. Leave empty for the default names.
+                </p>
+              </div>
+
+              <label className="flex items-center gap-2 text-sm">
+                <input
+                  type="checkbox"
+                  checked={writeManifest}
+                  onChange={(e) => setWriteManifest(e.target.checked)}
+                />
+                Write manifest.csv (file, frame, timestamp, timecode, sharpness)
+              </label>
+
+              <label className="flex items-center gap-2 text-sm">
+                <input
+                  type="checkbox"
+                  checked={writeExif}
+                  onChange={(e) => setWriteExif(e.target.checked)}
+                />
+                Write capture time and GPS location to JPEG EXIF
+              </label>
+
               <button
                 onClick={exportFrames}
                 disabled={exporting || getSelectedFrameCount() === 0}
@@ -1213,6 +1568,12 @@ function App() {
                 <Download size={20} className="inline mr-2" />
                 {exporting ? 'Exporting...' : `Export ${getSelectedFrameCount()} Selected Frames`}
               </button>
+              {exporting && (
+                <button onClick={cancelJob} className="btn-secondary w-full">
+                  <X size={20} className="inline mr-2" />
+                  Cancel Export
+                </button>
+              )}
             </div>
           </>
         )}
@@ -2,11 +2,13 @@ import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { open as openPath } from '@tauri-apps/plugin-dialog';
 import { X, Check, AlertCircle, FileSearch, Info } from 'lucide-react';
+import type { HwAccelProbe, ToolchainReport } from './types';
 
 interface AppSettings {
   ffmpeg_path: string | null;
   ffprobe_path: string | null;
   first_run: boolean;
+  analysis_cache_limit_mb: number;
 }
 
 interface SettingsDialogProps {
@@ -23,11 +25,15 @@ export function SettingsDialog({ isOpen, onClose, isFirstRun = false }: Settings
   const [detecting, setDetecting] = useState(false);
   const [instructions, setInstructions] = useState<string[]>([]);
   const [showInstructions, setShowInstructions] = useState(isFirstRun);
+  const [hwAccel, setHwAccel] = useState<HwAccelProbe | null>(null);
+  const [toolchain, setToolchain] = useState<ToolchainReport | null>(null);
+  const [checking, setChecking] = useState(false);
 
   useEffect(() => {
     if (isOpen) {
       loadSettings();
       loadInstructions();
+      loadHwAccel();
     }
   }, [isOpen]);
 
@@ -55,6 +61,29 @@ export function SettingsDialog({ isOpen, onClose, isFirstRun = false }: Settings
     }
   };
 
+  const loadHwAccel = async () => {
+    try {
+      setHwAccel(await invoke<HwAccelProbe>('get_hw_accel_status'));
+    } catch (error) {
+      console.error('Failed to probe hardware decoding:', error);
+    }
+  };
+
+  const handleCheckToolchain = async () => {
+    setChecking(true);
+    try {
+      const report = await invoke<ToolchainReport>('validate_ffmpeg_toolchain', {
+        ffmpegPath: ffmpegPath || null,
+        ffprobePath: ffprobePath || null,
+      });
+      setToolchain(report);
+    } catch (error) {
+      console.error('Toolchain check failed:', error);
+    } finally {
+      setChecking(false);
+    }
+  };
+
   const loadInstructions = async () => {
     try {
       const inst = await invoke<string[]>('get_ffmpeg_install_instructions');
@@ -124,7 +153,10 @@ export function SettingsDialog({ isOpen, onClose, isFirstRun = false }: Settings
 
   const handleSave = async () => {
     try {
+      // Keep settings this dialog doesn't edit
+      const current = await invoke<AppSettings>('get_settings');
       const newSettings: AppSettings = {
+        ...current,
         ffmpeg_path: ffmpegPath || null,
         ffprobe_path: ffprobePath || null,
         first_run: false,
@@ -195,8 +227,38 @@ export function SettingsDialog({ isOpen, onClose, isFirstRun = false }: Settings
               <Info size={18} />
               <span>Installation Instructions</span>
             </button>
+            <button
+              onClick={handleCheckToolchain}
+              disabled={checking}
+              className="btn-secondary flex items-center space-x-2"
+            >
+              <Check size={18} />
+              <span>{checking ? 'Checking...' : 'Check Toolchain'}</span>
+            </button>
           </div>
 
+          {/* Toolchain report */}
+          {toolchain && (
+            <div className="bg-gray-50 dark:bg-gray-900 rounded-lg p-4 space-y-2 text-sm">
+              <p className="font-semibold">
+                {toolchain.ok ? 'FFmpeg toolchain looks good' : 'FFmpeg toolchain has problems'}
+              </p>
+              <p>
+                FFmpeg {toolchain.ffmpeg.version ?? 'unknown'}, FFprobe {toolchain.ffprobe.version ?? 'unknown'}
+              </p>
+              {toolchain.problems.map((problem) => (
+                <p key={problem} className="text-red-600 dark:text-red-400">
+                  {problem}
+                </p>
+              ))}
+              {toolchain.warnings.map((warning) => (
+                <p key={warning} className="text-yellow-700 dark:text-yellow-400">
+                  {warning}
+                </p>
+              ))}
+            </div>
+          )}
+
           {/* Installation instructions */}
           {showInstructions && (
             <div className="bg-gray-50 dark:bg-gray-900 rounded-lg p-4 space-y-2">
@@ -278,6 +340,23 @@ export function SettingsDialog({ isOpen, onClose, isFirstRun = false }: Settings
               </p>
             )}
           </div>
+
+          {/* Hardware decoding */}
+          {hwAccel && (
+            <div className="space-y-1 text-sm">
+              <h3 className="font-medium">Hardware Decoding</h3>
+              <p>
+                {hwAccel.working.length > 0
+                  ? `Using ${hwAccel.working[0]} (working: ${hwAccel.working.join(', ')})`
+                  : 'Not available, decoding in software'}
+              </p>
+              {hwAccel.failures.map((failure) => (
+                <p key={failure} className="text-xs text-gray-500 dark:text-gray-400">
+                  {failure}
+                </p>
+              ))}
+            </div>
+          )}
         </div>
 
         {/* Footer */}
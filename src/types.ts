@@ -1,9 +1,100 @@
+export interface GeoLocation {
+  latitude: number;
+  longitude: number;
+  /** Meters above sea level */
+  altitude?: number | null;
+}
+
 export interface VideoInfo {
   duration: number;
+  /** Nominal frame rate */
   fps: number;
+  /** Frames per second from the frame timestamps (analysis results) or the container */
+  avg_fps?: number;
+  /** Variable frame rate: use mode 'uniform_time' for evenly spaced samples */
+  is_vfr?: boolean;
   width: number;
   height: number;
   total_frames: number;
+  stream_index: number;
+  rotation: number;
+  sample_aspect_ratio: number;
+  display_aspect_ratio: number;
+  display_width: number;
+  display_height: number;
+  codec_name?: string;
+  profile?: string;
+  pix_fmt?: string;
+  bit_depth?: number;
+  chroma_subsampling?: string;
+  color_primaries?: string;
+  color_transfer?: string;
+  color_range?: string;
+  color_space?: string;
+  is_hdr: boolean;
+  hdr_format?: string;
+  bit_rate?: number;
+  container_format?: string;
+  creation_time?: string;
+  /** Recording location from the container's ISO 6709 tags */
+  location?: GeoLocation | null;
+  /** SMPTE timecode of the first frame, e.g. "01:00:00:00" (";" before the frames for drop-frame) */
+  timecode?: string | null;
+  /** Detected by FFmpeg's idet filter for streams up to 1080 lines, otherwise from the container flags */
+  interlaced?: boolean;
+  field_order?: 'tff' | 'bff' | 'progressive' | null;
+  make?: string;
+  model?: string;
+  has_audio: boolean;
+  video_streams: VideoStreamInfo[];
+  warnings: string[];
+  hw_decode?: HwAccel | null;
+  hw_decode_fallbacks?: string[];
+}
+
+export interface BinaryReport {
+  path: string | null;
+  runs: boolean;
+  version: string | null;
+  major_version: number | null;
+  configuration: string[];
+}
+
+export interface Capability {
+  kind: 'filter' | 'encoder' | 'pixel_format';
+  name: string;
+  purpose: string;
+  required: boolean;
+  available: boolean;
+}
+
+export interface ToolchainReport {
+  ffmpeg: BinaryReport;
+  ffprobe: BinaryReport;
+  capabilities: Capability[];
+  problems: string[];
+  warnings: string[];
+  ok: boolean;
+}
+
+export type HwAccel = 'videotoolbox' | 'vaapi' | 'qsv' | 'vulkan' | 'cuda';
+
+export interface HwAccelProbe {
+  compiled: string[];
+  working: HwAccel[];
+  failures: string[];
+}
+
+export interface VideoStreamInfo {
+  index: number;
+  codec_name?: string;
+  width: number;
+  height: number;
+  fps: number;
+  bit_rate?: number;
+  pix_fmt?: string;
+  title?: string;
+  attached_pic: boolean;
 }
 
 export interface FrameData {
@@ -11,6 +102,37 @@ export interface FrameData {
   timestamp: number;
   sharpness: number;
   path?: string;
+  refined?: boolean;
+  status?: FrameStatus;
+  error?: string | null;
+  timecode?: string | null;
+  /** DJI flight telemetry interpolated onto the frame */
+  telemetry?: Telemetry | null;
+}
+
+export interface Telemetry {
+  latitude?: number | null;
+  longitude?: number | null;
+  /** Meters above the takeoff point */
+  relative_altitude?: number | null;
+  /** Meters above sea level */
+  absolute_altitude?: number | null;
+  gimbal_yaw?: number | null;
+  gimbal_pitch?: number | null;
+  gimbal_roll?: number | null;
+  iso?: number | null;
+  shutter?: string | null;
+  f_number?: number | null;
+  /** Meters travelled along the GPS track since the first fix */
+  distance?: number | null;
+}
+
+export type FrameStatus = 'decoded' | 'recovered' | 'failed';
+
+export interface DecodeFailureSummary {
+  failed_frames: number;
+  recovered_frames: number;
+  errors: string[];
 }
 
 export interface AnalysisResult {
@@ -18,12 +140,72 @@ export interface AnalysisResult {
   frames: FrameData[];
   suggested_threshold: number;
   suggested_frame_count: number;
+  mode?: AnalysisMode;
+  refined_frame_count?: number;
+  analysis_scale?: number;
+  analysis_width?: number;
+  analysis_height?: number;
+  resumed_frame_count?: number;
+  cached?: boolean;
+  decode_failures?: DecodeFailureSummary;
+  segments?: TimeSegments;
+  /** SRT file or subtitle stream the telemetry came from */
+  telemetry_source?: string | null;
+  /** Order the images of a sequence were numbered in */
+  sequence_order?: SequenceOrder;
+}
+
+/** Seconds from the start of the video; `start` and `end` are both included */
+export interface TimeRange {
+  start: number;
+  end: number;
+}
+
+/** Like TimeRange, but either end may also be a timecode such as `01:00:10:12` */
+export interface TimeRangeInput {
+  start: number | string;
+  end: number | string;
+}
+
+export interface TimeSegmentsInput {
+  include: TimeRangeInput[];
+  exclude: TimeRangeInput[];
+}
+
+export interface TimeSegments {
+  include: TimeRange[];
+  exclude: TimeRange[];
 }
 
 export interface AnalysisProgress {
   current_frame: number;
   total_frames: number;
   percentage: number;
+  stage: AnalysisStage;
+  added_frames: number;
+  resumed_frames: number;
+}
+
+export type AnalysisStage = 'sampling' | 'refining';
+
+export type AnalysisMode = 'uniform' | 'uniform_time' | 'adaptive' | 'keyframes';
+
+export type ToneMapping = 'auto' | 'off' | 'hable' | 'reinhard' | 'mobius' | 'clip';
+
+export type Deinterlace = 'auto' | 'off' | 'bwdif' | 'yadif';
+
+export type SequenceOrder = 'filename' | 'exif_time';
+
+export interface AnalysisOptions {
+  stream_index?: number;
+  tone_mapping?: ToneMapping;
+  deinterlace?: Deinterlace;
+  sequence_order?: SequenceOrder;
+  mode?: AnalysisMode;
+  analysis_resolution?: number;
+  job_id?: string;
+  refresh?: boolean;
+  segments?: TimeSegmentsInput;
 }
 
 export interface ExportOptions {
@@ -31,9 +213,36 @@ export interface ExportOptions {
   threshold?: number;
   max_frames?: number;
   min_frame_distance: number;
+  tone_mapping?: ToneMapping;
+  deinterlace?: Deinterlace;
+  bit_depth?: 8 | 16;
+  job_id?: string;
+  segments?: TimeSegmentsInput;
+  /** Placeholders: {name} {video} {frame} {time} {timecode} {sharpness} */
+  filename_template?: string;
+  write_manifest?: boolean;
+  /** Write location and capture time into JPEG EXIF (default true) */
+  write_exif?: boolean;
+  /** Meters travelled between selected frames, in place of min_frame_distance */
+  min_travel_distance?: number;
+}
+
+/** Stored analysis listed by list_cached_analyses */
+export interface CacheEntry {
+  key: string;
+  video_path: string;
+  fingerprint: string;
+  params: string;
+  frame_count: number;
+  size_bytes: number;
+  created_at: number;
+  last_used: number;
 }
 
-export type ExportFormat = 'jpg' | 'png';
+/** Error returned by cancellable commands (analyze_video, export_frames) */
+export type JobError = { kind: 'cancelled' } | { kind: 'failed'; message: string };
+
+export type ExportFormat = 'jpg' | 'png' | 'tiff';
 
 export type SelectionMode = 'threshold' | 'batch' | 'bestN' | 'topPercentage' | 'manual';
 